@@ -1,13 +1,20 @@
-use ssa::{models::events::{EventLine, EventLineParser}, LineItem, LineStreamParser};
+use ssa::{diagnostics::Diagnostics, models::events::EventLineParser, LineStreamParser, Span};
 
 fn main() {
+    let mut diagnostics = Diagnostics::default();
     let parser: LineStreamParser<9, EventLineParser> = LineStreamParser::new(
         "ReadOrder, Layer, Style, Name, MarginL, MarginR, MarginV, Effect, Text",
+        Span::default(),
+        &mut diagnostics,
     )
     .unwrap();
 
-    dbg!(parser.parse_line(
-        "",
-        "1,,Wolf main,Cher,0000,0000,0000,,Et les enregistrements de ses, ondes delta ?"
-    ));
+    dbg!(parser
+        .parse_line(
+            "",
+            "1,,Wolf main,Cher,0000,0000,0000,,Et les enregistrements de ses, ondes delta ?",
+            Span::default(),
+            &mut diagnostics,
+        )
+        .unwrap());
 }
@@ -0,0 +1,226 @@
+use std::fmt;
+
+use crate::{
+    models::{script_info::WrapStyle, style::Style, Color},
+    tags::{KaraokeKind, OverrideTag, Token},
+    writer::WriteAss,
+    Script,
+};
+
+/// Numpad alignment for an SSA `\a` alignment: 1-3 bottom, 5-7 top and 9-11 middle.
+pub fn legacy_to_numpad(alignment: u8) -> u8 {
+    match alignment {
+        5..=7 => alignment + 2,
+        9..=11 => alignment - 5,
+        _ => alignment,
+    }
+}
+
+/// SSA `\a` alignment for a numpad alignment.
+pub fn numpad_to_legacy(alignment: u8) -> u8 {
+    match alignment {
+        4..=6 => alignment + 5,
+        7..=9 => alignment - 2,
+        _ => alignment,
+    }
+}
+
+/// Something SSA v4 has no way to express, found while downgrading.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Loss {
+    ScriptInfo {
+        key: &'static str,
+    },
+    StyleField {
+        style: String,
+        field: &'static str,
+    },
+    /// `line` is an index into the script's events.
+    Layer {
+        line: usize,
+    },
+    /// An override tag SSA v4 renderers do not know, as written in the line.
+    OverrideTag {
+        line: usize,
+        tag: String,
+    },
+}
+
+impl fmt::Display for Loss {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Loss::ScriptInfo { key } => write!(f, "{key} has no SSA v4 equivalent"),
+            Loss::StyleField { style, field } => {
+                write!(f, "style `{style}`: {field} has no SSA v4 equivalent")
+            }
+            Loss::Layer { line } => write!(f, "line {line}: SSA v4 has no layers"),
+            Loss::OverrideTag { line, tag } => {
+                write!(f, "line {line}: `{tag}` is not an SSA v4 tag")
+            }
+        }
+    }
+}
+
+/// Converts an SSA v4 script to ASS: alignments become numpad alignments, `AlphaLevel` moves
+/// into the style colours and `Marked` gives way to layers. Scripts whose `ScriptType` is not
+/// `v4.00` are already ASS and left unchanged.
+pub fn upgrade(script: &mut Script<'_>) {
+    if !script.info.is_ssa() {
+        return;
+    }
+    script.info.script_type = Some("v4.00+".into());
+
+    for style in &mut script.styles {
+        style.alignment = legacy_to_numpad(alignment_byte(style.alignment)).into();
+        if let Some(alpha) = style.alpha_level.take().and_then(|a| u8::try_from(a).ok()) {
+            for color in [
+                &mut style.primary_color,
+                &mut style.secondary_color,
+                style.outline_color.get_or_insert_with(Color::default),
+            ] {
+                if color.alpha.unwrap_or(0) == 0 && alpha != 0 {
+                    color.alpha = Some(alpha);
+                }
+            }
+        }
+    }
+
+    for event in &mut script.events {
+        event.marked = None;
+        event.layer.get_or_insert(0);
+        event.map_tags(|tag| match *tag {
+            OverrideTag::LegacyAlignment(a) => {
+                *tag = OverrideTag::Alignment(a.map(legacy_to_numpad));
+                true
+            }
+            _ => false,
+        });
+    }
+}
+
+/// Converts an ASS script to SSA v4 for players that only read the older dialect. Everything
+/// that cannot be carried over is dropped from the script and reported; override tags SSA v4
+/// does not know are left in place, since renderers skip them. A script that is already
+/// SSA v4 is left unchanged.
+pub fn downgrade(script: &mut Script<'_>) -> Vec<Loss> {
+    let mut losses = Vec::new();
+    if script.info.is_ssa() {
+        return losses;
+    }
+    let info = &mut script.info;
+    info.script_type = Some("v4.00".into());
+    if info
+        .wrap_style
+        .take()
+        .is_some_and(|w| !matches!(w, WrapStyle::Smart))
+    {
+        losses.push(Loss::ScriptInfo { key: "WrapStyle" });
+    }
+    if info.scaled_border_and_shadow.take() == Some(true) {
+        losses.push(Loss::ScriptInfo {
+            key: "ScaledBorderAndShadow",
+        });
+    }
+
+    for style in &mut script.styles {
+        downgrade_style(style, &mut losses);
+    }
+
+    for (line, event) in script.events.iter_mut().enumerate() {
+        if event.layer.take().unwrap_or(0) != 0 {
+            losses.push(Loss::Layer { line });
+        }
+        event.marked.get_or_insert("Marked=0".into());
+
+        for token in event.tokens() {
+            let Token::Override(tags) = token else {
+                continue;
+            };
+            for tag in tags.iter().filter(|tag| !ssa_supports(tag)) {
+                losses.push(Loss::OverrideTag {
+                    line,
+                    tag: tag.to_ass_string(),
+                });
+            }
+        }
+        event.map_tags(|tag| match *tag {
+            OverrideTag::Alignment(an) => {
+                *tag = OverrideTag::LegacyAlignment(an.map(numpad_to_legacy));
+                true
+            }
+            _ => false,
+        });
+    }
+
+    losses
+}
+
+fn downgrade_style(style: &mut Style<'_>, losses: &mut Vec<Loss>) {
+    let mut lose = |field: &'static str, lost: bool| {
+        if lost {
+            losses.push(Loss::StyleField {
+                style: style.name.to_string(),
+                field,
+            });
+        }
+    };
+
+    lose("Underline", style.underline.take().unwrap_or(false));
+    lose("StrikeOut", style.strikeout.take().unwrap_or(false));
+    lose("ScaleX", style.scale_x.take().is_some_and(|v| v != 100.0));
+    lose("ScaleY", style.scale_y.take().is_some_and(|v| v != 100.0));
+    lose("Spacing", style.spacing.take().is_some_and(|v| v != 0.0));
+    lose("Angle", style.angle.take().is_some_and(|v| v != 0.0));
+
+    // one AlphaLevel stands in for the alpha of every colour
+    let alpha = |color: Option<Color>| color.and_then(|c| c.alpha).unwrap_or(0);
+    let text_alphas = [
+        alpha(Some(style.primary_color)),
+        alpha(Some(style.secondary_color)),
+        alpha(style.outline_color),
+    ];
+    lose(
+        "colour alpha",
+        text_alphas.iter().any(|a| *a != text_alphas[0]),
+    );
+    lose("BackColour alpha", alpha(Some(style.back_color)) != 0);
+    style.alpha_level = (text_alphas[0] != 0).then_some(text_alphas[0].into());
+    for color in [
+        Some(&mut style.primary_color),
+        Some(&mut style.secondary_color),
+        style.outline_color.as_mut(),
+        Some(&mut style.back_color),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        color.alpha = None;
+    }
+
+    style.alignment = numpad_to_legacy(alignment_byte(style.alignment)).into();
+}
+
+fn alignment_byte(alignment: i64) -> u8 {
+    u8::try_from(alignment).unwrap_or(2)
+}
+
+/// Tags SSA v4 renderers understand. `\an` is included since downgrading turns it into `\a`.
+fn ssa_supports(tag: &OverrideTag<'_>) -> bool {
+    use OverrideTag::*;
+    matches!(
+        tag,
+        Bold(_)
+            | Italic(_)
+            | FontName(_)
+            | FontSize(_)
+            | Encoding(_)
+            | Color(..)
+            | Alpha(..)
+            | Alignment(_)
+            | LegacyAlignment(_)
+            | Karaoke(KaraokeKind::Plain | KaraokeKind::Fill, _)
+            | Reset(_)
+            | Comment(_)
+            | Unknown(_)
+    )
+}
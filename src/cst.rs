@@ -0,0 +1,766 @@
+use std::{
+    borrow::Cow,
+    fmt::{self, Write},
+    str::FromStr,
+};
+
+use crate::{
+    diagnostics::{Diagnostics, ParseMode},
+    is_section_header,
+    models::{
+        aegisub::{Extradata, ProjectGarbage, EXTRADATA_TITLE, PROJECT_GARBAGE_TITLE},
+        attachment::{continues_entry, Attachment, AttachmentKind, AttachmentReader},
+        events::{self, EventLineParser},
+        script::{parse_extradata, Script, SectionKind},
+        script_info::ScriptInfo,
+        style::{self, StyleParser},
+    },
+    section_title,
+    writer::{FormattedLine, WriteAss, PROJECT_GARBAGE_FIELDS, SCRIPT_INFO_FIELDS},
+    LineItemParser, LineStreamParser, ParseError, SSAParser, Span,
+};
+
+/// A single source line and the line break that ended it (empty for a final unterminated line).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line<'a> {
+    pub text: Cow<'a, str>,
+    pub ending: &'a str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind<'l> {
+    Blank,
+    Comment,
+    Header(&'l str),
+    Entry { key: &'l str, value: &'l str },
+    Other,
+}
+
+impl<'a> Line<'a> {
+    pub fn kind(&self) -> LineKind<'_> {
+        let text = &*self.text;
+        if text.trim().is_empty() {
+            LineKind::Blank
+        } else if text.starts_with(';') {
+            LineKind::Comment
+        } else if is_section_header(text) {
+            LineKind::Header(section_title(text).unwrap_or(""))
+        } else if let Some((key, value)) = text.split_once(':') {
+            LineKind::Entry {
+                key: key.trim(),
+                value: value.trim(),
+            }
+        } else {
+            LineKind::Other
+        }
+    }
+
+    fn is_entry(&self) -> bool {
+        matches!(self.kind(), LineKind::Entry { .. })
+    }
+
+    fn is_format(&self) -> bool {
+        matches!(self.kind(), LineKind::Entry { key, .. } if key.eq_ignore_ascii_case("format"))
+    }
+
+    /// Byte index where the value of an entry starts, past the `:` and any padding after it.
+    fn value_start(&self) -> Option<usize> {
+        let colon = self.text.find(':')?;
+        let after = &self.text[colon + 1..];
+        Some(self.text.len() - after.trim_start().len())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section<'a> {
+    pub header: Line<'a>,
+    pub lines: Vec<Line<'a>>,
+}
+
+impl<'a> Section<'a> {
+    pub fn title(&self) -> &str {
+        match self.header.kind() {
+            LineKind::Header(title) => title,
+            _ => "",
+        }
+    }
+
+    fn format(&self) -> Option<&str> {
+        self.lines.iter().find_map(|line| match line.kind() {
+            LineKind::Entry { key, value } if key.eq_ignore_ascii_case("format") => Some(value),
+            _ => None,
+        })
+    }
+
+    /// Index just past the last non-blank line, where new entries are appended.
+    fn insertion_point(&self) -> usize {
+        self.lines
+            .iter()
+            .rposition(|line| line.kind() != LineKind::Blank)
+            .map_or(0, |idx| idx + 1)
+    }
+}
+
+/// A lossless view of a script: every line, comment, blank line and line ending is kept, so
+/// writing an unmodified document reproduces the source byte for byte.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Document<'a> {
+    pub preamble: Vec<Line<'a>>,
+    pub sections: Vec<Section<'a>>,
+    /// Line ending used for lines added by [`Document::update`].
+    pub newline: &'a str,
+}
+
+impl<'a> Document<'a> {
+    pub fn parse(source: &'a str) -> Document<'a> {
+        let mut document = Document {
+            preamble: Vec::new(),
+            sections: Vec::new(),
+            newline: "\n",
+        };
+        let mut found_newline = false;
+        let mut rest = source;
+
+        while !rest.is_empty() {
+            let (line, tail) = match rest.find('\n') {
+                Some(idx) => rest.split_at(idx + 1),
+                None => (rest, ""),
+            };
+            rest = tail;
+
+            let text_len = line.trim_end_matches('\n').trim_end_matches('\r').len();
+            let (text, ending) = line.split_at(text_len);
+            if !found_newline && !ending.is_empty() {
+                document.newline = ending;
+                found_newline = true;
+            }
+
+            let line = Line {
+                text: text.into(),
+                ending,
+            };
+            if is_section_header(text) && !document.continues_attachment(text) {
+                document.sections.push(Section {
+                    header: line,
+                    lines: Vec::new(),
+                });
+            } else {
+                match document.sections.last_mut() {
+                    Some(section) => section.lines.push(line),
+                    None => document.preamble.push(line),
+                }
+            }
+        }
+
+        document
+    }
+
+    /// Whether `text` is attachment data that merely looks like a section header.
+    fn continues_attachment(&self, text: &str) -> bool {
+        let Some(section) = self.sections.last() else {
+            return false;
+        };
+        let SectionKind::Attachments(kind) = SectionKind::of(section.title()) else {
+            return false;
+        };
+        let previous = section
+            .lines
+            .iter()
+            .rfind(|line| line.kind() != LineKind::Blank)
+            .map_or("", |line| &line.text);
+        continues_entry(kind, previous, text)
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = &Line<'a>> {
+        self.preamble.iter().chain(
+            self.sections
+                .iter()
+                .flat_map(|section| std::iter::once(&section.header).chain(&section.lines)),
+        )
+    }
+
+    pub fn section(&self, title: &str) -> Option<&Section<'a>> {
+        self.sections
+            .iter()
+            .find(|section| section.title().eq_ignore_ascii_case(title))
+    }
+
+    pub fn section_mut(&mut self, title: &str) -> Option<&mut Section<'a>> {
+        self.sections
+            .iter_mut()
+            .find(|section| section.title().eq_ignore_ascii_case(title))
+    }
+
+    pub fn script(&self) -> Result<Script<'_>, ParseError> {
+        self.script_with(&mut Diagnostics::new(ParseMode::Strict))
+    }
+
+    /// Builds the typed model from the current state of the document, reading the sections the
+    /// same way [`Script::from_parser`] reads a source string.
+    pub fn script_with(&self, diagnostics: &mut Diagnostics) -> Result<Script<'_>, ParseError> {
+        let mut cursor = SpanCursor::default();
+        let lines = self
+            .lines()
+            .map(move |line| (line.text.as_ref(), cursor.next(line)));
+        let mut parser = SSAParser::from_lines(lines, std::mem::take(diagnostics));
+        let script = Script::from_parser(&mut parser);
+        *diagnostics = parser.diagnostics;
+        script
+    }
+
+    /// Writes the typed values of `script` back into the document, touching only the lines and
+    /// fields whose values changed. Styles, events, attachments and extradata entries are matched
+    /// to existing lines by position; surplus items are appended and missing ones removed from
+    /// the end.
+    ///
+    /// `script` is usually parsed from the same source with [`crate::parse`], then edited.
+    pub fn update(&mut self, script: &Script<'_>) {
+        let info = &script.info;
+        self.update_entries(
+            SectionKind::ScriptInfo,
+            &SCRIPT_INFO_FIELDS,
+            |entries| {
+                let mut diagnostics = Diagnostics::new(ParseMode::Lenient);
+                let mut old = ScriptInfo::default();
+                for (field, value) in entries {
+                    let _ = old.parse_field(field, value, Span::default(), &mut diagnostics);
+                }
+                SCRIPT_INFO_FIELDS
+                    .iter()
+                    .map(|(field, _)| old.field_text(*field).map(Cow::into_owned))
+                    .collect()
+            },
+            |field| info.field_text(field),
+            &info.extra,
+        );
+        let project = &script.project;
+        self.update_entries(
+            SectionKind::ProjectGarbage,
+            &PROJECT_GARBAGE_FIELDS,
+            |entries| {
+                let mut diagnostics = Diagnostics::new(ParseMode::Lenient);
+                let mut old = ProjectGarbage::default();
+                for (field, value) in entries {
+                    let _ = old.parse_field(field, value, Span::default(), &mut diagnostics);
+                }
+                PROJECT_GARBAGE_FIELDS
+                    .iter()
+                    .map(|(field, _)| old.field_text(*field).map(Cow::into_owned))
+                    .collect()
+            },
+            |field| project.field_text(field),
+            &project.extra,
+        );
+        self.update_items(SectionKind::Styles, &script.styles, |format, key, value| {
+            parse_canonical::<{ style::MAX_FIELDS }, StyleParser, _>(
+                format,
+                key,
+                value,
+                |style, fields| canonical_fields(&style, fields),
+            )
+        });
+        for kind in [AttachmentKind::Font, AttachmentKind::Graphic] {
+            self.update_attachments(kind, script.attachments(kind));
+        }
+        self.update_items(SectionKind::Events, &script.events, |format, key, value| {
+            parse_canonical::<{ events::MAX_FIELDS }, EventLineParser, _>(
+                format,
+                key,
+                value,
+                |event, fields| canonical_fields(&event, fields),
+            )
+        });
+        self.update_extradata(&script.extradata);
+    }
+
+    /// Syncs a key-value section: `old` reads the current values of `fields` the way `new`
+    /// writes them, and only the entries whose text differs are rewritten, added or removed.
+    /// Unknown keys are synced to `extra`.
+    fn update_entries<'n, F: FromStr + Copy + PartialEq>(
+        &mut self,
+        kind: SectionKind,
+        fields: &[(F, &str)],
+        old: impl for<'l> FnOnce(Vec<(F, &'l str)>) -> Vec<Option<String>>,
+        new: impl Fn(F) -> Option<Cow<'n, str>>,
+        extra: &[(Cow<'_, str>, Cow<'_, str>)],
+    ) {
+        let old = old(self
+            .sections_of(kind)
+            .flat_map(|s| &s.lines)
+            .filter_map(|line| match line.kind() {
+                LineKind::Entry { key, value } => Some((F::from_str(key).ok()?, value)),
+                _ => None,
+            })
+            .collect());
+
+        for ((field, key), old) in fields.iter().zip(old) {
+            let new = new(*field);
+            if new.as_deref() == old.as_deref() {
+                continue;
+            }
+
+            let existing = self.find_last(kind, |line| {
+                matches!(line.kind(), LineKind::Entry { key, .. } if F::from_str(key).ok() == Some(*field))
+            });
+
+            match (existing, new) {
+                (Some((s, l)), Some(value)) => {
+                    let line = &mut self.sections[s].lines[l];
+                    let start = line.value_start().unwrap_or(line.text.len());
+                    let trailing = &line.text[line.text.trim_end().len().max(start)..];
+                    line.text = format!("{}{}{}", &line.text[..start], value, trailing).into();
+                }
+                (Some((s, l)), None) => {
+                    self.sections[s].lines.remove(l);
+                }
+                (None, Some(value)) => {
+                    let s = self.section_index_or_insert(kind);
+                    let at = self.sections[s].insertion_point();
+                    self.insert_line(s, at, format!("{key}: {value}"));
+                }
+                (None, None) => {}
+            }
+        }
+
+        self.update_extra::<F>(kind, extra);
+    }
+
+    /// Syncs the lines with unknown keys to `extra`, pairing lines and entries with the same key
+    /// in order.
+    fn update_extra<F: FromStr>(
+        &mut self,
+        kind: SectionKind,
+        extra: &[(Cow<'_, str>, Cow<'_, str>)],
+    ) {
+        let mut unknown: Vec<(usize, usize)> = Vec::new();
+        for (s, section) in self.sections.iter().enumerate() {
+            if SectionKind::of(section.title()) != kind {
+                continue;
+            }
+            for (l, line) in section.lines.iter().enumerate() {
+                if let LineKind::Entry { key, .. } = line.kind() {
+                    if F::from_str(key).is_err() {
+                        unknown.push((s, l));
+                    }
+                }
+            }
+        }
+
+        for (key, value) in extra {
+            let matching = unknown.iter().position(|&(s, l)| {
+                matches!(self.sections[s].lines[l].kind(), LineKind::Entry { key: k, .. } if k == key)
+            });
+            match matching {
+                Some(idx) => {
+                    let (s, l) = unknown.remove(idx);
+                    let line = &mut self.sections[s].lines[l];
+                    if matches!(line.kind(), LineKind::Entry { value: v, .. } if v == value) {
+                        continue;
+                    }
+                    let start = line.value_start().unwrap_or(line.text.len());
+                    let trailing = &line.text[line.text.trim_end().len().max(start)..];
+                    line.text = format!("{}{}{}", &line.text[..start], value, trailing).into();
+                }
+                None => {
+                    let s = self.section_index_or_insert(kind);
+                    let at = self.sections[s].insertion_point();
+                    self.insert_line(s, at, format!("{key}: {value}"));
+                }
+            }
+        }
+
+        for (s, l) in unknown.into_iter().rev() {
+            self.sections[s].lines.remove(l);
+        }
+    }
+
+    /// Syncs the `Data:` lines to `extradata`, matching entries to lines by position.
+    fn update_extradata(&mut self, extradata: &[Extradata<'_>]) {
+        let positions = self.entry_positions(SectionKind::Extradata);
+
+        for (entry, &(s, l)) in extradata.iter().zip(&positions) {
+            let line = &mut self.sections[s].lines[l];
+            let old = match line.kind() {
+                LineKind::Entry { key, value } => parse_extradata(key, value, Span::default()).ok(),
+                _ => None,
+            };
+            if old.as_ref() != Some(entry) {
+                line.text = format!("Data: {}", entry.encoded()).into();
+            }
+        }
+
+        for &(s, l) in positions.iter().skip(extradata.len()).rev() {
+            self.sections[s].lines.remove(l);
+        }
+
+        if extradata.len() > positions.len() {
+            let s = self.section_index_or_insert(SectionKind::Extradata);
+            let start = self.sections[s].insertion_point();
+            for (at, entry) in (start..).zip(&extradata[positions.len()..]) {
+                self.insert_line(s, at, format!("Data: {}", entry.encoded()));
+            }
+        }
+    }
+
+    /// Syncs a `[Fonts]` or `[Graphics]` section to `attachments`, matched by position. Only
+    /// the lines of attachments whose name or data changed are rewritten.
+    fn update_attachments(&mut self, kind: AttachmentKind, attachments: &[Attachment<'_>]) {
+        // the section and line range of each attachment in the document
+        let mut ranges: Vec<(usize, usize, usize)> = Vec::new();
+        for (s, section) in self.sections.iter().enumerate() {
+            if SectionKind::of(section.title()) != SectionKind::Attachments(kind) {
+                continue;
+            }
+            for (l, line) in section.lines.iter().enumerate() {
+                if kind.entry_name(&line.text).is_some() {
+                    ranges.push((s, l, l + 1));
+                } else if line.kind() != LineKind::Blank {
+                    match ranges.last_mut() {
+                        Some((last, _, end)) if *last == s => *end = l + 1,
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        let newline = self.newline;
+        let lines_of = |attachment: &Attachment<'_>| {
+            std::iter::once(format!("{}: {}", kind.key(), attachment.name))
+                .chain(attachment.encoded_lines())
+                .collect::<Vec<_>>()
+        };
+
+        for (idx, &(s, start, end)) in ranges.iter().enumerate().rev() {
+            let lines = &mut self.sections[s].lines;
+            let Some(attachment) = attachments.get(idx) else {
+                lines.drain(start..end);
+                continue;
+            };
+
+            let mut reader = AttachmentReader::new(kind);
+            for line in &lines[start..end] {
+                if line.kind() != LineKind::Blank {
+                    let _ = reader.push(&line.text, Span::default());
+                }
+            }
+            if reader.finish().first() == Some(attachment) {
+                continue;
+            }
+
+            let ending = lines[end - 1].ending;
+            let mut new: Vec<Line<'a>> = lines_of(attachment)
+                .into_iter()
+                .map(|text| Line {
+                    text: text.into(),
+                    ending: newline,
+                })
+                .collect();
+            if let Some(last) = new.last_mut() {
+                last.ending = ending;
+            }
+            lines.splice(start..end, new);
+        }
+
+        if attachments.len() > ranges.len() {
+            let s = self.section_index_or_insert(SectionKind::Attachments(kind));
+            let mut at = self.sections[s].insertion_point();
+            for attachment in &attachments[ranges.len()..] {
+                for text in lines_of(attachment) {
+                    self.insert_line(s, at, text);
+                    at += 1;
+                }
+            }
+        }
+    }
+
+    fn update_items<T: FormattedLine>(
+        &mut self,
+        kind: SectionKind,
+        items: &[T],
+        parse_old: impl Fn(&str, &str, &str) -> Option<(&'static str, Vec<String>)>,
+    ) where
+        T::Fields: FromStr + Default,
+    {
+        let default_format = default_format(kind);
+        let positions = self.entry_positions(kind);
+
+        for (item, &(s, l)) in items.iter().zip(&positions) {
+            let section = &self.sections[s];
+            let format = section.format().unwrap_or(default_format);
+            let fields = parse_format::<T::Fields>(format);
+            let new = canonical_fields(item, &fields);
+
+            let line = &section.lines[l];
+            let LineKind::Entry { key, value } = line.kind() else {
+                continue;
+            };
+            let old = parse_old(format, key, value);
+            if old.as_ref() == Some(&new) {
+                continue;
+            }
+
+            let text = rewrite_fields(line, &new, old.as_ref());
+            self.sections[s].lines[l].text = text.into();
+        }
+
+        for &(s, l) in positions.iter().skip(items.len()).rev() {
+            self.sections[s].lines.remove(l);
+        }
+
+        if items.len() > positions.len() {
+            let s = self.section_index_or_insert(kind);
+            let format = self.sections[s]
+                .format()
+                .unwrap_or(default_format)
+                .to_owned();
+            let fields = parse_format::<T::Fields>(&format);
+            let start = self.sections[s].insertion_point();
+            for (at, item) in (start..).zip(&items[positions.len()..]) {
+                let (key, values) = canonical_fields(item, &fields);
+                self.insert_line(s, at, format!("{key}: {}", values.join(",")));
+            }
+        }
+    }
+
+    /// Every entry of the sections of `kind` other than their Format lines.
+    fn entry_positions(&self, kind: SectionKind) -> Vec<(usize, usize)> {
+        let mut positions = Vec::new();
+        for (s, section) in self.sections.iter().enumerate() {
+            if SectionKind::of(section.title()) != kind {
+                continue;
+            }
+            for (l, line) in section.lines.iter().enumerate() {
+                if line.is_entry() && !line.is_format() {
+                    positions.push((s, l));
+                }
+            }
+        }
+        positions
+    }
+
+    fn sections_of(&self, kind: SectionKind) -> impl Iterator<Item = &Section<'a>> {
+        self.sections
+            .iter()
+            .filter(move |section| SectionKind::of(section.title()) == kind)
+    }
+
+    fn find_last(
+        &self,
+        kind: SectionKind,
+        predicate: impl Fn(&Line<'a>) -> bool,
+    ) -> Option<(usize, usize)> {
+        self.sections
+            .iter()
+            .enumerate()
+            .filter(|(_, section)| SectionKind::of(section.title()) == kind)
+            .flat_map(|(s, section)| {
+                section
+                    .lines
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, line)| predicate(line))
+                    .map(move |(l, _)| (s, l))
+            })
+            .last()
+    }
+
+    fn insert_line(&mut self, section: usize, at: usize, text: String) {
+        let newline = self.newline;
+        let section = &mut self.sections[section];
+        let previous = match at.checked_sub(1) {
+            Some(idx) => &mut section.lines[idx],
+            None => &mut section.header,
+        };
+        if previous.ending.is_empty() {
+            previous.ending = newline;
+        }
+
+        section.lines.insert(
+            at,
+            Line {
+                text: text.into(),
+                ending: newline,
+            },
+        );
+    }
+
+    /// Finds the first section of `kind`, creating it where Aegisub would put it if missing.
+    fn section_index_or_insert(&mut self, kind: SectionKind) -> usize {
+        if let Some(idx) = self
+            .sections
+            .iter()
+            .position(|section| SectionKind::of(section.title()) == kind)
+        {
+            return idx;
+        }
+
+        let idx = self
+            .sections
+            .iter()
+            .position(|section| SectionKind::of(section.title()).order() > kind.order())
+            .unwrap_or(self.sections.len());
+        let title = match kind {
+            SectionKind::ScriptInfo => "Script Info",
+            SectionKind::Styles => "V4+ Styles",
+            SectionKind::ProjectGarbage => PROJECT_GARBAGE_TITLE,
+            SectionKind::Extradata => EXTRADATA_TITLE,
+            SectionKind::Attachments(kind) => kind.section_title(),
+            _ => "Events",
+        };
+
+        let newline = self.newline;
+        let mut lines = Vec::new();
+        if matches!(kind, SectionKind::Styles | SectionKind::Events) {
+            lines.push(Line {
+                text: format!("Format: {}", default_format(kind)).into(),
+                ending: newline,
+            });
+        }
+        lines.push(Line {
+            text: "".into(),
+            ending: newline,
+        });
+
+        if let Some(previous) = idx.checked_sub(1).map(|i| &mut self.sections[i]) {
+            match previous.lines.last_mut() {
+                Some(last) if last.ending.is_empty() => last.ending = newline,
+                None if previous.header.ending.is_empty() => previous.header.ending = newline,
+                _ => {}
+            }
+            if previous.lines.last().map(Line::kind) != Some(LineKind::Blank) {
+                previous.lines.push(Line {
+                    text: "".into(),
+                    ending: newline,
+                });
+            }
+        }
+
+        self.sections.insert(
+            idx,
+            Section {
+                header: Line {
+                    text: format!("[{title}]").into(),
+                    ending: newline,
+                },
+                lines,
+            },
+        );
+        idx
+    }
+}
+
+impl<'a> WriteAss for Document<'a> {
+    fn write_ass<W: Write>(&self, out: &mut W) -> fmt::Result {
+        for line in self.lines() {
+            out.write_str(&line.text)?;
+            out.write_str(line.ending)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct SpanCursor {
+    offset: usize,
+    line: usize,
+}
+
+impl SpanCursor {
+    fn next(&mut self, line: &Line<'_>) -> Span {
+        let span = Span::start_of_line(self.offset, self.line + 1);
+        self.offset += line.text.len() + line.ending.len();
+        self.line += 1;
+        span
+    }
+}
+
+fn default_format(kind: SectionKind) -> &'static str {
+    match kind {
+        SectionKind::Styles => {
+            <StyleParser as LineItemParser<{ style::MAX_FIELDS }>>::DEFAULT_FORMAT
+        }
+        _ => <EventLineParser as LineItemParser<{ events::MAX_FIELDS }>>::DEFAULT_FORMAT,
+    }
+}
+
+/// Format fields in order; unknown names become the default (ignored) field.
+fn parse_format<F: FromStr + Default>(format: &str) -> Vec<F> {
+    format
+        .split(',')
+        .map(|name| F::from_str(name.trim()).unwrap_or_default())
+        .collect()
+}
+
+fn canonical_fields<T: FormattedLine>(
+    item: &T,
+    fields: &[T::Fields],
+) -> (&'static str, Vec<String>) {
+    let values = fields
+        .iter()
+        .map(|field| {
+            let mut out = String::new();
+            let _ = item.write_field(&mut out, *field);
+            out
+        })
+        .collect();
+    (item.key(), values)
+}
+
+/// Parses an existing line leniently and returns its canonical key and field texts.
+fn parse_canonical<const MAX_FIELDS: usize, L, F>(
+    format: &str,
+    key: &str,
+    value: &str,
+    canonical: F,
+) -> Option<(&'static str, Vec<String>)>
+where
+    L: LineItemParser<MAX_FIELDS>,
+    F: for<'x> FnOnce(L::Item<'x>, &[L::Fields]) -> (&'static str, Vec<String>),
+{
+    let mut diagnostics = Diagnostics::new(ParseMode::Lenient);
+    let parser =
+        LineStreamParser::<MAX_FIELDS, L>::new(format, Span::default(), &mut diagnostics).ok()?;
+    let item = parser
+        .parse_line(key, value, Span::default(), &mut diagnostics)
+        .ok()?;
+    Some(canonical(item, &parse_format(format)))
+}
+
+/// Rebuilds an entry line, keeping the original text (and padding) of every field whose
+/// canonical value did not change.
+fn rewrite_fields(
+    line: &Line<'_>,
+    new: &(&'static str, Vec<String>),
+    old: Option<&(&'static str, Vec<String>)>,
+) -> String {
+    let text = &*line.text;
+    let start = line.value_start().unwrap_or(text.len());
+    let mut out = match old {
+        Some((old_key, _)) if *old_key == new.0 => text[..start].to_owned(),
+        _ => format!("{}: ", new.0),
+    };
+
+    let raw_fields: Vec<&str> = text[start..].splitn(new.1.len(), ',').collect();
+    for (idx, value) in new.1.iter().enumerate() {
+        if idx > 0 {
+            out.push(',');
+        }
+
+        let raw = raw_fields.get(idx).copied();
+        let unchanged = old.is_some_and(|(_, old)| old.get(idx) == Some(value));
+        match raw {
+            Some(raw) if unchanged => out.push_str(raw),
+            Some(raw) => {
+                let trimmed_start = raw.trim_start();
+                let lead = &raw[..raw.len() - trimmed_start.len()];
+                let trail = &trimmed_start[trimmed_start.trim_end().len()..];
+                out.push_str(lead);
+                out.push_str(value);
+                out.push_str(trail);
+            }
+            None => out.push_str(value),
+        }
+    }
+
+    out
+}
@@ -0,0 +1,111 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ParseError, Span};
+
+/// Strict parsing stops at the first error. Lenient parsing records a [`Diagnostic`] for every
+/// problem and carries on with a best-effort value. Warnings never stop a parse.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ParseMode {
+    #[default]
+    Strict,
+    Lenient,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub span: Span,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn from_error(severity: Severity, error: &ParseError) -> Diagnostic {
+        Diagnostic {
+            severity,
+            span: error.span(),
+            message: error.message(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}: {}", self.severity, self.span, self.message)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    mode: ParseMode,
+    items: Vec<Diagnostic>,
+    failed: bool,
+}
+
+impl Diagnostics {
+    pub fn new(mode: ParseMode) -> Diagnostics {
+        Diagnostics {
+            mode,
+            ..Default::default()
+        }
+    }
+
+    pub fn mode(&self) -> ParseMode {
+        self.mode
+    }
+
+    pub fn items(&self) -> &[Diagnostic] {
+        &self.items
+    }
+
+    pub fn take(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.items)
+    }
+
+    /// Whether a strict parse has already returned an error.
+    pub fn failed(&self) -> bool {
+        self.failed
+    }
+
+    /// Hands `error` back in strict mode, records it in lenient mode. Warnings are recorded in
+    /// either mode.
+    pub fn report(&mut self, severity: Severity, error: ParseError) -> Result<(), ParseError> {
+        match self.mode {
+            ParseMode::Strict if severity == Severity::Error => {
+                self.failed = true;
+                Err(error)
+            }
+            _ => {
+                self.items.push(Diagnostic::from_error(severity, &error));
+                Ok(())
+            }
+        }
+    }
+
+    pub fn recover<T>(
+        &mut self,
+        result: Result<T, ParseError>,
+        fallback: impl FnOnce() -> T,
+    ) -> Result<T, ParseError> {
+        match result {
+            Ok(v) => Ok(v),
+            Err(e) => self.report(Severity::Error, e).map(|_| fallback()),
+        }
+    }
+}
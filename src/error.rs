@@ -0,0 +1,173 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// A location in the source text. `offset` is a byte offset, `line` and `column` are 1-based,
+/// with `column` counted in characters.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub(crate) fn start_of_line(offset: usize, line: usize) -> Span {
+        Span {
+            offset,
+            line,
+            column: 1,
+        }
+    }
+
+    /// Moves the span past `text`, which must not contain a line break.
+    pub(crate) fn advance(self, text: &str) -> Span {
+        Span {
+            offset: self.offset + text.len(),
+            line: self.line,
+            column: self.column + text.chars().count(),
+        }
+    }
+
+    /// Span of `inner`, a subslice of `outer`, where `self` is the span of `outer`.
+    pub(crate) fn of_subslice(self, outer: &str, inner: &str) -> Span {
+        let start = inner.as_ptr() as usize - outer.as_ptr() as usize;
+        self.advance(&outer[..start])
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    MissingSectionHeader {
+        span: Span,
+    },
+    UnexpectedSection {
+        found: String,
+        span: Span,
+    },
+    MissingKeyValueSeparator {
+        span: Span,
+    },
+    MissingFormatLine {
+        span: Span,
+    },
+    UnknownFormatField {
+        name: String,
+        span: Span,
+    },
+    TooManyFields {
+        max: usize,
+        span: Span,
+    },
+    UnexpectedLineKind {
+        key: String,
+        span: Span,
+    },
+    MissingField {
+        field: &'static str,
+        span: Span,
+    },
+    InvalidTimestamp {
+        field: &'static str,
+        value: String,
+        span: Span,
+    },
+    InvalidColor {
+        field: &'static str,
+        value: String,
+        span: Span,
+    },
+    InvalidBoolean {
+        field: &'static str,
+        value: String,
+        span: Span,
+    },
+    InvalidNumber {
+        field: &'static str,
+        value: String,
+        span: Span,
+    },
+    InvalidValue {
+        field: &'static str,
+        value: String,
+        span: Span,
+    },
+}
+
+impl ParseError {
+    pub fn span(&self) -> Span {
+        use ParseError::*;
+        match self {
+            MissingSectionHeader { span }
+            | UnexpectedSection { span, .. }
+            | MissingKeyValueSeparator { span }
+            | MissingFormatLine { span }
+            | UnknownFormatField { span, .. }
+            | TooManyFields { span, .. }
+            | UnexpectedLineKind { span, .. }
+            | MissingField { span, .. }
+            | InvalidTimestamp { span, .. }
+            | InvalidColor { span, .. }
+            | InvalidBoolean { span, .. }
+            | InvalidNumber { span, .. }
+            | InvalidValue { span, .. } => *span,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        use ParseError::*;
+        match self {
+            MissingSectionHeader { .. } => "expected a [section] header".to_owned(),
+            UnexpectedSection { found, .. } => format!("unexpected section [{found}]"),
+            MissingKeyValueSeparator { .. } => "expected a `key: value` line".to_owned(),
+            MissingFormatLine { .. } => "section does not start with a Format line".to_owned(),
+            UnknownFormatField { name, .. } => format!("unknown field `{name}` in Format line"),
+            TooManyFields { max, .. } => format!("Format line has more than {max} fields"),
+            UnexpectedLineKind { key, .. } => format!("unexpected `{key}` line"),
+            MissingField { field, .. } => format!("missing value for {field}"),
+            InvalidTimestamp { field, value, .. } => {
+                format!("invalid timestamp `{value}` for {field}")
+            }
+            InvalidColor { field, value, .. } => format!("invalid colour `{value}` for {field}"),
+            InvalidBoolean { field, value, .. } => format!("invalid boolean `{value}` for {field}"),
+            InvalidNumber { field, value, .. } => format!("invalid number `{value}` for {field}"),
+            InvalidValue { field, value, .. } => format!("invalid value `{value}` for {field}"),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}", self.message(), self.span())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ValueKind {
+    Timestamp,
+    Color,
+    Boolean,
+    Number,
+    Other,
+}
+
+impl ValueKind {
+    pub(crate) fn error(self, field: &'static str, value: &str, span: Span) -> ParseError {
+        let value = value.to_owned();
+        match self {
+            ValueKind::Timestamp => ParseError::InvalidTimestamp { field, value, span },
+            ValueKind::Color => ParseError::InvalidColor { field, value, span },
+            ValueKind::Boolean => ParseError::InvalidBoolean { field, value, span },
+            ValueKind::Number => ParseError::InvalidNumber { field, value, span },
+            ValueKind::Other => ParseError::InvalidValue { field, value, span },
+        }
+    }
+}
@@ -0,0 +1,632 @@
+use std::{fmt, ops::Not, str::FromStr};
+
+use regex::Regex;
+
+use crate::{
+    models::events::EventLine,
+    tags::{OverrideTag, Token},
+    Timestamp,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    pub fn test<T: Ord>(self, value: T, operand: T) -> bool {
+        match self {
+            Comparison::Eq => value == operand,
+            Comparison::Ne => value != operand,
+            Comparison::Lt => value < operand,
+            Comparison::Le => value <= operand,
+            Comparison::Gt => value > operand,
+            Comparison::Ge => value >= operand,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarginSide {
+    Left,
+    Right,
+    Vertical,
+}
+
+/// A condition on event lines. Filters combine with [`EventFilter::and`], [`EventFilter::or`]
+/// and `!`, and can be parsed from a query such as
+/// `style:Sign* layer>=5 time:10:00-12:00 tag:pos`; see [`EventFilter::parse`].
+#[derive(Debug, Clone)]
+pub enum EventFilter {
+    /// Style name matching a pattern where `*` is any run of characters and `?` any one.
+    Style(String),
+    /// Actor name matching a `*` and `?` pattern.
+    Name(String),
+    /// Effect matching a `*` and `?` pattern.
+    Effect(String),
+    /// Lines without a layer are on layer 0.
+    Layer(Comparison, i64),
+    Comment(bool),
+    Start(Comparison, Timestamp),
+    End(Comparison, Timestamp),
+    /// Lines shown at some point from the first time up to the second.
+    During(Timestamp, Timestamp),
+    Margin(MarginSide, Comparison, i64),
+    /// Text, override blocks included, matching a regex.
+    Text(Regex),
+    /// Lines with an override tag of this name, as given by [`OverrideTag::name`], anywhere in
+    /// their text including inside `\t`.
+    Tag(String),
+    And(Vec<EventFilter>),
+    Or(Vec<EventFilter>),
+    Not(Box<EventFilter>),
+}
+
+impl EventFilter {
+    /// Parses a query. Terms separated by spaces must all match; `or` (or `|`) separates
+    /// alternatives, `not` (or a leading `-` or `!`) negates a term and parentheses group.
+    ///
+    /// - `style:`, `name:` (or `actor:`) and `effect:` take a `*` and `?` pattern.
+    /// - `layer`, `marginl`, `marginr`, `marginv`, `start` and `end` compare with `:`, `=`,
+    ///   `!=`, `<`, `<=`, `>` or `>=` against a number or a time such as `1:02:03.45` or `10:00`.
+    /// - `time:<from>-<to>` picks lines shown in that range.
+    /// - `text:` looks for literal text and `text~` for a regex.
+    /// - `tag:` picks lines with an override tag, e.g. `tag:pos` or `tag:3c`.
+    /// - `comment` and `dialogue` pick comment and dialogue lines.
+    ///
+    /// Values with spaces or parentheses can be quoted: `text:"two words"`. Inside quotes only
+    /// `\"` is an escape, so backslashes can be written as they are.
+    pub fn parse(query: &str) -> Result<EventFilter, QueryError> {
+        let tokens = lex(query)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let filter = parser.or()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(filter),
+            Some(token) => Err(QueryError::UnexpectedToken { at: token.at }),
+        }
+    }
+
+    /// Lines whose text contains `text`.
+    pub fn text_contains(text: &str) -> EventFilter {
+        EventFilter::Text(Regex::new(&regex::escape(text)).expect("escaped text is a valid regex"))
+    }
+
+    pub fn and(self, other: EventFilter) -> EventFilter {
+        match self {
+            EventFilter::And(mut filters) => {
+                filters.push(other);
+                EventFilter::And(filters)
+            }
+            filter => EventFilter::And(vec![filter, other]),
+        }
+    }
+
+    pub fn or(self, other: EventFilter) -> EventFilter {
+        match self {
+            EventFilter::Or(mut filters) => {
+                filters.push(other);
+                EventFilter::Or(filters)
+            }
+            filter => EventFilter::Or(vec![filter, other]),
+        }
+    }
+
+    pub fn matches(&self, event: &EventLine<'_>) -> bool {
+        use EventFilter::*;
+        match self {
+            Style(pattern) => glob_match(pattern, &event.style),
+            Name(pattern) => glob_match(pattern, &event.name),
+            Effect(pattern) => glob_match(pattern, &event.effect),
+            Layer(cmp, layer) => cmp.test(event.layer.unwrap_or(0), *layer),
+            Comment(comment) => event.is_comment == *comment,
+            Start(cmp, time) => event.start.is_some_and(|start| cmp.test(start, *time)),
+            End(cmp, time) => event.end.is_some_and(|end| cmp.test(end, *time)),
+            During(from, to) => match (event.start, event.end) {
+                // a range of no length is the single centisecond at its start
+                (Some(start), Some(end)) => {
+                    start < (*to).max(*from + Timestamp::from_centis(1)) && end > *from
+                }
+                _ => false,
+            },
+            Margin(side, cmp, margin) => {
+                let value = match side {
+                    MarginSide::Left => event.margin_left,
+                    MarginSide::Right => event.margin_right,
+                    MarginSide::Vertical => event.margin_vertical,
+                };
+                cmp.test(value, *margin)
+            }
+            Text(regex) => regex.is_match(&event.text),
+            Tag(name) => has_tag(event, name),
+            And(filters) => filters.iter().all(|f| f.matches(event)),
+            Or(filters) => filters.iter().any(|f| f.matches(event)),
+            Not(filter) => !filter.matches(event),
+        }
+    }
+
+    /// The matching events with their indices.
+    pub fn select<'e, 'a>(
+        &'e self,
+        events: &'e [EventLine<'a>],
+    ) -> impl Iterator<Item = (usize, &'e EventLine<'a>)> + 'e {
+        events
+            .iter()
+            .enumerate()
+            .filter(|(_, event)| self.matches(event))
+    }
+
+    pub fn select_mut<'e, 'a>(
+        &'e self,
+        events: &'e mut [EventLine<'a>],
+    ) -> impl Iterator<Item = (usize, &'e mut EventLine<'a>)> + 'e {
+        events
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, event)| self.matches(event))
+    }
+
+    pub fn indices(&self, events: &[EventLine<'_>]) -> Vec<usize> {
+        self.select(events).map(|(idx, _)| idx).collect()
+    }
+}
+
+impl Not for EventFilter {
+    type Output = EventFilter;
+
+    fn not(self) -> EventFilter {
+        match self {
+            EventFilter::Not(filter) => *filter,
+            filter => EventFilter::Not(Box::new(filter)),
+        }
+    }
+}
+
+impl FromStr for EventFilter {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        EventFilter::parse(s)
+    }
+}
+
+/// Matches `text` against a pattern where `*` is any run of characters and `?` any one.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // the last `*` and the text position it is currently matched up to
+    let mut star = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+fn has_tag(event: &EventLine<'_>, name: &str) -> bool {
+    fn any(tags: &[OverrideTag<'_>], name: &str) -> bool {
+        tags.iter().any(|tag| {
+            tag.name() == name
+                || matches!(tag, OverrideTag::Transform { tags, .. } if any(tags, name))
+        })
+    }
+
+    let name = name.strip_prefix('\\').unwrap_or(name);
+    // names the parser reads as another tag
+    let name = match name {
+        "1c" => "c",
+        "fr" => "frz",
+        "K" => "kf",
+        name => name,
+    };
+    event.tokens().any(|token| match token {
+        Token::Override(tags) => any(&tags, name),
+        _ => false,
+    })
+}
+
+/// An error in a query, at a byte offset into it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryError {
+    UnexpectedEnd,
+    UnexpectedToken { at: usize },
+    UnclosedQuote { at: usize },
+    UnknownField { field: String, at: usize },
+    InvalidValue { value: String, at: usize },
+    InvalidRegex { message: String, at: usize },
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use QueryError::*;
+        match self {
+            UnexpectedEnd => write!(f, "unexpected end of query"),
+            UnexpectedToken { at } => write!(f, "unexpected token at {at}"),
+            UnclosedQuote { at } => write!(f, "unclosed quote at {at}"),
+            UnknownField { field, at } => write!(f, "unknown field `{field}` at {at}"),
+            InvalidValue { value, at } => write!(f, "invalid value `{value}` at {at}"),
+            InvalidRegex { message, at } => write!(f, "invalid regex at {at}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+#[derive(Debug, PartialEq)]
+enum TokenKind {
+    Open,
+    Close,
+    Bang,
+    Word(String),
+}
+
+struct QueryToken {
+    kind: TokenKind,
+    at: usize,
+}
+
+fn lex(query: &str) -> Result<Vec<QueryToken>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = query.char_indices().peekable();
+
+    while let Some(&(at, c)) = chars.peek() {
+        let kind = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '(' | ')' | '|' => {
+                chars.next();
+                match c {
+                    '(' => TokenKind::Open,
+                    ')' => TokenKind::Close,
+                    _ => TokenKind::Word("or".to_owned()),
+                }
+            }
+            '-' | '!' => {
+                chars.next();
+                TokenKind::Bang
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&(quote_at, c)) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' {
+                        break;
+                    }
+                    chars.next();
+                    if c != '"' {
+                        word.push(c);
+                        continue;
+                    }
+                    loop {
+                        match chars.next() {
+                            Some((_, '"')) => break,
+                            Some((_, '\\')) if chars.peek().is_some_and(|(_, c)| *c == '"') => {
+                                word.push('"');
+                                chars.next();
+                            }
+                            Some((_, c)) => word.push(c),
+                            None => return Err(QueryError::UnclosedQuote { at: quote_at }),
+                        }
+                    }
+                }
+                TokenKind::Word(word)
+            }
+        };
+        tokens.push(QueryToken { kind, at });
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<QueryToken>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&QueryToken> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(QueryToken { kind: TokenKind::Word(word), .. }) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn or(&mut self) -> Result<EventFilter, QueryError> {
+        let mut filter = self.and()?;
+        while self.peek_keyword("or") {
+            self.pos += 1;
+            filter = filter.or(self.and()?);
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<EventFilter, QueryError> {
+        let mut filter = self.unary()?;
+        loop {
+            if self.peek_keyword("and") {
+                self.pos += 1;
+            } else if self.peek_keyword("or")
+                || matches!(
+                    self.peek(),
+                    None | Some(QueryToken {
+                        kind: TokenKind::Close,
+                        ..
+                    })
+                )
+            {
+                return Ok(filter);
+            }
+            filter = filter.and(self.unary()?);
+        }
+    }
+
+    fn unary(&mut self) -> Result<EventFilter, QueryError> {
+        if self.peek_keyword("not") {
+            self.pos += 1;
+            return Ok(!self.unary()?);
+        }
+
+        let QueryToken { kind, at } = self.tokens.get(self.pos).ok_or(QueryError::UnexpectedEnd)?;
+        let at = *at;
+        self.pos += 1;
+        match kind {
+            TokenKind::Bang => Ok(!self.unary()?),
+            TokenKind::Open => {
+                let filter = self.or()?;
+                match self.peek() {
+                    Some(QueryToken {
+                        kind: TokenKind::Close,
+                        ..
+                    }) => {
+                        self.pos += 1;
+                        Ok(filter)
+                    }
+                    Some(token) => Err(QueryError::UnexpectedToken { at: token.at }),
+                    None => Err(QueryError::UnexpectedEnd),
+                }
+            }
+            TokenKind::Close => Err(QueryError::UnexpectedToken { at }),
+            TokenKind::Word(word) => term(word, at),
+        }
+    }
+}
+
+fn term(word: &str, at: usize) -> Result<EventFilter, QueryError> {
+    if word.eq_ignore_ascii_case("comment") {
+        return Ok(EventFilter::Comment(true));
+    }
+    if word.eq_ignore_ascii_case("dialogue") {
+        return Ok(EventFilter::Comment(false));
+    }
+
+    let field_len = word
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(word.len());
+    let (field, rest) = word.split_at(field_len);
+    let (op, value) = ["!=", "<=", ">=", ":", "~", "=", "<", ">"]
+        .into_iter()
+        .find_map(|op| Some((op, rest.strip_prefix(op)?)))
+        .ok_or_else(|| QueryError::UnknownField {
+            field: word.to_owned(),
+            at,
+        })?;
+    let value_at = at + field.len() + op.len();
+    let invalid = || QueryError::InvalidValue {
+        value: value.to_owned(),
+        at: value_at,
+    };
+    let comparison = || match op {
+        ":" | "=" => Ok(Comparison::Eq),
+        "!=" => Ok(Comparison::Ne),
+        "<" => Ok(Comparison::Lt),
+        "<=" => Ok(Comparison::Le),
+        ">" => Ok(Comparison::Gt),
+        ">=" => Ok(Comparison::Ge),
+        _ => Err(QueryError::UnexpectedToken {
+            at: at + field.len(),
+        }),
+    };
+    let pattern = || match op {
+        ":" | "=" => Ok(value.to_owned()),
+        _ => Err(QueryError::UnexpectedToken {
+            at: at + field.len(),
+        }),
+    };
+    let number = || value.parse::<i64>().map_err(|_| invalid());
+    let time = |value: &str| Timestamp::parse_lenient(value).ok_or_else(invalid);
+
+    Ok(match field.to_ascii_lowercase().as_str() {
+        "style" => EventFilter::Style(pattern()?),
+        "name" | "actor" => EventFilter::Name(pattern()?),
+        "effect" => EventFilter::Effect(pattern()?),
+        "layer" => EventFilter::Layer(comparison()?, number()?),
+        "marginl" => EventFilter::Margin(MarginSide::Left, comparison()?, number()?),
+        "marginr" => EventFilter::Margin(MarginSide::Right, comparison()?, number()?),
+        "marginv" => EventFilter::Margin(MarginSide::Vertical, comparison()?, number()?),
+        "start" => EventFilter::Start(comparison()?, time(value)?),
+        "end" => EventFilter::End(comparison()?, time(value)?),
+        "time" => {
+            pattern()?;
+            let (from, to) = value.split_once('-').ok_or_else(invalid)?;
+            EventFilter::During(time(from)?, time(to)?)
+        }
+        "text" if op == "~" => {
+            EventFilter::Text(Regex::new(value).map_err(|error| QueryError::InvalidRegex {
+                message: error.to_string(),
+                at: value_at,
+            })?)
+        }
+        "text" => EventFilter::text_contains(&pattern()?),
+        "tag" => EventFilter::Tag(pattern()?),
+        _ => {
+            return Err(QueryError::UnknownField {
+                field: field.to_owned(),
+                at,
+            })
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(
+        style: &'static str,
+        layer: i64,
+        start: i64,
+        end: i64,
+        text: &'static str,
+    ) -> EventLine<'static> {
+        EventLine {
+            style: style.into(),
+            layer: Some(layer),
+            start: Some(Timestamp::from_centis(start)),
+            end: Some(Timestamp::from_centis(end)),
+            text: text.into(),
+            ..EventLine::default()
+        }
+    }
+
+    fn events() -> Vec<EventLine<'static>> {
+        vec![
+            event("Default", 0, 0, 100, "plain line"),
+            event("Sign Top", 5, 100, 300, r"{\pos(10,20)}two words"),
+            event("Sign Bottom", 0, 300, 400, r"{\t(\3c&HFF&)}back\slash"),
+            event("Default", 10, 400, 400, "empty"),
+        ]
+    }
+
+    fn indices(query: &str) -> Vec<usize> {
+        EventFilter::parse(query).unwrap().indices(&events())
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(indices("style:Sign* layer:0 or style:Default"), [0, 2, 3]);
+        assert_eq!(indices("style:Default or style:Sign* layer:0"), [0, 2, 3]);
+        assert_eq!(indices("style:Sign* (layer:0 or style:Default)"), [2]);
+        assert_eq!(indices("style:Sign* and layer>0 | layer>=10"), [1, 3]);
+    }
+
+    #[test]
+    fn negation() {
+        assert_eq!(indices("not style:Sign*"), [0, 3]);
+        assert_eq!(indices("-style:Sign* layer:0"), [0]);
+        assert_eq!(indices("!(style:Sign* or layer:0)"), [3]);
+        assert_eq!(indices("not not layer:5"), [1]);
+        // `not` applies to one term, not to an `or`
+        assert_eq!(indices("not layer:0 or style:Default"), [0, 1, 3]);
+    }
+
+    #[test]
+    fn quoted_values() {
+        assert_eq!(indices(r#"text:"two words""#), [1]);
+        assert_eq!(indices(r#"style:"Sign Top""#), [1]);
+        assert_eq!(indices(r#"text:"back\slash""#), [2]);
+        assert_eq!(indices(r#"text:"\"""#), Vec::<usize>::new());
+        assert_eq!(
+            EventFilter::parse(r#"text:"open"#).unwrap_err(),
+            QueryError::UnclosedQuote { at: 5 }
+        );
+    }
+
+    #[test]
+    fn text_regex() {
+        assert_eq!(indices(r#"text~"^\w+ line$""#), [0]);
+        assert_eq!(indices(r"text~\\pos"), [1]);
+        assert!(matches!(
+            EventFilter::parse("layer:0 text~[a-"),
+            Err(QueryError::InvalidRegex { at: 13, .. })
+        ));
+    }
+
+    #[test]
+    fn time_ranges() {
+        assert_eq!(indices("time:0:00:01.00-0:00:03.00"), [1]);
+        assert_eq!(indices("time:1-3.5"), [1, 2]);
+        // a range of no length is the centisecond at its start
+        assert_eq!(indices("time:2-2"), [1]);
+        assert_eq!(indices("start>=3 end<=4"), [2, 3]);
+        assert_eq!(
+            EventFilter::parse("time:1").unwrap_err(),
+            QueryError::InvalidValue {
+                value: "1".to_owned(),
+                at: 5
+            }
+        );
+        assert!(EventFilter::parse("time>1-2").is_err());
+    }
+
+    #[test]
+    fn tags() {
+        assert_eq!(indices("tag:pos"), [1]);
+        assert_eq!(indices(r"tag:\3c"), [2]);
+        assert_eq!(indices("tag:move"), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn query_errors() {
+        assert_eq!(
+            EventFilter::parse("colour:red").unwrap_err(),
+            QueryError::UnknownField {
+                field: "colour".to_owned(),
+                at: 0
+            }
+        );
+        assert_eq!(
+            EventFilter::parse("layer:0 )").unwrap_err(),
+            QueryError::UnexpectedToken { at: 8 }
+        );
+        assert_eq!(
+            EventFilter::parse("(layer:0").unwrap_err(),
+            QueryError::UnexpectedEnd
+        );
+        assert_eq!(
+            EventFilter::parse("layer:0 or").unwrap_err(),
+            QueryError::UnexpectedEnd
+        );
+        assert_eq!(
+            EventFilter::parse("style<a").unwrap_err(),
+            QueryError::UnexpectedToken { at: 5 }
+        );
+    }
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob_match("Sign*", "Sign Top"));
+        assert!(glob_match("*Top", "Sign Top"));
+        assert!(glob_match("S?gn*p", "Sign Top"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "abbbc"));
+        assert!(glob_match("*a*", "banana"));
+        assert!(!glob_match("Sign", "Sign Top"));
+        assert!(!glob_match("?", ""));
+        assert!(!glob_match("a*b", "ac"));
+        assert!(glob_match("é?", "éa"));
+    }
+}
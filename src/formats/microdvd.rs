@@ -0,0 +1,321 @@
+use std::fmt::{self, Write};
+
+use crate::{
+    diagnostics::{Diagnostic, Diagnostics, ParseMode, Severity},
+    models::Color,
+    timing::{FrameTime, Framerate},
+    ParseError, Script, Span, Timestamp,
+};
+
+use super::{cues, new_event, new_script, source_lines, ExportOptions, Run, TextStyle};
+
+pub fn parse(data: &str, framerate: &Framerate) -> Result<Script<'static>, ParseError> {
+    parse_with(data, framerate, &mut Diagnostics::new(ParseMode::Strict))
+}
+
+pub fn parse_lenient(
+    data: &str,
+    framerate: &Framerate,
+) -> Result<(Script<'static>, Vec<Diagnostic>), ParseError> {
+    let mut diagnostics = Diagnostics::new(ParseMode::Lenient);
+    let script = parse_with(data, framerate, &mut diagnostics)?;
+    Ok((script, diagnostics.take()))
+}
+
+/// Reads `{start}{end}text` lines into a script with a single `Default` style, timing the frame
+/// numbers with `framerate`. `|` starts a new line, and the `{y:ibus}`, `{c:$BBGGRR}`, `{f:font}`
+/// and `{s:size}` control codes become override tags; lowercase codes only last until the end of
+/// their line. A leading `{1}{1}<fps>` framerate declaration is skipped.
+pub fn parse_with(
+    data: &str,
+    framerate: &Framerate,
+    diagnostics: &mut Diagnostics,
+) -> Result<Script<'static>, ParseError> {
+    let mut script = new_script();
+
+    for (line, span) in source_lines(data) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (start, end, text) = match parse_frames(line, span) {
+            Ok(frames) => frames,
+            Err(error) => {
+                diagnostics.report(Severity::Error, error)?;
+                continue;
+            }
+        };
+        if script.events.is_empty() && (start, end) == (1, 1) && text.trim().parse::<f64>().is_ok()
+        {
+            continue;
+        }
+
+        script.events.push(new_event(
+            framerate
+                .time_at_frame(start, FrameTime::Start)
+                .max(Timestamp::ZERO),
+            framerate.time_at_frame(end, FrameTime::End),
+            text_to_ass(text),
+        ));
+    }
+
+    Ok(script)
+}
+
+/// Splits `{start}{end}text` into its frame numbers and text.
+fn parse_frames(line: &str, span: Span) -> Result<(i64, i64, &str), ParseError> {
+    fn frame(rest: &str) -> Option<(i64, &str)> {
+        let (frame, rest) = rest.strip_prefix('{')?.split_once('}')?;
+        let frame = frame.trim();
+        let valid = !frame.is_empty() && frame.bytes().all(|b| b.is_ascii_digit());
+        Some((valid.then(|| frame.parse().ok()).flatten()?, rest))
+    }
+
+    frame(line)
+        .and_then(|(start, rest)| Some((start, frame(rest)?)))
+        .map(|(start, (end, text))| (start, end, text))
+        .ok_or_else(|| ParseError::InvalidValue {
+            field: "frames",
+            value: line.to_owned(),
+            span,
+        })
+}
+
+/// The override tag for a control code and, for lowercase codes, the tags that undo it at the
+/// end of the line. Unknown codes are dropped.
+fn control_code(key: char, value: &str) -> Option<(String, String)> {
+    let value = value.trim();
+    let (tags, resets) = match key.to_ascii_lowercase() {
+        'y' => {
+            let flags: String = value
+                .chars()
+                .map(|c| c.to_ascii_lowercase())
+                .filter(|c| matches!(c, 'i' | 'b' | 'u' | 's'))
+                .collect();
+            (
+                flags.chars().map(|c| format!("\\{c}1")).collect(),
+                flags.chars().map(|c| format!("\\{c}0")).collect(),
+            )
+        }
+        'c' => {
+            let hex = value.strip_prefix('$').unwrap_or(value);
+            if hex.len() != 6 {
+                return None;
+            }
+            let bgr = u32::from_str_radix(hex, 16).ok()?;
+            let color = Color::rgb(bgr as u8, (bgr >> 8) as u8, (bgr >> 16) as u8);
+            (format!("\\c{color}&"), "\\c".to_owned())
+        }
+        'f' if !value.is_empty() => (format!("\\fn{value}"), "\\fn".to_owned()),
+        's' => (
+            format!("\\fs{}", value.parse::<u32>().ok()?),
+            "\\fs".to_owned(),
+        ),
+        _ => return None,
+    };
+
+    let resets = if key.is_ascii_lowercase() {
+        resets
+    } else {
+        String::new()
+    };
+    Some((tags, resets))
+}
+
+fn text_to_ass(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    // tags that undo the lowercase codes of the previous line
+    let mut resets = String::new();
+
+    for (idx, line) in text.split('|').enumerate() {
+        if idx > 0 {
+            out.push_str("\\N");
+        }
+        let mut tags = std::mem::take(&mut resets);
+        let mut rest = line;
+        while let Some(idx) = rest.find('{') {
+            let code = rest[idx + 1..].split_once('}').and_then(|(code, tail)| {
+                let (key, value) = code.split_once(':')?;
+                let mut chars = key.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(key), None) if key.is_ascii_alphabetic() => Some((key, value, tail)),
+                    _ => None,
+                }
+            });
+            let Some((key, value, tail)) = code else {
+                break;
+            };
+
+            if !rest[..idx].is_empty() {
+                if !tags.is_empty() {
+                    out.push_str(&format!("{{{}}}", std::mem::take(&mut tags)));
+                }
+                out.push_str(&rest[..idx]);
+            }
+            if let Some((tag, reset)) = control_code(key, value) {
+                tags.push_str(&tag);
+                resets.push_str(&reset);
+            }
+            rest = tail;
+        }
+        if !tags.is_empty() {
+            out.push_str(&format!("{{{tags}}}"));
+        }
+        out.push_str(rest);
+    }
+
+    out
+}
+
+/// The control codes for a line of runs: the flags every run shares and the colour if it is
+/// the same throughout, since codes cannot change formatting partway through a line.
+fn line_codes(runs: &[(&str, &TextStyle)]) -> String {
+    let styles: Vec<&TextStyle> = runs
+        .iter()
+        .filter(|(text, _)| !text.trim().is_empty())
+        .map(|(_, style)| *style)
+        .collect();
+    let Some(first) = styles.first() else {
+        return String::new();
+    };
+
+    let mut codes = String::new();
+    let shared = |flag: fn(&TextStyle) -> bool| styles.iter().all(|s| flag(s));
+    let flags: String = [
+        ('i', shared(|s| s.italic)),
+        ('b', shared(|s| s.bold)),
+        ('u', shared(|s| s.underline)),
+        ('s', shared(|s| s.strikeout)),
+    ]
+    .iter()
+    .filter(|(_, on)| *on)
+    .map(|(c, _)| *c)
+    .collect();
+    if !flags.is_empty() {
+        write!(codes, "{{y:{flags}}}").unwrap();
+    }
+    if let Some(color) = first
+        .color
+        .filter(|c| styles.iter().all(|s| s.color == Some(*c)))
+    {
+        write!(
+            codes,
+            "{{c:${:02X}{:02X}{:02X}}}",
+            color.blue, color.green, color.red
+        )
+        .unwrap();
+    }
+    codes
+}
+
+fn render(runs: &[Run], formatting: bool) -> String {
+    let mut lines: Vec<Vec<(&str, &TextStyle)>> = vec![Vec::new()];
+    for run in runs {
+        for (idx, text) in run.text.split('\n').enumerate() {
+            if idx > 0 {
+                lines.push(Vec::new());
+            }
+            lines.last_mut().unwrap().push((text, &run.style));
+        }
+    }
+
+    lines
+        .iter()
+        .filter(|line| line.iter().any(|(text, _)| !text.trim().is_empty()))
+        .map(|line| {
+            let codes = if formatting {
+                line_codes(line)
+            } else {
+                String::new()
+            };
+            let text: String = line.iter().map(|(text, _)| *text).collect();
+            codes + text.trim()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Writes the script as MicroDVD, timed to frames of `framerate`. A constant framerate is
+/// declared on a `{1}{1}<fps>` first line. Drawings and comments are dropped and `\N` becomes
+/// `|`.
+pub fn write<W: Write>(
+    script: &Script<'_>,
+    options: &ExportOptions,
+    framerate: &Framerate,
+    out: &mut W,
+) -> fmt::Result {
+    if !framerate.is_vfr() {
+        writeln!(out, "{{1}}{{1}}{:.6}", framerate.fps())?;
+    }
+
+    let cues = cues(script, options, |_, runs| {
+        (render(runs, options.formatting), String::new())
+    });
+    for cue in cues {
+        let start = framerate.frame_at_time(cue.start, FrameTime::Start);
+        let end = framerate.frame_at_time(cue.end, FrameTime::End).max(start);
+        writeln!(out, "{{{start}}}{{{end}}}{}", cue.text.replace('\n', "|"))?;
+    }
+    Ok(())
+}
+
+pub fn to_string(script: &Script<'_>, options: &ExportOptions, framerate: &Framerate) -> String {
+    let mut out = String::new();
+    write(script, options, framerate, &mut out).expect("writing to a String cannot fail");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pal() -> Framerate {
+        Framerate::cfr(25, 1).unwrap()
+    }
+
+    #[test]
+    fn control_codes_become_overrides() {
+        let data = "{0}{25}{y:i}italic|plain\n\
+            {25}{50}{Y:b}{c:$0000FF}bold red|still bold\n\
+            {50}{75}{y:x}{c:$12}{q:1}text\n";
+        let script = parse(data, &pal()).unwrap();
+        let texts: Vec<&str> = script.events.iter().map(|e| e.text.as_ref()).collect();
+        assert_eq!(
+            texts,
+            [
+                r"{\i1}italic\N{\i0}plain",
+                r"{\b1\c&H0000FF&}bold red\N{\c}still bold",
+                "text",
+            ]
+        );
+    }
+
+    #[test]
+    fn framerate_header_is_skipped() {
+        let data = "{1}{1}23.976\n{0}{25}text\n";
+        let script = parse(data, &pal()).unwrap();
+        assert_eq!(script.events.len(), 1);
+        assert_eq!(script.events[0].text, "text");
+
+        // only as the first line
+        let data = "{0}{25}text\n{1}{1}23.976\n";
+        assert_eq!(parse(data, &pal()).unwrap().events.len(), 2);
+    }
+
+    #[test]
+    fn bad_frames_are_errors() {
+        assert!(parse("{a}{25}text\n", &pal()).is_err());
+        let (script, diagnostics) = parse_lenient("{0}25}text\n{0}{25}ok\n", &pal()).unwrap();
+        assert_eq!(script.events.len(), 1);
+        assert_eq!(diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = "{1}{1}25.000000\n{0}{25}{y:i}italic|second\n{50}{100}{c:$00FF00}green\n";
+        let script = parse(data, &pal()).unwrap();
+        // halfway between frames 49 and 50
+        assert_eq!(script.events[1].start, Some(Timestamp::from_centis(198)));
+        let written = to_string(&script, &ExportOptions::default(), &pal());
+        assert_eq!(written, data);
+    }
+}
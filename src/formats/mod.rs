@@ -0,0 +1,652 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    convert::legacy_to_numpad,
+    models::{
+        events::EventLine,
+        script_info::{PlayInfo, ScriptInfo},
+        style::Style,
+        Color,
+    },
+    tags::{ColorSlot, OverrideTag, Token},
+    Script, Span, Timestamp,
+};
+
+pub mod microdvd;
+pub mod sbv;
+pub mod srt;
+pub mod ttml;
+pub mod vtt;
+mod xml;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportOptions {
+    /// Translate italic, bold, underline, strikeout and colour overrides into the target
+    /// format's markup instead of dropping them.
+    pub formatting: bool,
+    /// Turn overlapping lines into consecutive cues that each show every active line, for
+    /// targets that can't show two cues at once. Identical text on different layers is merged.
+    pub merge_overlaps: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions {
+            formatting: true,
+            merge_overlaps: true,
+        }
+    }
+}
+
+/// Text formatting that other subtitle formats can express.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct TextStyle {
+    pub italic: bool,
+    pub bold: bool,
+    pub underline: bool,
+    pub strikeout: bool,
+    /// An override colour; `None` is the style's colour.
+    pub color: Option<Color>,
+}
+
+impl TextStyle {
+    fn of(style: Option<&Style<'_>>) -> TextStyle {
+        style.map_or_else(TextStyle::default, |style| TextStyle {
+            italic: style.italic,
+            bold: style.bold,
+            underline: style.underline.unwrap_or(false),
+            strikeout: style.strikeout.unwrap_or(false),
+            color: None,
+        })
+    }
+}
+
+/// A piece of text with uniform formatting. Line breaks are `\n`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Run {
+    pub text: String,
+    pub style: TextStyle,
+}
+
+fn find_style<'s, 'a>(styles: &'s [Style<'a>], name: &str) -> Option<&'s Style<'a>> {
+    styles
+        .iter()
+        .find(|s| s.name.eq_ignore_ascii_case(name))
+        .or_else(|| {
+            styles
+                .iter()
+                .find(|s| s.name.eq_ignore_ascii_case("Default"))
+        })
+}
+
+/// Splits the text of `event` into formatted runs. Drawings, comments and tags that have no
+/// equivalent are dropped; `\n` becomes a space and `\h` a no-break space.
+pub(crate) fn runs(event: &EventLine<'_>, styles: &[Style<'_>]) -> Vec<Run> {
+    let line_style = find_style(styles, &event.style);
+    let mut state = TextStyle::of(line_style);
+    let mut drawing = false;
+    let mut runs: Vec<Run> = Vec::new();
+
+    let mut push = |text: &str, state: &TextStyle| match runs.last_mut() {
+        Some(run) if run.style == *state => run.text.push_str(text),
+        _ => runs.push(Run {
+            text: text.to_owned(),
+            style: state.clone(),
+        }),
+    };
+
+    for token in event.tokens() {
+        match token {
+            Token::Text(text) if !drawing => push(text, &state),
+            Token::HardBreak if !drawing => push("\n", &state),
+            Token::SoftBreak if !drawing => push(" ", &state),
+            Token::HardSpace if !drawing => push("\u{a0}", &state),
+            Token::Override(tags) => {
+                for tag in tags {
+                    let base = || TextStyle::of(line_style);
+                    match tag {
+                        OverrideTag::Italic(v) => state.italic = v.unwrap_or(base().italic),
+                        OverrideTag::Bold(v) => {
+                            state.bold = v.map_or(base().bold, |v| v == 1 || v >= 700)
+                        }
+                        OverrideTag::Underline(v) => {
+                            state.underline = v.unwrap_or(base().underline)
+                        }
+                        OverrideTag::StrikeOut(v) => {
+                            state.strikeout = v.unwrap_or(base().strikeout)
+                        }
+                        OverrideTag::Color(ColorSlot::Primary, color) => state.color = color,
+                        OverrideTag::Reset(None) => state = base(),
+                        OverrideTag::Reset(Some(name)) => {
+                            state = TextStyle::of(find_style(styles, name))
+                        }
+                        OverrideTag::Drawing(level) => drawing = level > 0,
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    runs
+}
+
+/// Where a line is shown, in script pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Placement {
+    /// Numpad alignment.
+    pub alignment: u8,
+    pub position: Option<(f64, f64)>,
+    /// Left, right and vertical margin.
+    pub margins: (f64, f64, f64),
+}
+
+impl Placement {
+    /// The first `\an`/`\a` and `\pos` of the line, falling back to the style's alignment, and
+    /// the line's margins where they are set.
+    pub fn of(event: &EventLine<'_>, style: Option<&Style<'_>>) -> Placement {
+        let mut alignment = None;
+        let mut position = None;
+        for token in event.tokens() {
+            let Token::Override(tags) = token else {
+                continue;
+            };
+            for tag in tags {
+                match tag {
+                    OverrideTag::Alignment(Some(an)) if alignment.is_none() => alignment = Some(an),
+                    OverrideTag::LegacyAlignment(Some(a)) if alignment.is_none() => {
+                        alignment = Some(legacy_to_numpad(a))
+                    }
+                    OverrideTag::Position { x, y } if position.is_none() => position = Some((x, y)),
+                    _ => {}
+                }
+            }
+        }
+
+        let alignment = alignment
+            .or_else(|| style.and_then(|s| u8::try_from(s.alignment).ok()))
+            .filter(|an| (1..=9).contains(an))
+            .unwrap_or(2);
+        let margin = |line: i64, style: Option<i64>| {
+            (if line != 0 { line } else { style.unwrap_or(0) }) as f64
+        };
+        Placement {
+            alignment,
+            position,
+            margins: (
+                margin(event.margin_left, style.map(|s| s.margin_left)),
+                margin(event.margin_right, style.map(|s| s.margin_right)),
+                margin(event.margin_vertical, style.map(|s| s.margin_vertical)),
+            ),
+        }
+    }
+
+    /// 0 for left, 1 for centre, 2 for right.
+    pub fn column(&self) -> usize {
+        usize::from(self.alignment - 1) % 3
+    }
+
+    /// 0 for bottom, 1 for middle, 2 for top.
+    pub fn row(&self) -> usize {
+        usize::from(self.alignment - 1) / 3
+    }
+}
+
+/// The script's PlayRes as renderers read it: 384x288 when it is missing, and a missing side
+/// worked out from the other one for a 4:3 picture, or 5:4 for 1280x1024.
+pub(crate) fn play_res(script: &Script<'_>) -> (f64, f64) {
+    let info = &script.info.play_info;
+    let (width, height) = match (
+        info.play_res_x.filter(|x| *x > 0),
+        info.play_res_y.filter(|y| *y > 0),
+    ) {
+        (Some(width), Some(height)) => (width, height),
+        (Some(width), None) => (width, if width == 1280 { 1024 } else { width * 3 / 4 }),
+        (None, Some(height)) => (if height == 1024 { 1280 } else { height * 4 / 3 }, height),
+        (None, None) => (384, 288),
+    };
+    (width as f64, height as f64)
+}
+
+/// An identifier for a style name that is valid in CSS and XML: other characters become `_`
+/// and a leading digit gets an `s` in front.
+pub(crate) fn identifier(name: &str) -> String {
+    let mut identifier: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if !identifier.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        identifier.insert(0, 's');
+    }
+    identifier
+}
+
+/// An [`identifier`] for each style name, made unique with a `_2`, `_3`, … suffix where two
+/// names map to the same one. A name used by more than one style gets the identifier of the
+/// first.
+pub(crate) fn style_identifiers<'s>(styles: &'s [Style<'_>]) -> BTreeMap<&'s str, String> {
+    let mut identifiers: BTreeMap<&str, String> = BTreeMap::new();
+    for style in styles {
+        if identifiers.contains_key(style.name.as_ref()) {
+            continue;
+        }
+        let base = identifier(&style.name);
+        let mut candidate = base.clone();
+        let mut n = 1;
+        while identifiers.values().any(|id| *id == candidate) {
+            n += 1;
+            candidate = format!("{base}_{n}");
+        }
+        identifiers.insert(&style.name, candidate);
+    }
+    identifiers
+}
+
+/// A timed piece of text ready to be written in another format.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Cue {
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub text: String,
+    /// Placement in the target format's syntax, if it has any.
+    pub settings: String,
+}
+
+/// The cues to export from `script`, in order of start time. Comments and lines without
+/// visible text are left out. `render` turns a line and its runs into the target format's text
+/// and placement settings.
+pub(crate) fn cues(
+    script: &Script<'_>,
+    options: &ExportOptions,
+    render: impl Fn(&EventLine<'_>, &[Run]) -> (String, String),
+) -> Vec<Cue> {
+    let mut cues: Vec<Cue> = script
+        .events
+        .iter()
+        .filter(|event| !event.is_comment)
+        .filter_map(|event| {
+            let start = event.start.unwrap_or_default().max(Timestamp::ZERO);
+            let end = event.end.unwrap_or_default();
+            let (text, settings) = render(event, &runs(event, &script.styles));
+            let text = text.trim_matches(['\n', ' ']);
+            (end > start && !text.is_empty()).then(|| Cue {
+                start,
+                end,
+                text: text.to_owned(),
+                settings,
+            })
+        })
+        .collect();
+
+    if options.merge_overlaps {
+        cues = merge_overlaps(&cues);
+    } else {
+        cues.sort_by_key(|cue| cue.start);
+    }
+    cues
+}
+
+/// Splits overlapping cues at every start and end time. Cues active over the same stretch with
+/// the same settings are joined into one.
+fn merge_overlaps(cues: &[Cue]) -> Vec<Cue> {
+    let mut bounds: Vec<Timestamp> = cues.iter().flat_map(|c| [c.start, c.end]).collect();
+    bounds.sort_unstable();
+    bounds.dedup();
+
+    let mut merged: Vec<Cue> = Vec::new();
+    // cues in `merged` that end where the current stretch starts
+    let mut open: Vec<usize> = Vec::new();
+    for window in bounds.windows(2) {
+        let (start, end) = (window[0], window[1]);
+        let mut groups: Vec<(&str, Vec<&str>)> = Vec::new();
+        for cue in cues.iter().filter(|c| c.start <= start && c.end >= end) {
+            let idx = match groups.iter().position(|(s, _)| *s == cue.settings) {
+                Some(idx) => idx,
+                None => {
+                    groups.push((&cue.settings, Vec::new()));
+                    groups.len() - 1
+                }
+            };
+            if !groups[idx].1.contains(&cue.text.as_str()) {
+                groups[idx].1.push(&cue.text);
+            }
+        }
+
+        let mut next_open = Vec::with_capacity(groups.len());
+        for (settings, texts) in groups {
+            let text = texts.join("\n");
+            let continued = open
+                .iter()
+                .copied()
+                .find(|idx| merged[*idx].settings == settings && merged[*idx].text == text);
+            match continued {
+                Some(idx) => {
+                    merged[idx].end = end;
+                    next_open.push(idx);
+                }
+                None => {
+                    next_open.push(merged.len());
+                    merged.push(Cue {
+                        start,
+                        end,
+                        text,
+                        settings: settings.to_owned(),
+                    });
+                }
+            }
+        }
+        open = next_open;
+    }
+    merged
+}
+
+/// A script holding only the classic `Default` style, for formats that have no styles.
+pub(crate) fn new_script() -> Script<'static> {
+    Script {
+        info: ScriptInfo {
+            script_type: Some("v4.00+".into()),
+            play_info: PlayInfo {
+                play_res_x: Some(384),
+                play_res_y: Some(288),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        styles: vec![Style::named_default()],
+        ..Default::default()
+    }
+}
+
+pub(crate) fn new_event(start: Timestamp, end: Timestamp, text: String) -> EventLine<'static> {
+    EventLine {
+        layer: Some(0),
+        start: Some(start),
+        end: Some(end),
+        style: "Default".into(),
+        text: text.into(),
+        ..Default::default()
+    }
+}
+
+/// Lines of `data` with their spans, without line endings or a leading byte order mark.
+pub(crate) fn source_lines(data: &str) -> Vec<(&str, Span)> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    for (idx, raw) in data.split_inclusive('\n').enumerate() {
+        let mut span = Span::start_of_line(offset, idx + 1);
+        offset += raw.len();
+        let mut line = raw.strip_suffix('\n').unwrap_or(raw);
+        line = line.strip_suffix('\r').unwrap_or(line);
+        if idx == 0 {
+            if let Some(rest) = line.strip_prefix('\u{feff}') {
+                span = span.of_subslice(line, rest);
+                line = rest;
+            }
+        }
+        lines.push((line, span));
+    }
+    lines
+}
+
+/// `H:MM:SS` and one to three digits of milliseconds after any of `separators`, like
+/// `1:02:03.5`. With `optional_hours`, `MM:SS.mmm` is read too.
+pub(crate) fn parse_clock_time(
+    s: &str,
+    separators: &[char],
+    optional_hours: bool,
+) -> Option<Timestamp> {
+    let (hms, millis) = s.split_once(separators)?;
+    let parts: Vec<&str> = hms.split(':').collect();
+    let number = |v: &str| {
+        (!v.is_empty() && v.bytes().all(|b| b.is_ascii_digit()))
+            .then(|| v.parse::<i64>().ok())
+            .flatten()
+    };
+    let (hours, minutes, seconds) = match parts[..] {
+        [m, s] if optional_hours => (0, number(m)?, number(s)?),
+        [h, m, s] => (number(h)?, number(m)?, number(s)?),
+        _ => return None,
+    };
+    if minutes >= 60 || seconds >= 60 || millis.len() > 3 {
+        return None;
+    }
+    let millis = number(millis)? * 10i64.pow(3 - millis.len() as u32);
+
+    let total = hours
+        .checked_mul(3_600_000)?
+        .checked_add(minutes * 60_000 + seconds * 1_000 + millis)?;
+    Some(Timestamp::from_millis(total))
+}
+
+/// Writes `time` as `HH:MM:SS`, `separator` and three digits of milliseconds. Hours are padded
+/// to `hour_digits`.
+pub(crate) fn write_clock_time<W: Write>(
+    out: &mut W,
+    time: Timestamp,
+    separator: char,
+    hour_digits: usize,
+) -> fmt::Result {
+    let ms = time.millis().max(0);
+    write!(
+        out,
+        "{:0hour_digits$}:{:02}:{:02}{separator}{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1_000 % 60,
+        ms % 1_000
+    )
+}
+
+const HTML_COLORS: [(&str, Color); 16] = [
+    ("black", Color::rgb(0, 0, 0)),
+    ("silver", Color::rgb(192, 192, 192)),
+    ("gray", Color::rgb(128, 128, 128)),
+    ("white", Color::rgb(255, 255, 255)),
+    ("maroon", Color::rgb(128, 0, 0)),
+    ("red", Color::rgb(255, 0, 0)),
+    ("purple", Color::rgb(128, 0, 128)),
+    ("fuchsia", Color::rgb(255, 0, 255)),
+    ("green", Color::rgb(0, 128, 0)),
+    ("lime", Color::rgb(0, 255, 0)),
+    ("olive", Color::rgb(128, 128, 0)),
+    ("yellow", Color::rgb(255, 255, 0)),
+    ("navy", Color::rgb(0, 0, 128)),
+    ("blue", Color::rgb(0, 0, 255)),
+    ("teal", Color::rgb(0, 128, 128)),
+    ("aqua", Color::rgb(0, 255, 255)),
+];
+
+/// `#RRGGBB`, `#RGB`, a bare `RRGGBB` or one of the sixteen basic HTML colour names.
+pub(crate) fn html_color(value: &str) -> Option<Color> {
+    let value = value.trim();
+    if let Some((_, color)) = HTML_COLORS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(value))
+    {
+        return Some(*color);
+    }
+
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let digit = |idx: usize| u8::from_str_radix(&hex[idx..idx + 1], 16).ok();
+    match hex.len() {
+        6 => {
+            let v = u32::from_str_radix(hex, 16).ok()?;
+            Some(Color::rgb((v >> 16) as u8, (v >> 8) as u8, v as u8))
+        }
+        3 => Some(Color::rgb(digit(0)? * 17, digit(1)? * 17, digit(2)? * 17)),
+        _ => None,
+    }
+}
+
+pub(crate) fn write_html_color(color: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.red, color.green, color.blue)
+}
+
+/// Attribute values of an HTML-like tag such as `font color="red" face=Arial`.
+pub(crate) fn html_attributes(tag: &str) -> Vec<(String, &str)> {
+    let mut attributes = Vec::new();
+    let mut rest = tag.split_once(char::is_whitespace).map_or("", |(_, r)| r);
+    while let Some((key, after)) = rest.split_once('=') {
+        let key = key.trim().to_ascii_lowercase();
+        let after = after.trim_start();
+        let (value, remaining) = match after.chars().next() {
+            Some(quote @ ('"' | '\'')) => after[1..].split_once(quote).unwrap_or((&after[1..], "")),
+            _ => after.split_once(char::is_whitespace).unwrap_or((after, "")),
+        };
+        attributes.push((key, value));
+        rest = remaining;
+    }
+    attributes
+}
+
+pub(crate) fn decode_entity(entity: &str) -> Option<&'static str> {
+    Some(match entity {
+        "amp" => "&",
+        "lt" => "<",
+        "gt" => ">",
+        "quot" => "\"",
+        "apos" => "'",
+        "nbsp" => "\\h",
+        "lrm" => "\u{200e}",
+        "rlm" => "\u{200f}",
+        _ => return None,
+    })
+}
+
+/// Tags that subtitle markup uses, besides those [`html_to_ass`] converts.
+const IGNORED_TAGS: [&str; 8] = ["c", "v", "lang", "ruby", "rt", "span", "p", "br"];
+
+/// Whether the text between `<` and `>` is a markup tag rather than text that happens to be
+/// between the two: a closing tag, a known tag name or a WebVTT timestamp.
+fn is_markup_tag(tag: &str) -> bool {
+    if tag.starts_with('/') {
+        return true;
+    }
+    let name = tag
+        .split(|c: char| c.is_whitespace() || c == '.' || c == '/')
+        .next()
+        .unwrap_or_default();
+    ["i", "b", "u", "s", "font"]
+        .iter()
+        .chain(&IGNORED_TAGS)
+        .any(|known| name.eq_ignore_ascii_case(known))
+        || parse_clock_time(name, &['.'], true).is_some()
+}
+
+/// Converts HTML-style markup (`<i>`, `<b>`, `<u>`, `<s>`, `<font color face>`) and line
+/// breaks into ASS override tags. Other known tags are dropped and a `<` that does not start a
+/// tag is kept as text; existing `{...}` blocks are kept.
+pub(crate) fn html_to_ass(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut fonts: Vec<(Option<Color>, Option<String>)> = Vec::new();
+    let mut rest = text;
+
+    while let Some(idx) = rest.find(['<', '\n', '&']) {
+        out.push_str(&rest[..idx]);
+        let after = &rest[idx..];
+
+        if let Some(tail) = after.strip_prefix('\n') {
+            out.push_str("\\N");
+            rest = tail;
+            continue;
+        }
+
+        if let Some(entity) = after.strip_prefix('&') {
+            let decoded = entity
+                .split_once(';')
+                .and_then(|(name, tail)| Some((decode_entity(name)?, tail)));
+            match decoded {
+                Some((text, tail)) => {
+                    out.push_str(text);
+                    rest = tail;
+                }
+                None => {
+                    out.push('&');
+                    rest = entity;
+                }
+            }
+            continue;
+        }
+
+        let Some((tag, tail)) = after[1..]
+            .split_once('>')
+            .filter(|(tag, _)| is_markup_tag(tag))
+        else {
+            // a `<` in text, like `1 < 2`
+            out.push('<');
+            rest = &after[1..];
+            continue;
+        };
+        rest = tail;
+
+        let tag = tag.trim();
+        let (closing, tag) = match tag.strip_prefix('/') {
+            Some(tag) => (true, tag.trim_start()),
+            None => (false, tag),
+        };
+        let name = tag
+            .split(|c: char| c.is_whitespace() || c == '.')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let flag = if closing { '0' } else { '1' };
+
+        match name.as_str() {
+            "i" | "b" | "u" | "s" => out.push_str(&format!("{{\\{name}{flag}}}")),
+            "font" if !closing => {
+                let mut color = None;
+                let mut face = None;
+                for (key, value) in html_attributes(tag) {
+                    match key.as_str() {
+                        "color" => color = html_color(value),
+                        "face" => face = Some(value.to_owned()),
+                        _ => {}
+                    }
+                }
+                if let Some(color) = color {
+                    out.push_str(&format!("{{\\c{color}&}}"));
+                }
+                if let Some(face) = &face {
+                    out.push_str(&format!("{{\\fn{face}}}"));
+                }
+                fonts.push((color, face));
+            }
+            "font" => {
+                let Some((color, face)) = fonts.pop() else {
+                    continue;
+                };
+                if color.is_some() {
+                    match fonts.iter().rev().find_map(|f| f.0) {
+                        Some(previous) => out.push_str(&format!("{{\\c{previous}&}}")),
+                        None => out.push_str("{\\c}"),
+                    }
+                }
+                if face.is_some() {
+                    match fonts.iter().rev().find_map(|f| f.1.as_deref()) {
+                        Some(previous) => out.push_str(&format!("{{\\fn{previous}}}")),
+                        None => out.push_str("{\\fn}"),
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
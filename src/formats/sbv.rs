@@ -0,0 +1,142 @@
+use std::fmt::{self, Write};
+
+use crate::{
+    diagnostics::{Diagnostic, Diagnostics, ParseMode, Severity},
+    ParseError, Script, Span, Timestamp,
+};
+
+use super::{
+    cues, new_event, new_script, parse_clock_time, source_lines, write_clock_time, ExportOptions,
+};
+
+pub fn parse(data: &str) -> Result<Script<'static>, ParseError> {
+    parse_with(data, &mut Diagnostics::new(ParseMode::Strict))
+}
+
+pub fn parse_lenient(data: &str) -> Result<(Script<'static>, Vec<Diagnostic>), ParseError> {
+    let mut diagnostics = Diagnostics::new(ParseMode::Lenient);
+    let script = parse_with(data, &mut diagnostics)?;
+    Ok((script, diagnostics.take()))
+}
+
+/// Reads YouTube SubViewer cues into a script with a single `Default` style. Each cue is a
+/// `start,end` line followed by its text; `[br]` is a line break.
+pub fn parse_with(
+    data: &str,
+    diagnostics: &mut Diagnostics,
+) -> Result<Script<'static>, ParseError> {
+    let lines = source_lines(data);
+    let mut script = new_script();
+    let blank = |idx: usize| lines[idx].0.trim().is_empty();
+
+    let mut idx = 0;
+    while idx < lines.len() {
+        if blank(idx) {
+            idx += 1;
+            continue;
+        }
+
+        let (line, span) = lines[idx];
+        idx += 1;
+        let timing = parse_timing(line, span);
+        let mut text = Vec::new();
+        while idx < lines.len() && !blank(idx) {
+            text.push(lines[idx].0.replace("[br]", "\\N"));
+            idx += 1;
+        }
+
+        match timing {
+            Ok((start, end)) => script.events.push(new_event(start, end, text.join("\\N"))),
+            Err(error) => diagnostics.report(Severity::Error, error)?,
+        }
+    }
+
+    Ok(script)
+}
+
+fn parse_timing(line: &str, span: Span) -> Result<(Timestamp, Timestamp), ParseError> {
+    let Some((start, end)) = line.split_once(',') else {
+        return Err(ParseError::InvalidValue {
+            field: "timing",
+            value: line.to_owned(),
+            span,
+        });
+    };
+
+    let time = |field: &'static str, value: &str| {
+        let value = value.trim();
+        parse_clock_time(value, &['.'], false).ok_or_else(|| ParseError::InvalidTimestamp {
+            field,
+            value: value.to_owned(),
+            span: if value.is_empty() {
+                span.advance(line)
+            } else {
+                span.of_subslice(line, value)
+            },
+        })
+    };
+    Ok((time("Start", start)?, time("End", end)?))
+}
+
+/// Writes the script as SBV. The format has no markup, so formatting is always dropped, along
+/// with drawings and comments; `\N` becomes a line break.
+pub fn write<W: Write>(script: &Script<'_>, options: &ExportOptions, out: &mut W) -> fmt::Result {
+    let cues = cues(script, options, |_, runs| {
+        let text: String = runs.iter().map(|run| run.text.as_str()).collect();
+        // a blank line would end the cue
+        let text = text
+            .split('\n')
+            .filter(|line| !line.trim().is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
+        (text, String::new())
+    });
+    for (idx, cue) in cues.iter().enumerate() {
+        if idx > 0 {
+            out.write_char('\n')?;
+        }
+        write_clock_time(out, cue.start, '.', 1)?;
+        out.write_char(',')?;
+        write_clock_time(out, cue.end, '.', 1)?;
+        writeln!(out, "\n{}", cue.text)?;
+    }
+    Ok(())
+}
+
+pub fn to_string(script: &Script<'_>, options: &ExportOptions) -> String {
+    let mut out = String::new();
+    write(script, options, &mut out).expect("writing to a String cannot fail");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cues_and_line_breaks() {
+        let data = "0:00:01.000,0:00:02.500\nfirst[br]line\nsecond\n\n\
+            0:01:00.5,0:01:01.25\nlater\n";
+        let script = parse(data).unwrap();
+        let texts: Vec<&str> = script.events.iter().map(|e| e.text.as_ref()).collect();
+        assert_eq!(texts, [r"first\Nline\Nsecond", "later"]);
+        assert_eq!(script.events[0].end, Some(Timestamp::from_centis(250)));
+        assert_eq!(script.events[1].start, Some(Timestamp::from_centis(6_050)));
+        assert_eq!(script.events[1].end, Some(Timestamp::from_centis(6_125)));
+    }
+
+    #[test]
+    fn bad_timings_are_errors() {
+        assert!(parse("0:00:01.000 0:00:02.000\ntext\n").is_err());
+        let (script, diagnostics) = parse_lenient("0:00:01.000,x\ntext\n").unwrap();
+        assert!(script.events.is_empty());
+        assert_eq!(diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = "0:00:01.000,0:00:02.500\nfirst\nsecond\n\n1:02:03.450,1:02:04.000\nlater\n";
+        let script = parse(data).unwrap();
+        assert_eq!(to_string(&script, &ExportOptions::default()), data);
+    }
+}
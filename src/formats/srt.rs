@@ -0,0 +1,234 @@
+use std::fmt::{self, Write};
+
+use crate::{
+    diagnostics::{Diagnostic, Diagnostics, ParseMode, Severity},
+    ParseError, Script, Span, Timestamp,
+};
+
+use super::{
+    cues, html_to_ass, new_event, new_script, parse_clock_time, source_lines, write_clock_time,
+    write_html_color, ExportOptions, Run,
+};
+
+pub fn parse(data: &str) -> Result<Script<'static>, ParseError> {
+    parse_with(data, &mut Diagnostics::new(ParseMode::Strict))
+}
+
+pub fn parse_lenient(data: &str) -> Result<(Script<'static>, Vec<Diagnostic>), ParseError> {
+    let mut diagnostics = Diagnostics::new(ParseMode::Lenient);
+    let script = parse_with(data, &mut diagnostics)?;
+    Ok((script, diagnostics.take()))
+}
+
+/// Reads SubRip cues into a script with a single `Default` style. `<i>`, `<b>`, `<u>`, `<s>` and
+/// `<font color face>` become override tags.
+pub fn parse_with(
+    data: &str,
+    diagnostics: &mut Diagnostics,
+) -> Result<Script<'static>, ParseError> {
+    let lines = source_lines(data);
+    let mut script = new_script();
+    let blank = |idx: usize| lines[idx].0.trim().is_empty();
+
+    let mut idx = 0;
+    while idx < lines.len() {
+        if blank(idx) {
+            idx += 1;
+            continue;
+        }
+
+        let timing = if is_index(&lines, idx) { idx + 1 } else { idx };
+        let (line, span) = lines[timing];
+        idx = timing + 1;
+
+        match parse_timing(line, span) {
+            Ok((start, end)) => {
+                let mut text = Vec::new();
+                while idx < lines.len() && !blank(idx) && !is_cue_start(&lines, idx) {
+                    text.push(lines[idx].0);
+                    idx += 1;
+                }
+                script
+                    .events
+                    .push(new_event(start, end, html_to_ass(&text.join("\n"))));
+            }
+            Err(error) => {
+                diagnostics.report(Severity::Error, error)?;
+                while idx < lines.len() && !blank(idx) {
+                    idx += 1;
+                }
+            }
+        }
+    }
+
+    Ok(script)
+}
+
+/// A cue number directly followed by a timing line.
+fn is_index(lines: &[(&str, Span)], idx: usize) -> bool {
+    let line = lines[idx].0.trim();
+    !line.is_empty()
+        && line.bytes().all(|b| b.is_ascii_digit())
+        && lines.get(idx + 1).is_some_and(|(l, _)| l.contains("-->"))
+}
+
+fn is_cue_start(lines: &[(&str, Span)], idx: usize) -> bool {
+    let (line, span) = lines[idx];
+    is_index(lines, idx) || parse_timing(line, span).is_ok()
+}
+
+fn parse_timing(line: &str, span: Span) -> Result<(Timestamp, Timestamp), ParseError> {
+    let Some((start, rest)) = line.split_once("-->") else {
+        return Err(ParseError::InvalidValue {
+            field: "timing",
+            value: line.to_owned(),
+            span,
+        });
+    };
+    // anything after the end time is position information, which is ignored
+    let end = rest.split_whitespace().next().unwrap_or_default();
+    let start = start.trim();
+
+    let time = |field: &'static str, value: &str| {
+        parse_clock_time(value, &[',', '.'], false).ok_or_else(|| ParseError::InvalidTimestamp {
+            field,
+            value: value.to_owned(),
+            span: if value.is_empty() {
+                span.advance(line)
+            } else {
+                span.of_subslice(line, value)
+            },
+        })
+    };
+    Ok((time("Start", start)?, time("End", end)?))
+}
+
+fn render(runs: &[Run], formatting: bool) -> String {
+    let mut out = String::new();
+    for run in runs {
+        if !formatting {
+            out.push_str(&run.text);
+            continue;
+        }
+
+        let style = &run.style;
+        let tags = [
+            (style.bold, "b"),
+            (style.italic, "i"),
+            (style.underline, "u"),
+            (style.strikeout, "s"),
+        ];
+        for (_, tag) in tags.iter().filter(|(on, _)| *on) {
+            write!(out, "<{tag}>").unwrap();
+        }
+        if let Some(color) = style.color {
+            write!(out, "<font color=\"{}\">", write_html_color(color)).unwrap();
+        }
+        out.push_str(&run.text);
+        if style.color.is_some() {
+            out.push_str("</font>");
+        }
+        for (_, tag) in tags.iter().rev().filter(|(on, _)| *on) {
+            write!(out, "</{tag}>").unwrap();
+        }
+    }
+
+    // a blank line would end the cue
+    out.split('\n')
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Writes the script as SubRip. Drawings and comments are dropped and `\N` becomes a line break.
+pub fn write<W: Write>(script: &Script<'_>, options: &ExportOptions, out: &mut W) -> fmt::Result {
+    let cues = cues(script, options, |_, runs| {
+        (render(runs, options.formatting), String::new())
+    });
+    for (idx, cue) in cues.iter().enumerate() {
+        if idx > 0 {
+            out.write_char('\n')?;
+        }
+        writeln!(out, "{}", idx + 1)?;
+        write_clock_time(out, cue.start, ',', 2)?;
+        out.write_str(" --> ")?;
+        write_clock_time(out, cue.end, ',', 2)?;
+        writeln!(out, "\n{}", cue.text)?;
+    }
+    Ok(())
+}
+
+pub fn to_string(script: &Script<'_>, options: &ExportOptions) -> String {
+    let mut out = String::new();
+    write(script, options, &mut out).expect("writing to a String cannot fail");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(data: &str) -> Vec<String> {
+        parse(data)
+            .unwrap()
+            .events
+            .iter()
+            .map(|e| e.text.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn html_tags_become_overrides() {
+        let data = "1\n00:00:01,000 --> 00:00:02,000\n\
+            <i>italic</i> <B>bold</B> <u>u</u> <s>s</s>\n\
+            <font color=\"#ff0000\" face=\"Arial\">red</font> 1 < 2 &amp; <unknown>\n";
+        assert_eq!(
+            texts(data),
+            [concat!(
+                r"{\i1}italic{\i0} {\b1}bold{\b0} {\u1}u{\u0} {\s1}s{\s0}\N",
+                r"{\c&H0000FF&}{\fnArial}red{\c}{\fn} 1 < 2 & <unknown>",
+            )]
+        );
+    }
+
+    #[test]
+    fn braces_in_text_are_kept_as_override_blocks() {
+        let data = "1\n00:00:01,000 --> 00:00:02,000\n{\\an8}top {note}\n";
+        let script = parse(data).unwrap();
+        assert_eq!(script.events[0].text, r"{\an8}top {note}");
+        assert_eq!(
+            to_string(&script, &ExportOptions::default()).lines().nth(2),
+            Some("top")
+        );
+    }
+
+    #[test]
+    fn crlf_input() {
+        let data = "\u{feff}1\r\n00:00:01,000 --> 00:00:02,500\r\nfirst\r\nline\r\n\r\n\
+            2\r\n00:00:03.000 --> 00:00:04.000 X1:0 X2:0\r\nsecond\r\n";
+        let script = parse(data).unwrap();
+        assert_eq!(texts(data), [r"first\Nline", "second"]);
+        assert_eq!(script.events[0].start, Some(Timestamp::from_centis(100)));
+        assert_eq!(script.events[0].end, Some(Timestamp::from_centis(250)));
+        assert_eq!(script.events[1].start, Some(Timestamp::from_centis(300)));
+    }
+
+    #[test]
+    fn bad_timings_are_errors() {
+        let data = "1\n00:00:01,000 -> 00:00:02,000\ntext\n";
+        assert!(parse(data).is_err());
+        let (script, diagnostics) = parse_lenient(data).unwrap();
+        assert!(script.events.is_empty());
+        assert_eq!(diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = "1\n00:00:01,000 --> 00:00:02,000\n<i>first</i> line\nsecond line\n\n\
+            2\n00:00:03,500 --> 00:00:04,250\n<b>bold</b> <font color=\"#00ff00\">green</font>\n";
+        let script = parse(data).unwrap();
+        let written = to_string(&script, &ExportOptions::default());
+        assert_eq!(written, data);
+        assert_eq!(parse(&written).unwrap().events.len(), 2);
+    }
+}
@@ -0,0 +1,1153 @@
+use std::fmt::{self, Write};
+
+use crate::{
+    diagnostics::{Diagnostic, Diagnostics, ParseMode, Severity},
+    models::{events::EventLine, style::Style, Color},
+    tags::{ColorSlot, Num, OverrideTag, Token},
+    timing::{FrameTime, Framerate},
+    writer::WriteAss,
+    ParseError, Script, Timestamp,
+};
+
+use super::{
+    cues, find_style, html_color, new_event, new_script, play_res, style_identifiers,
+    write_clock_time, write_html_color,
+    xml::{self, Element, Node, XML_NAMESPACE},
+    ExportOptions, Placement, Run, TextStyle,
+};
+
+const TT: &str = "http://www.w3.org/ns/ttml";
+const TTS: &str = "http://www.w3.org/ns/ttml#styling";
+const TTP: &str = "http://www.w3.org/ns/ttml#parameter";
+const IMSC1_TEXT: &str = "http://www.w3.org/ns/ttml/profile/imsc1/text";
+
+/// Style and region ids get different prefixes so a style can't take a region's id. Reading
+/// strips the style prefix again to get the style name.
+const STYLE_ID_PREFIX: &str = "s_";
+const REGION_ID_PREFIX: &str = "r";
+
+fn style_name(id: &str) -> &str {
+    id.strip_prefix(STYLE_ID_PREFIX).unwrap_or(id)
+}
+
+/// How `begin` and `end` are written.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum TimeExpression {
+    /// Media time as `HH:MM:SS.mmm`.
+    #[default]
+    Media,
+    /// Non-drop SMPTE frames as `HH:MM:SS:FF`, counted at the framerate rounded up to whole
+    /// frames, with `ttp:frameRateMultiplier` giving the real rate.
+    Frames(Framerate),
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Whole frames per second, rounded up, and the reduced multiplier that gives the real rate.
+fn frame_rate(framerate: &Framerate) -> (i64, (i64, i64)) {
+    let (numerator, denominator) = framerate.ratio();
+    let rate = (numerator + denominator - 1) / denominator;
+    let divisor = gcd(numerator, rate * denominator);
+    (rate, (numerator / divisor, rate * denominator / divisor))
+}
+
+fn write_time<W: Write>(
+    out: &mut W,
+    time: Timestamp,
+    end: bool,
+    expression: &TimeExpression,
+) -> fmt::Result {
+    match expression {
+        TimeExpression::Media => write_clock_time(out, time, '.', 2),
+        TimeExpression::Frames(framerate) => {
+            // `end` is the first frame the line is no longer shown on
+            let frame = if end {
+                framerate.frame_at_time(time, FrameTime::End) + 1
+            } else {
+                framerate.frame_at_time(time, FrameTime::Start)
+            }
+            .max(0);
+            let (rate, _) = frame_rate(framerate);
+            let seconds = frame / rate;
+            write!(
+                out,
+                "{:02}:{:02}:{:02}:{:02}",
+                seconds / 3600,
+                seconds / 60 % 60,
+                seconds % 60,
+                frame % rate
+            )
+        }
+    }
+}
+
+/// `#rrggbbaa`. TTML alpha is opacity, the inverse of ASS alpha.
+fn write_color(color: Color) -> String {
+    format!(
+        "{}{:02x}",
+        write_html_color(color),
+        255 - color.alpha.unwrap_or(0)
+    )
+}
+
+fn parse_color(value: &str) -> Option<Color> {
+    let value = value.trim();
+    let with_opacity = |color: Color, opacity: u8| Color {
+        alpha: (opacity != 255).then_some(255 - opacity),
+        ..color
+    };
+
+    if let Some(hex) = value.strip_prefix('#').filter(|hex| hex.len() == 8) {
+        let opacity = u8::from_str_radix(&hex[6..], 16).ok()?;
+        return Some(with_opacity(html_color(&hex[..6])?, opacity));
+    }
+    let function = value
+        .strip_prefix("rgba(")
+        .or_else(|| value.strip_prefix("rgb("))
+        .and_then(|args| args.strip_suffix(')'));
+    if let Some(args) = function {
+        let values: Vec<u8> = args
+            .split(',')
+            .map(|v| v.trim().parse().ok())
+            .collect::<Option<_>>()?;
+        return match values[..] {
+            [r, g, b] => Some(Color::rgb(r, g, b)),
+            [r, g, b, a] => Some(with_opacity(Color::rgb(r, g, b), a)),
+            _ => None,
+        };
+    }
+    match value.to_ascii_lowercase().as_str() {
+        "transparent" => Some(with_opacity(Color::rgb(0, 0, 0), 0)),
+        "cyan" => html_color("aqua"),
+        "magenta" => html_color("fuchsia"),
+        "silver" => Some(Color::rgb(192, 192, 192)),
+        "gray" => Some(Color::rgb(128, 128, 128)),
+        _ => html_color(value),
+    }
+}
+
+fn style_attributes(style: &Style<'_>) -> String {
+    let mut out = String::new();
+    let family = style.font_name.trim();
+    let family = if family.contains([' ', ',']) {
+        format!("\"{}\"", family.replace('"', ""))
+    } else {
+        family.to_owned()
+    };
+    write!(
+        out,
+        " tts:fontFamily=\"{}\" tts:fontSize=\"{}px\" tts:color=\"{}\"",
+        xml::escape(&family),
+        style.font_size,
+        write_color(style.primary_color)
+    )
+    .unwrap();
+
+    if style.bold {
+        out.push_str(" tts:fontWeight=\"bold\"");
+    }
+    if style.italic {
+        out.push_str(" tts:fontStyle=\"italic\"");
+    }
+    match (style.underline, style.strikeout) {
+        (Some(true), Some(true)) => out.push_str(" tts:textDecoration=\"underline lineThrough\""),
+        (Some(true), _) => out.push_str(" tts:textDecoration=\"underline\""),
+        (_, Some(true)) => out.push_str(" tts:textDecoration=\"lineThrough\""),
+        _ => {}
+    }
+
+    // border style 3 draws an opaque box in the outline colour instead of an outline
+    let outline = style.outline_color.unwrap_or(Color::rgb(0, 0, 0));
+    if style.border_style == 3 {
+        write!(out, " tts:backgroundColor=\"{}\"", write_color(outline)).unwrap();
+    } else if style.outline > 0.0 {
+        write!(
+            out,
+            " tts:textOutline=\"{} {}px\"",
+            write_color(outline),
+            style.outline
+        )
+        .unwrap();
+    }
+    out
+}
+
+/// Region attributes for the area the line is placed in, as percentages of the PlayRes. Lines
+/// placed by margins get the area inside them; a `\pos` gets the largest area with the
+/// position on the alignment's anchor.
+fn region_attributes(placement: &Placement, res: (f64, f64)) -> String {
+    let percent = |v: f64, of: f64| (v / of * 100.0).clamp(0.0, 100.0);
+    let (column, row) = (placement.column(), placement.row());
+
+    let ((x, width), (y, height)) = match placement.position {
+        Some((x, y)) => {
+            let (x, y) = (percent(x, res.0), percent(y, res.1));
+            let span = |v: f64, anchor: usize| match anchor {
+                0 => (v, 100.0 - v),
+                1 => {
+                    let half = v.min(100.0 - v);
+                    (v - half, half * 2.0)
+                }
+                _ => (0.0, v),
+            };
+            // rows count from the bottom, so flip them to anchor from the top
+            (span(x, column), span(y, 2 - row))
+        }
+        None => {
+            let (left, right, vertical) = placement.margins;
+            let (left, right, vertical) = (
+                percent(left, res.0),
+                percent(right, res.0),
+                percent(vertical, res.1),
+            );
+            let vertical = match row {
+                0 => (0.0, 100.0 - vertical),
+                1 => (0.0, 100.0),
+                _ => (vertical, 100.0 - vertical),
+            };
+            ((left, (100.0 - left - right).max(0.0)), vertical)
+        }
+    };
+
+    format!(
+        "tts:origin=\"{}% {}%\" tts:extent=\"{}% {}%\" tts:displayAlign=\"{}\" tts:textAlign=\"{}\"",
+        Num(x),
+        Num(y),
+        Num(width),
+        Num(height),
+        ["after", "center", "before"][row],
+        ["left", "center", "right"][column],
+    )
+}
+
+/// Spans for runs whose formatting differs from the line's style.
+fn render(runs: &[Run], base: &TextStyle, formatting: bool) -> String {
+    let mut out = String::new();
+    for run in runs {
+        let text = xml::escape(&run.text);
+        let style = &run.style;
+        let mut attributes = String::new();
+        if formatting {
+            if style.italic != base.italic {
+                let value = if style.italic { "italic" } else { "normal" };
+                write!(attributes, " tts:fontStyle=\"{value}\"").unwrap();
+            }
+            if style.bold != base.bold {
+                let value = if style.bold { "bold" } else { "normal" };
+                write!(attributes, " tts:fontWeight=\"{value}\"").unwrap();
+            }
+            if style.underline != base.underline || style.strikeout != base.strikeout {
+                let underline = if style.underline {
+                    "underline"
+                } else {
+                    "noUnderline"
+                };
+                let strikeout = if style.strikeout {
+                    "lineThrough"
+                } else {
+                    "noLineThrough"
+                };
+                write!(
+                    attributes,
+                    " tts:textDecoration=\"{underline} {strikeout}\""
+                )
+                .unwrap();
+            }
+            if let Some(color) = style.color {
+                write!(attributes, " tts:color=\"{}\"", write_color(color)).unwrap();
+            }
+        }
+
+        if attributes.is_empty() {
+            out.push_str(&text);
+        } else {
+            write!(out, "<span{attributes}>{text}</span>").unwrap();
+        }
+    }
+    out
+}
+
+/// Writes the script as IMSC1 Text Profile TTML. The PlayRes becomes the root extent and the
+/// `Language` its `xml:lang`, each used style a `style` element and each distinct placement a
+/// region.
+pub fn write<W: Write>(
+    script: &Script<'_>,
+    options: &ExportOptions,
+    time: &TimeExpression,
+    out: &mut W,
+) -> fmt::Result {
+    let res = play_res(script);
+    let style_ids = style_identifiers(&script.styles);
+    let cues = cues(script, options, |event, runs| {
+        let style = find_style(&script.styles, &event.style);
+        let text = render(runs, &TextStyle::of(style), options.formatting);
+        let text = match style {
+            Some(style) => format!(
+                "<span style=\"{STYLE_ID_PREFIX}{}\">{text}</span>",
+                style_ids[style.name.as_ref()]
+            ),
+            None => text,
+        };
+        (text, region_attributes(&Placement::of(event, style), res))
+    });
+
+    let mut used_styles: Vec<&Style<'_>> = Vec::new();
+    for event in script.events.iter().filter(|e| !e.is_comment) {
+        if let Some(style) = find_style(&script.styles, &event.style) {
+            if !used_styles.iter().any(|s| s.name == style.name) {
+                used_styles.push(style);
+            }
+        }
+    }
+    let mut regions: Vec<&str> = Vec::new();
+    for cue in &cues {
+        if !regions.contains(&cue.settings.as_str()) {
+            regions.push(&cue.settings);
+        }
+    }
+
+    // IMSC1 wants a language; `und` is BCP 47 for an undetermined one
+    let language = script
+        .info
+        .language
+        .as_deref()
+        .map(str::trim)
+        .filter(|language| !language.is_empty())
+        .unwrap_or("und");
+    out.write_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n")?;
+    write!(
+        out,
+        "<tt xmlns=\"{TT}\" xmlns:ttp=\"{TTP}\" xmlns:tts=\"{TTS}\" xml:lang=\"{}\" \
+         ttp:profile=\"{IMSC1_TEXT}\" ttp:timeBase=\"media\"",
+        xml::escape(language)
+    )?;
+    if let TimeExpression::Frames(framerate) = time {
+        let (rate, (numerator, denominator)) = frame_rate(framerate);
+        write!(out, " ttp:frameRate=\"{rate}\"")?;
+        if numerator != denominator {
+            write!(
+                out,
+                " ttp:frameRateMultiplier=\"{numerator} {denominator}\""
+            )?;
+        }
+    }
+    writeln!(out, " tts:extent=\"{}px {}px\">", res.0, res.1)?;
+
+    out.write_str("  <head>\n")?;
+    if !used_styles.is_empty() {
+        out.write_str("    <styling>\n")?;
+        for style in used_styles {
+            writeln!(
+                out,
+                "      <style xml:id=\"{STYLE_ID_PREFIX}{}\"{}/>",
+                style_ids[style.name.as_ref()],
+                style_attributes(style)
+            )?;
+        }
+        out.write_str("    </styling>\n")?;
+    }
+    if !regions.is_empty() {
+        out.write_str("    <layout>\n")?;
+        for (idx, region) in regions.iter().enumerate() {
+            writeln!(
+                out,
+                "      <region xml:id=\"{REGION_ID_PREFIX}{idx}\" {region}/>"
+            )?;
+        }
+        out.write_str("    </layout>\n")?;
+    }
+    out.write_str("  </head>\n  <body>\n    <div>\n")?;
+
+    for cue in &cues {
+        let region = regions
+            .iter()
+            .position(|r| *r == cue.settings)
+            .expect("every cue's region was collected");
+        out.write_str("      <p begin=\"")?;
+        write_time(out, cue.start, false, time)?;
+        out.write_str("\" end=\"")?;
+        write_time(out, cue.end, true, time)?;
+        writeln!(
+            out,
+            "\" region=\"{REGION_ID_PREFIX}{region}\">{}</p>",
+            cue.text.replace('\n', "<br/>")
+        )?;
+    }
+
+    out.write_str("    </div>\n  </body>\n</tt>\n")
+}
+
+pub fn to_string(script: &Script<'_>, options: &ExportOptions, time: &TimeExpression) -> String {
+    let mut out = String::new();
+    write(script, options, time, &mut out).expect("writing to a String cannot fail");
+    out
+}
+
+pub fn parse(data: &str) -> Result<Script<'static>, ParseError> {
+    parse_with(data, &mut Diagnostics::new(ParseMode::Strict))
+}
+
+pub fn parse_lenient(data: &str) -> Result<(Script<'static>, Vec<Diagnostic>), ParseError> {
+    let mut diagnostics = Diagnostics::new(ParseMode::Lenient);
+    let script = parse_with(data, &mut diagnostics)?;
+    Ok((script, diagnostics.take()))
+}
+
+/// Reads TTML. The root extent becomes the PlayRes, every `style` element a style, regions
+/// become alignment and margins, and inline styling becomes override tags. A malformed
+/// document is an error in either mode.
+pub fn parse_with(
+    data: &str,
+    diagnostics: &mut Diagnostics,
+) -> Result<Script<'static>, ParseError> {
+    let root = xml::parse(data)?;
+    let mut script = new_script();
+    if !root.is(TT, "tt") {
+        diagnostics.report(
+            Severity::Error,
+            ParseError::InvalidValue {
+                field: "root",
+                value: root.name.clone(),
+                span: root.span,
+            },
+        )?;
+    }
+
+    let language = root.attribute(XML_NAMESPACE, "lang").map(str::trim);
+    if let Some(language) = language.filter(|l| !l.is_empty() && *l != "und") {
+        script.info.language = Some(language.to_owned().into());
+    }
+
+    let extent = root
+        .attribute(TTS, "extent")
+        .and_then(|extent| pair(extent, |v| v.strip_suffix("px")?.trim().parse::<f64>().ok()));
+    if let Some((width, height)) = extent.filter(|(w, h)| *w >= 1.0 && *h >= 1.0) {
+        script.info.play_info.play_res_x = Some(width.round() as i64);
+        script.info.play_info.play_res_y = Some(height.round() as i64);
+    }
+
+    let head = root.child(TT, "head");
+    let children = |parent: &'static str, name: &'static str| {
+        head.and_then(|head| head.child(TT, parent))
+            .map(|parent| parent.elements().filter(|e| e.is(TT, name)).collect())
+            .unwrap_or_default()
+    };
+    let document = Document {
+        styles: children("styling", "style"),
+        regions: children("layout", "region"),
+        clock: Clock::of(&root),
+        res: play_res(&script),
+        cell_height: play_res(&script).1 / cell_rows(&root),
+    };
+
+    for element in &document.styles {
+        let Some(id) = element.attribute(XML_NAMESPACE, "id") else {
+            continue;
+        };
+        let name = style_name(id);
+        let idx = match script.styles.iter().position(|s| s.name == name) {
+            Some(idx) => idx,
+            None => {
+                script.styles.push(Style {
+                    name: name.to_owned().into(),
+                    ..Style::named_default()
+                });
+                script.styles.len() - 1
+            }
+        };
+        document.apply_style(&mut script.styles[idx], &document.properties(element));
+    }
+
+    if let Some(body) = root.child(TT, "body") {
+        let context = Context {
+            begin: (0, false),
+            end: None,
+            region: None,
+            style: None,
+        };
+        document.read(body, context, &mut script, diagnostics)?;
+    }
+    Ok(script)
+}
+
+/// Two whitespace separated values, like an origin or extent.
+fn pair<T>(value: &str, parse: impl Fn(&str) -> Option<T>) -> Option<(T, T)> {
+    let mut values = value.split_whitespace();
+    let pair = (parse(values.next()?)?, parse(values.next()?)?);
+    values.next().is_none().then_some(pair)
+}
+
+fn cell_rows(root: &Element) -> f64 {
+    root.attribute(TTP, "cellResolution")
+        .and_then(|cells| pair(cells, |v| v.parse::<f64>().ok()))
+        .map(|(_, rows)| rows)
+        .filter(|rows| *rows > 0.0)
+        .unwrap_or(15.0)
+}
+
+/// Reads time expressions with the document's frame and tick rates.
+struct Clock {
+    framerate: Framerate,
+    frame_rate: i64,
+    tick_rate: f64,
+}
+
+impl Clock {
+    fn of(root: &Element) -> Clock {
+        let positive = |name: &str| {
+            root.attribute(TTP, name)
+                .and_then(|v| v.trim().parse::<i64>().ok())
+                .filter(|v| *v > 0)
+        };
+        let frame_rate = positive("frameRate");
+        let (numerator, denominator) = root
+            .attribute(TTP, "frameRateMultiplier")
+            .and_then(|m| pair(m, |v| v.parse::<i64>().ok().filter(|v| *v > 0)))
+            .unwrap_or((1, 1));
+        let tick_rate = positive("tickRate").unwrap_or(match frame_rate {
+            Some(rate) => rate * positive("subFrameRate").unwrap_or(1),
+            None => 1,
+        });
+
+        let frame_rate = frame_rate.unwrap_or(30);
+        Clock {
+            framerate: Framerate::cfr(frame_rate * numerator, denominator)
+                .expect("frame rates are positive"),
+            frame_rate,
+            tick_rate: tick_rate as f64,
+        }
+    }
+
+    /// A time in milliseconds, and whether it was given in frames.
+    fn parse(&self, value: &str) -> Option<(i64, bool)> {
+        let value = value.trim();
+        let number = |v: &str| {
+            (!v.is_empty() && v.bytes().all(|b| b.is_ascii_digit()))
+                .then(|| v.parse::<i64>().ok())
+                .flatten()
+        };
+        let frame = |count: i64| {
+            (
+                self.framerate.time_at_frame_ms(count, FrameTime::Exact),
+                true,
+            )
+        };
+
+        if value.contains(':') {
+            let parts: Vec<&str> = value.split(':').collect();
+            return match parts[..] {
+                [hours, minutes, seconds] => {
+                    let (seconds, fraction) = seconds.split_once('.').unwrap_or((seconds, ""));
+                    let fraction = match fraction {
+                        "" => 0,
+                        f if number(f).is_some() => {
+                            (format!("0.{f}").parse::<f64>().ok()? * 1000.0).round() as i64
+                        }
+                        _ => return None,
+                    };
+                    let seconds = (number(hours)? * 60 + number(minutes)?) * 60 + number(seconds)?;
+                    Some((seconds.checked_mul(1000)? + fraction, false))
+                }
+                [hours, minutes, seconds, frames] => {
+                    // sub-frames are dropped
+                    let frames = frames.split_once('.').map_or(frames, |(f, _)| f);
+                    let seconds = (number(hours)? * 60 + number(minutes)?) * 60 + number(seconds)?;
+                    Some(frame(
+                        seconds.checked_mul(self.frame_rate)? + number(frames)?,
+                    ))
+                }
+                _ => None,
+            };
+        }
+
+        let split = value.find(|c: char| !(c.is_ascii_digit() || c == '.'))?;
+        let (count, metric) = value.split_at(split);
+        let count = count.parse::<f64>().ok().filter(|c| c.is_finite())?;
+        let ms = match metric {
+            "h" => count * 3_600_000.0,
+            "m" => count * 60_000.0,
+            "s" => count * 1_000.0,
+            "ms" => count,
+            "t" => count / self.tick_rate * 1_000.0,
+            "f" => return Some(frame(count.floor() as i64)),
+            _ => return None,
+        };
+        Some((ms.round() as i64, false))
+    }
+}
+
+/// Timing, region and style inherited from enclosing elements.
+#[derive(Clone, Copy)]
+struct Context<'e> {
+    begin: (i64, bool),
+    end: Option<(i64, bool)>,
+    region: Option<&'e str>,
+    style: Option<&'e str>,
+}
+
+struct Document<'e> {
+    styles: Vec<&'e Element>,
+    regions: Vec<&'e Element>,
+    clock: Clock,
+    res: (f64, f64),
+    cell_height: f64,
+}
+
+impl<'e> Document<'e> {
+    /// Styling attributes of `element`, with those of the styles it references first so that
+    /// its own take precedence.
+    fn properties(&self, element: &'e Element) -> Vec<(&'e str, &'e str)> {
+        let mut properties = Vec::new();
+        self.collect_properties(element, &mut properties, 0);
+        properties
+    }
+
+    fn collect_properties(
+        &self,
+        element: &'e Element,
+        properties: &mut Vec<(&'e str, &'e str)>,
+        depth: usize,
+    ) {
+        // references can form a cycle
+        if depth > 16 {
+            return;
+        }
+        for id in element
+            .attribute("", "style")
+            .unwrap_or_default()
+            .split_whitespace()
+        {
+            let referenced = self
+                .styles
+                .iter()
+                .find(|s| s.attribute(XML_NAMESPACE, "id") == Some(id));
+            if let Some(referenced) = referenced {
+                self.collect_properties(referenced, properties, depth + 1);
+            }
+        }
+        properties.extend(
+            element
+                .attributes
+                .iter()
+                .filter(|a| a.namespace == TTS)
+                .map(|a| (a.name.as_str(), a.value.as_str())),
+        );
+    }
+
+    /// A length in pixels. Percentages and `em` are relative to the default font size of one
+    /// cell.
+    fn length(&self, value: &str, of: f64) -> Option<f64> {
+        let value = value.trim();
+        let (number, scale) = if let Some(v) = value.strip_suffix("px") {
+            (v, 1.0)
+        } else if let Some(v) = value.strip_suffix('%') {
+            (v, of / 100.0)
+        } else if let Some(v) = value.strip_suffix("em") {
+            (v, self.cell_height)
+        } else if let Some(v) = value.strip_suffix('c') {
+            (v, self.cell_height)
+        } else {
+            return None;
+        };
+        number
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|v| v.is_finite())
+            .map(|v| v * scale)
+    }
+
+    fn apply_style(&self, style: &mut Style<'static>, properties: &[(&str, &str)]) {
+        for &(name, value) in properties {
+            let value = value.trim();
+            match name {
+                "fontFamily" => {
+                    let family = value.split(',').next().unwrap_or_default();
+                    style.font_name = family.trim().trim_matches(['"', '\'']).to_owned().into();
+                }
+                "fontSize" => {
+                    // a second value is the height
+                    let size = value.split_whitespace().last().unwrap_or_default();
+                    if let Some(size) = self.length(size, self.cell_height) {
+                        style.font_size = size;
+                    }
+                }
+                "color" => {
+                    if let Some(color) = parse_color(value) {
+                        style.primary_color = color.with_alpha();
+                    }
+                }
+                "backgroundColor" => {
+                    if let Some(color) = parse_color(value).filter(|c| c.alpha != Some(255)) {
+                        style.border_style = 3;
+                        style.outline_color = Some(color.with_alpha());
+                    }
+                }
+                "textOutline" => {
+                    let mut thickness = None;
+                    for part in value.split_whitespace() {
+                        if let Some(color) = parse_color(part) {
+                            style.outline_color = Some(color.with_alpha());
+                        } else if thickness.is_none() {
+                            thickness = self.length(part, style.font_size);
+                        }
+                    }
+                    style.outline = thickness.unwrap_or(0.0);
+                }
+                "textAlign" => {
+                    let row = (style.alignment.clamp(1, 9) - 1) / 3;
+                    style.alignment = row * 3 + column(value).unwrap_or(1) as i64 + 1;
+                }
+                _ => {
+                    let mut text = TextStyle::of(Some(style));
+                    apply_text_style(&mut text, name, value);
+                    style.bold = text.bold;
+                    style.italic = text.italic;
+                    style.underline = Some(text.underline);
+                    style.strikeout = Some(text.strikeout);
+                }
+            }
+        }
+    }
+
+    fn read(
+        &self,
+        element: &'e Element,
+        parent: Context<'e>,
+        script: &mut Script<'static>,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<(), ParseError> {
+        let time = |field: &'static str| -> Result<Option<(i64, bool)>, ParseError> {
+            let Some(value) = element.attribute("", field) else {
+                return Ok(None);
+            };
+            match self.clock.parse(value) {
+                Some(time) => Ok(Some(time)),
+                None => Err(ParseError::InvalidTimestamp {
+                    field,
+                    value: value.to_owned(),
+                    span: element.span,
+                }),
+            }
+        };
+        let offset = |(ms, frames): (i64, bool)| (parent.begin.0 + ms, parent.begin.1 || frames);
+
+        let timing = (|| {
+            let begin = time("begin")?.map_or(parent.begin, offset);
+            let end = match (time("end")?, time("dur")?) {
+                (Some(end), _) => Some(offset(end)),
+                (None, Some((ms, frames))) => Some((begin.0 + ms, begin.1 || frames)),
+                (None, None) => parent.end,
+            };
+            Ok((begin, end))
+        })();
+        let (begin, end) = match timing {
+            Ok(timing) => timing,
+            Err(error) => return diagnostics.report(Severity::Error, error),
+        };
+        let context = Context {
+            begin,
+            end,
+            region: element.attribute("", "region").or(parent.region),
+            style: element.attribute("", "style").or(parent.style),
+        };
+
+        if !element.is(TT, "p") {
+            // lines keep document order even where `div`s and `p`s are mixed
+            for child in element
+                .elements()
+                .filter(|e| e.is(TT, "div") || e.is(TT, "p"))
+            {
+                self.read(child, context, script, diagnostics)?;
+            }
+            return Ok(());
+        }
+
+        let Some(end) = context.end else {
+            return diagnostics.report(
+                Severity::Error,
+                ParseError::MissingField {
+                    field: "end",
+                    span: element.span,
+                },
+            );
+        };
+        let start = match begin {
+            (ms, true) => self.clock.framerate.time_at_frame(
+                self.clock.framerate.frame_at_ms(ms, FrameTime::Exact),
+                FrameTime::Start,
+            ),
+            (ms, false) => Timestamp::from_millis(ms),
+        };
+        let end = match end {
+            (ms, true) => self.clock.framerate.time_at_frame(
+                self.clock.framerate.frame_at_ms(ms, FrameTime::Exact) - 1,
+                FrameTime::End,
+            ),
+            (ms, false) => Timestamp::from_millis(ms),
+        };
+
+        script
+            .events
+            .push(self.event(element, context, start, end, &script.styles));
+        Ok(())
+    }
+
+    /// A line for `p`. The first style it references is the line's style; further styles,
+    /// inline styling and spans become override tags.
+    fn event(
+        &self,
+        p: &'e Element,
+        context: Context<'e>,
+        start: Timestamp,
+        end: Timestamp,
+        styles: &[Style<'_>],
+    ) -> EventLine<'static> {
+        // a paragraph holding nothing but one styled span takes its style from the span
+        let mut nodes = p
+            .children
+            .iter()
+            .filter(|node| !matches!(node, Node::Text(text) if text.trim().is_empty()));
+        let sole_span = match (nodes.next(), nodes.next()) {
+            (Some(Node::Element(span)), None)
+                if span.is(TT, "span")
+                    && span.attribute("", "style").is_some()
+                    && p.attribute("", "style").is_none() =>
+            {
+                Some(span)
+            }
+            _ => None,
+        };
+        let (content, ids) = match sole_span {
+            Some(span) => (span, span.attribute("", "style")),
+            None => (p, context.style),
+        };
+
+        let mut ids = ids.unwrap_or_default().split_whitespace();
+        let style = ids
+            .next()
+            .and_then(|id| styles.iter().find(|s| s.name == style_name(id)))
+            .or_else(|| find_style(styles, "Default"));
+
+        let mut properties = Vec::new();
+        for id in ids {
+            if let Some(referenced) = self
+                .styles
+                .iter()
+                .find(|s| s.attribute(XML_NAMESPACE, "id") == Some(id))
+            {
+                self.collect_properties(referenced, &mut properties, 1);
+            }
+        }
+        let inline = p
+            .attributes
+            .iter()
+            .chain(sole_span.map_or(&[][..], |s| &s.attributes));
+        properties.extend(
+            inline
+                .filter(|a| a.namespace == TTS)
+                .map(|a| (a.name.as_str(), a.value.as_str())),
+        );
+
+        let base = TextStyle {
+            color: style.map(|s| opaque(s.primary_color)),
+            ..TextStyle::of(style)
+        };
+        let mut line = Line {
+            text: String::new(),
+            pending_space: false,
+            line_start: true,
+        };
+        let mut state = base.clone();
+        for &(name, value) in &properties {
+            apply_text_style(&mut state, name, value);
+        }
+        line.tags(&base, &state);
+        self.content(content, &mut line, &state);
+
+        let mut event = new_event(start, end, line.text);
+        if let Some(style) = style {
+            event.style = style.name.to_string().into();
+        }
+
+        let region = context.region.and_then(|id| {
+            self.regions
+                .iter()
+                .find(|r| r.attribute(XML_NAMESPACE, "id") == Some(id))
+        });
+        if let Some(region) = region {
+            self.place(&mut event, region, &properties, style);
+        }
+        event
+    }
+
+    /// Alignment and margins for a line shown in `region`.
+    fn place(
+        &self,
+        event: &mut EventLine<'static>,
+        region: &'e Element,
+        line_properties: &[(&str, &str)],
+        style: Option<&Style<'_>>,
+    ) {
+        let properties = self.properties(region);
+        let property = |name: &str| {
+            line_properties
+                .iter()
+                .chain(&properties)
+                .rev()
+                .find(|(n, _)| *n == name)
+                .map(|(_, v)| v.trim())
+        };
+        let (x, y) = property("origin")
+            .and_then(|v| pair(v, |v| Some(v.to_owned())))
+            .and_then(|(x, y)| Some((self.length(&x, self.res.0)?, self.length(&y, self.res.1)?)))
+            .unwrap_or((0.0, 0.0));
+        let (width, height) = property("extent")
+            .and_then(|v| pair(v, |v| Some(v.to_owned())))
+            .and_then(|(w, h)| Some((self.length(&w, self.res.0)?, self.length(&h, self.res.1)?)))
+            .unwrap_or((self.res.0 - x, self.res.1 - y));
+
+        let row = match property("displayAlign") {
+            Some("after") => 0,
+            Some("center") => 1,
+            _ => 2,
+        };
+        let column = property("textAlign").and_then(column).unwrap_or(0);
+        let alignment = (row * 3 + column + 1) as u8;
+
+        let margin = |value: f64, style: Option<i64>| {
+            let value = value.round().max(0.0) as i64;
+            if Some(value) == style {
+                0
+            } else {
+                value
+            }
+        };
+        event.margin_left = margin(x, style.map(|s| s.margin_left));
+        event.margin_right = margin(self.res.0 - x - width, style.map(|s| s.margin_right));
+        event.margin_vertical = match row {
+            0 => margin(self.res.1 - y - height, style.map(|s| s.margin_vertical)),
+            1 => 0,
+            _ => margin(y, style.map(|s| s.margin_vertical)),
+        };
+
+        if style.map(|s| s.alignment) != Some(i64::from(alignment)) {
+            event.text = format!("{{\\an{alignment}}}{}", event.text).into();
+        }
+    }
+
+    fn content(&self, element: &'e Element, line: &mut Line, state: &TextStyle) {
+        for node in &element.children {
+            match node {
+                Node::Text(text) => line.push(text),
+                Node::Element(child) if child.is(TT, "br") => line.line_break(),
+                Node::Element(child) if child.is(TT, "span") => {
+                    let mut inner = state.clone();
+                    for (name, value) in self.properties(child) {
+                        apply_text_style(&mut inner, name, value);
+                    }
+                    line.tags(state, &inner);
+                    self.content(child, line, &inner);
+                    line.tags(&inner, state);
+                }
+                Node::Element(_) => {}
+            }
+        }
+    }
+}
+
+/// 0 for left, 1 for centre, 2 for right, assuming left-to-right text.
+fn column(text_align: &str) -> Option<usize> {
+    match text_align {
+        "left" | "start" => Some(0),
+        "center" => Some(1),
+        "right" | "end" => Some(2),
+        _ => None,
+    }
+}
+
+/// `\c` sets no alpha, so colours are compared without it.
+fn opaque(color: Color) -> Color {
+    Color {
+        alpha: None,
+        ..color
+    }
+}
+
+fn apply_text_style(state: &mut TextStyle, name: &str, value: &str) {
+    let value = value.trim();
+    match name {
+        "color" => {
+            if let Some(color) = parse_color(value) {
+                state.color = Some(opaque(color));
+            }
+        }
+        "fontWeight" => state.bold = value == "bold",
+        "fontStyle" => state.italic = value == "italic" || value == "oblique",
+        "textDecoration" => {
+            for decoration in value.split_whitespace() {
+                match decoration {
+                    "none" => {
+                        state.underline = false;
+                        state.strikeout = false;
+                    }
+                    "underline" => state.underline = true,
+                    "noUnderline" => state.underline = false,
+                    "lineThrough" => state.strikeout = true,
+                    "noLineThrough" => state.strikeout = false,
+                    _ => {}
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Builds line text from TTML content, collapsing whitespace the way `xml:space="default"`
+/// does.
+struct Line {
+    text: String,
+    pending_space: bool,
+    line_start: bool,
+}
+
+impl Line {
+    fn push(&mut self, text: &str) {
+        for c in text.chars() {
+            // a no-break space is whitespace too, but is kept
+            if c.is_whitespace() && c != '\u{a0}' {
+                self.pending_space = !self.line_start;
+                continue;
+            }
+            if self.pending_space {
+                self.text.push(' ');
+                self.pending_space = false;
+            }
+            self.line_start = false;
+            match c {
+                // braces would start an override block
+                '{' => self.text.push('('),
+                '}' => self.text.push(')'),
+                '\u{a0}' => self.text.push_str("\\h"),
+                c => self.text.push(c),
+            }
+        }
+    }
+
+    fn line_break(&mut self) {
+        self.text.push_str("\\N");
+        self.pending_space = false;
+        self.line_start = true;
+    }
+
+    /// Override tags that change `from` into `to`.
+    fn tags(&mut self, from: &TextStyle, to: &TextStyle) {
+        let mut tags = Vec::new();
+        if from.italic != to.italic {
+            tags.push(OverrideTag::Italic(Some(to.italic)));
+        }
+        if from.bold != to.bold {
+            tags.push(OverrideTag::Bold(Some(i32::from(to.bold))));
+        }
+        if from.underline != to.underline {
+            tags.push(OverrideTag::Underline(Some(to.underline)));
+        }
+        if from.strikeout != to.strikeout {
+            tags.push(OverrideTag::StrikeOut(Some(to.strikeout)));
+        }
+        if from.color != to.color {
+            tags.push(OverrideTag::Color(ColorSlot::Primary, to.color));
+        }
+        if !tags.is_empty() {
+            if self.pending_space {
+                self.text.push(' ');
+                self.pending_space = false;
+            }
+            Token::Override(tags)
+                .write_ass(&mut self.text)
+                .expect("writing to a String cannot fail");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn export(script: &Script<'_>) -> String {
+        let mut out = String::new();
+        write(
+            script,
+            &ExportOptions::default(),
+            &TimeExpression::Media,
+            &mut out,
+        )
+        .unwrap();
+        out
+    }
+
+    fn document(body: &str) -> String {
+        format!("<tt xmlns=\"{TT}\"><body>{body}</body></tt>")
+    }
+
+    #[test]
+    fn style_and_region_ids_are_unique() {
+        let mut script = new_script();
+        script.styles = ["Sign Top", "Sign_Top", "r0"]
+            .into_iter()
+            .map(|name| Style {
+                name: name.into(),
+                ..Style::named_default()
+            })
+            .collect();
+        for (idx, style) in ["Sign Top", "Sign_Top", "r0"].into_iter().enumerate() {
+            let mut event = new_event(
+                Timestamp::from_millis(idx as i64 * 1000),
+                Timestamp::from_millis(idx as i64 * 1000 + 500),
+                "x".to_owned(),
+            );
+            event.style = style.into();
+            script.events.push(event);
+        }
+
+        let out = export(&script);
+        let mut ids: Vec<&str> = out
+            .split("xml:id=\"")
+            .skip(1)
+            .map(|rest| &rest[..rest.find('"').unwrap()])
+            .collect();
+        assert_eq!(ids, ["s_Sign_Top", "s_Sign_Top_2", "s_r0", "r0"]);
+        ids.dedup();
+        assert_eq!(ids.len(), 4);
+
+        let imported = parse(&out).unwrap();
+        let names: Vec<&str> = imported.styles.iter().map(|s| s.name.as_ref()).collect();
+        assert_eq!(names, ["Default", "Sign_Top", "Sign_Top_2", "r0"]);
+        let styles: Vec<&str> = imported.events.iter().map(|e| e.style.as_ref()).collect();
+        assert_eq!(styles, ["Sign_Top", "Sign_Top_2", "r0"]);
+    }
+
+    #[test]
+    fn no_break_spaces_become_hard_spaces() {
+        let script = parse(&document("<p begin=\"0s\" end=\"1s\">a\u{a0}b  c</p>")).unwrap();
+        assert_eq!(script.events[0].text, "a\\hb c");
+    }
+
+    #[test]
+    fn lines_keep_document_order() {
+        let script = parse(&document(
+            "<p begin=\"0s\" end=\"1s\">one</p>\
+             <div><p begin=\"1s\" end=\"2s\">two</p></div>\
+             <p begin=\"2s\" end=\"3s\">three</p>",
+        ))
+        .unwrap();
+        let text: Vec<&str> = script.events.iter().map(|e| e.text.as_ref()).collect();
+        assert_eq!(text, ["one", "two", "three"]);
+    }
+}
@@ -0,0 +1,674 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Write},
+};
+
+use crate::{
+    diagnostics::{Diagnostic, Diagnostics, ParseMode, Severity},
+    models::{events::EventLine, style::Style, Color},
+    tags::Num,
+    ParseError, Script, Span, Timestamp,
+};
+
+use super::{
+    cues, find_style, html_color, html_to_ass, new_event, new_script, parse_clock_time, play_res,
+    runs, source_lines, style_identifiers, write_clock_time, write_html_color, ExportOptions,
+    Placement, Run,
+};
+
+/// The colour classes every WebVTT player knows.
+const CUE_COLORS: [(&str, Color); 8] = [
+    ("white", Color::rgb(255, 255, 255)),
+    ("lime", Color::rgb(0, 255, 0)),
+    ("cyan", Color::rgb(0, 255, 255)),
+    ("red", Color::rgb(255, 0, 0)),
+    ("yellow", Color::rgb(255, 255, 0)),
+    ("magenta", Color::rgb(255, 0, 255)),
+    ("blue", Color::rgb(0, 0, 255)),
+    ("black", Color::rgb(0, 0, 0)),
+];
+
+fn color_class(color: Color) -> String {
+    format!("c{:02x}{:02x}{:02x}", color.red, color.green, color.blue)
+}
+
+/// The colour of a class written by [`color_class`].
+fn class_color(class: &str) -> Option<Color> {
+    let hex = class.strip_prefix('c').filter(|h| h.len() == 6)?;
+    html_color(&format!("#{hex}"))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn render(runs: &[Run], style_class: Option<&str>, voice: &str, formatting: bool) -> String {
+    let mut out = String::new();
+    if formatting && !voice.trim().is_empty() {
+        write!(out, "<v {}>", escape(voice.trim())).unwrap();
+    }
+    for run in runs {
+        let text = escape(&run.text);
+        if !formatting {
+            out.push_str(&text);
+            continue;
+        }
+
+        let style = &run.style;
+        let tags = [
+            (style.bold, "b"),
+            (style.italic, "i"),
+            (style.underline, "u"),
+        ];
+        for (_, tag) in tags.iter().filter(|(on, _)| *on) {
+            write!(out, "<{tag}>").unwrap();
+        }
+        match style.color {
+            Some(color) => write!(out, "<c.{}>{text}</c>", color_class(color)).unwrap(),
+            None => out.push_str(&text),
+        }
+        for (_, tag) in tags.iter().rev().filter(|(on, _)| *on) {
+            write!(out, "</{tag}>").unwrap();
+        }
+    }
+
+    let text = out
+        .split('\n')
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    match style_class {
+        Some(class) if formatting => format!("<c.{class}>{text}</c>"),
+        _ => text,
+    }
+}
+
+/// Cue settings that put the line where `\an`, `\pos` or the style's alignment and margins put
+/// it, as percentages of the PlayRes.
+fn cue_settings(placement: &Placement, res: (f64, f64)) -> String {
+    let (column, row) = (placement.column(), placement.row());
+    let (margin_left, margin_right, margin_vertical) = placement.margins;
+    let percent = |v: f64, of: f64| Num((v / of * 100.0).clamp(0.0, 100.0));
+
+    let h_anchor = ["line-left", "center", "line-right"][column];
+    let v_anchor = ["end", "center", "start"][row];
+    let align = ["left", "center", "right"][column];
+
+    let (x, y) = match placement.position {
+        Some((x, y)) => (percent(x, res.0), percent(y, res.1)),
+        None => {
+            let x = match column {
+                0 => margin_left,
+                1 => res.0 / 2.0 + (margin_left - margin_right) / 2.0,
+                _ => res.0 - margin_right,
+            };
+            let y = match row {
+                0 => res.1 - margin_vertical,
+                1 => res.1 / 2.0,
+                _ => margin_vertical,
+            };
+            (percent(x, res.0), percent(y, res.1))
+        }
+    };
+
+    format!("position:{x}%,{h_anchor} line:{y}%,{v_anchor} align:{align}")
+}
+
+/// Writes a `::cue(.class)` rule. `name` is the style name the class stands for, kept in a
+/// comment when the class had to be changed from it.
+fn css_rule<W: Write>(
+    out: &mut W,
+    class: &str,
+    name: Option<&str>,
+    declarations: &[String],
+) -> fmt::Result {
+    writeln!(out, "::cue(.{class}) {{")?;
+    if let Some(name) = name.filter(|name| *name != class && !name.contains(['{', '}', '*'])) {
+        writeln!(out, "  /* {name} */")?;
+    }
+    for declaration in declarations {
+        writeln!(out, "  {declaration};")?;
+    }
+    writeln!(out, "}}")
+}
+
+/// Writes the script as WebVTT. Each style becomes a `::cue` class and each line a cue placed
+/// by its alignment, margins and `\pos`. Style names that are not valid class names are kept in
+/// a comment in their rule, unless they contain `{`, `}` or `*`.
+pub fn write<W: Write>(script: &Script<'_>, options: &ExportOptions, out: &mut W) -> fmt::Result {
+    let res = play_res(script);
+    let events = script.events.iter().filter(|e| !e.is_comment);
+
+    let classes = style_identifiers(&script.styles);
+    out.write_str("WEBVTT\n")?;
+    if options.formatting {
+        let mut used_styles = BTreeSet::new();
+        let mut colors = BTreeMap::new();
+        for event in events {
+            if let Some(style) = find_style(&script.styles, &event.style) {
+                used_styles.insert(style.name.as_ref());
+            }
+            for color in runs(event, &script.styles)
+                .iter()
+                .filter_map(|r| r.style.color)
+            {
+                colors.insert(color_class(color), color);
+            }
+        }
+
+        if !used_styles.is_empty() || !colors.is_empty() {
+            out.write_str("\nSTYLE\n")?;
+        }
+        for style in script
+            .styles
+            .iter()
+            .filter(|s| used_styles.contains(s.name.as_ref()))
+        {
+            let mut declarations = vec![
+                format!("color: {}", write_html_color(style.primary_color)),
+                format!("font-family: \"{}\"", style.font_name.replace('"', "")),
+            ];
+            if style.bold {
+                declarations.push("font-weight: bold".to_owned());
+            }
+            if style.italic {
+                declarations.push("font-style: italic".to_owned());
+            }
+            match (style.underline, style.strikeout) {
+                (Some(true), Some(true)) => {
+                    declarations.push("text-decoration: underline line-through".to_owned())
+                }
+                (Some(true), _) => declarations.push("text-decoration: underline".to_owned()),
+                (_, Some(true)) => declarations.push("text-decoration: line-through".to_owned()),
+                _ => {}
+            }
+            let class = &classes[style.name.as_ref()];
+            css_rule(out, class, Some(&style.name), &declarations)?;
+        }
+        for (class, color) in colors {
+            css_rule(
+                out,
+                &class,
+                None,
+                &[format!("color: {}", write_html_color(color))],
+            )?;
+        }
+    }
+
+    let cues = cues(script, options, |event, runs| {
+        let style = find_style(&script.styles, &event.style);
+        let class = style.map(|s| classes[s.name.as_ref()].as_str());
+        (
+            render(runs, class, &event.name, options.formatting),
+            cue_settings(&Placement::of(event, style), res),
+        )
+    });
+    for cue in cues {
+        out.write_char('\n')?;
+        write_clock_time(out, cue.start, '.', 2)?;
+        out.write_str(" --> ")?;
+        write_clock_time(out, cue.end, '.', 2)?;
+        writeln!(out, " {}\n{}", cue.settings, cue.text)?;
+    }
+    Ok(())
+}
+
+pub fn to_string(script: &Script<'_>, options: &ExportOptions) -> String {
+    let mut out = String::new();
+    write(script, options, &mut out).expect("writing to a String cannot fail");
+    out
+}
+
+pub fn parse(data: &str) -> Result<Script<'static>, ParseError> {
+    parse_with(data, &mut Diagnostics::new(ParseMode::Strict))
+}
+
+pub fn parse_lenient(data: &str) -> Result<(Script<'static>, Vec<Diagnostic>), ParseError> {
+    let mut diagnostics = Diagnostics::new(ParseMode::Lenient);
+    let script = parse_with(data, &mut diagnostics)?;
+    Ok((script, diagnostics.take()))
+}
+
+/// Reads WebVTT cues. `::cue(.class)` rules become styles, a cue wrapped in one of those classes
+/// uses that style, colour classes become `\c` tags, voices become the line's name and cue
+/// settings become an `\an` tag.
+pub fn parse_with(
+    data: &str,
+    diagnostics: &mut Diagnostics,
+) -> Result<Script<'static>, ParseError> {
+    let lines = source_lines(data);
+    let mut script = new_script();
+    // style class to style name
+    let mut classes = BTreeMap::new();
+    let blank = |idx: usize| lines[idx].0.trim().is_empty();
+
+    let (header, span) = lines
+        .first()
+        .copied()
+        .unwrap_or(("", Span::start_of_line(0, 1)));
+    if !(header == "WEBVTT" || header.starts_with("WEBVTT ") || header.starts_with("WEBVTT\t")) {
+        diagnostics.report(
+            Severity::Error,
+            ParseError::InvalidValue {
+                field: "header",
+                value: header.to_owned(),
+                span,
+            },
+        )?;
+    }
+
+    let mut idx = 0;
+    while idx < lines.len() && !blank(idx) {
+        idx += 1;
+    }
+
+    while idx < lines.len() {
+        if blank(idx) {
+            idx += 1;
+            continue;
+        }
+
+        let block_start = idx;
+        while idx < lines.len() && !blank(idx) {
+            idx += 1;
+        }
+        let block = &lines[block_start..idx];
+        let first = block[0].0;
+
+        if first.starts_with("NOTE") || first.starts_with("REGION") {
+            continue;
+        }
+        if first.trim() == "STYLE" {
+            let css: Vec<&str> = block[1..].iter().map(|(line, _)| *line).collect();
+            parse_css(&css.join("\n"), &mut script.styles, &mut classes);
+            continue;
+        }
+
+        let timing = if first.contains("-->") { 0 } else { 1 };
+        let Some(&(line, span)) = block.get(timing) else {
+            diagnostics.report(
+                Severity::Error,
+                ParseError::InvalidValue {
+                    field: "timing",
+                    value: first.to_owned(),
+                    span: block[0].1,
+                },
+            )?;
+            continue;
+        };
+
+        match parse_timing(line, span) {
+            Ok((start, end, settings)) => {
+                let text: Vec<&str> = block[timing + 1..].iter().map(|(l, _)| *l).collect();
+                script.events.push(cue_event(
+                    start,
+                    end,
+                    settings,
+                    &text.join("\n"),
+                    &script.styles,
+                    &classes,
+                ));
+            }
+            Err(error) => diagnostics.report(Severity::Error, error)?,
+        }
+    }
+
+    Ok(script)
+}
+
+fn parse_timing(line: &str, span: Span) -> Result<(Timestamp, Timestamp, &str), ParseError> {
+    let Some((start, rest)) = line.split_once("-->") else {
+        return Err(ParseError::InvalidValue {
+            field: "timing",
+            value: line.to_owned(),
+            span,
+        });
+    };
+    let rest = rest.trim_start();
+    let (end, settings) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let start = start.trim();
+
+    let time = |field: &'static str, value: &str| {
+        parse_clock_time(value, &['.'], true).ok_or_else(|| ParseError::InvalidTimestamp {
+            field,
+            value: value.to_owned(),
+            span: if value.is_empty() {
+                span.advance(line)
+            } else {
+                span.of_subslice(line, value)
+            },
+        })
+    };
+    Ok((time("Start", start)?, time("End", end)?, settings.trim()))
+}
+
+/// Adds a style for every `::cue(.class)` rule, and applies a bare `::cue` rule to `Default`.
+/// A comment in a class rule gives the name of its style, as [`write`] leaves it; `classes`
+/// maps each class to its style name.
+fn parse_css(css: &str, styles: &mut Vec<Style<'static>>, classes: &mut BTreeMap<String, String>) {
+    let mut rest = css;
+    while let Some((selector, after)) = rest.split_once('{') {
+        let Some((body, tail)) = after.split_once('}') else {
+            break;
+        };
+        rest = tail;
+
+        let mut name = None;
+        let mut declarations = String::with_capacity(body.len());
+        let mut body_rest = body;
+        while let Some((before, comment)) = body_rest.split_once("/*") {
+            declarations.push_str(before);
+            let (comment, after) = comment.split_once("*/").unwrap_or((comment, ""));
+            name = name.or(Some(comment.trim()).filter(|c| !c.is_empty()));
+            body_rest = after;
+        }
+        declarations.push_str(body_rest);
+
+        let selector = selector.trim();
+        let Some(target) = selector.strip_prefix("::cue") else {
+            continue;
+        };
+        let class = target
+            .trim()
+            .strip_prefix("(.")
+            .and_then(|v| v.strip_suffix(')'))
+            .map(str::trim);
+
+        let style = match class {
+            None if target.trim().is_empty() => match styles.first_mut() {
+                Some(style) => style,
+                None => continue,
+            },
+            Some(class)
+                if !class.is_empty()
+                    && !class.contains(['.', ' '])
+                    && class_color(class).is_none() =>
+            {
+                let name = name.unwrap_or(class);
+                classes.insert(class.to_owned(), name.to_owned());
+                match styles.iter().position(|s| s.name == name) {
+                    Some(idx) => &mut styles[idx],
+                    None => {
+                        styles.push(Style {
+                            name: name.to_owned().into(),
+                            ..Style::named_default()
+                        });
+                        styles.last_mut().expect("style was just pushed")
+                    }
+                }
+            }
+            _ => continue,
+        };
+
+        for declaration in declarations.split(';') {
+            let Some((property, value)) = declaration.split_once(':') else {
+                continue;
+            };
+            let value = value.trim().trim_end_matches("!important").trim();
+            match property.trim().to_ascii_lowercase().as_str() {
+                "color" => {
+                    if let Some(color) = html_color(value) {
+                        style.primary_color = color;
+                    }
+                }
+                "font-family" => {
+                    let family = value.split(',').next().unwrap_or_default();
+                    style.font_name = family.trim().trim_matches(['"', '\'']).to_owned().into();
+                }
+                "font-weight" => {
+                    style.bold = value == "bold"
+                        || value == "bolder"
+                        || value.parse::<u32>().is_ok_and(|w| w >= 600)
+                }
+                "font-style" => style.italic = value == "italic" || value == "oblique",
+                "text-decoration" => {
+                    style.underline = Some(value.contains("underline"));
+                    style.strikeout = Some(value.contains("line-through"));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// `\an` value for WebVTT cue settings, if they move the cue away from the bottom centre.
+fn settings_alignment(settings: &str) -> Option<u8> {
+    let mut column = 1;
+    let mut row = 0;
+    for setting in settings.split_whitespace() {
+        let Some((key, value)) = setting.split_once(':') else {
+            continue;
+        };
+        let value = value.split(',').next().unwrap_or_default();
+        match key {
+            "align" => {
+                column = match value {
+                    "start" | "left" => 0,
+                    "end" | "right" => 2,
+                    _ => 1,
+                }
+            }
+            "line" => {
+                row = match value.strip_suffix('%') {
+                    Some(percent) => match percent.parse::<f64>() {
+                        Ok(p) if p < 100.0 / 3.0 => 2,
+                        Ok(p) if p < 200.0 / 3.0 => 1,
+                        _ => 0,
+                    },
+                    // line numbers count from the top when positive, from the bottom otherwise
+                    None => match value.parse::<i64>() {
+                        Ok(n) if n >= 0 => 2,
+                        _ => 0,
+                    },
+                }
+            }
+            _ => {}
+        }
+    }
+    let alignment = row * 3 + column + 1;
+    (alignment != 2).then_some(alignment)
+}
+
+fn cue_event(
+    start: Timestamp,
+    end: Timestamp,
+    settings: &str,
+    text: &str,
+    styles: &[Style<'_>],
+    classes: &BTreeMap<String, String>,
+) -> EventLine<'static> {
+    let mut style = None;
+    let mut text = text;
+    // a cue wrapped in a single style class uses that style
+    if let Some(inner) = text
+        .strip_prefix("<c.")
+        .and_then(|t| t.strip_suffix("</c>"))
+    {
+        if let Some((class, body)) = inner.split_once('>') {
+            if let Some(found) = class_style(class, styles, classes).filter(|_| closes_inside(body))
+            {
+                style = Some(found.name.to_string());
+                text = body;
+            }
+        }
+    }
+
+    let (markup, voice) = cue_markup(text, styles, classes);
+    let mut ass = html_to_ass(&markup);
+    if let Some(an) = settings_alignment(settings) {
+        ass.insert_str(0, &format!("{{\\an{an}}}"));
+    }
+
+    let mut event = new_event(start, end, ass);
+    if let Some(style) = style {
+        event.style = style.into();
+    }
+    if let Some(voice) = voice {
+        event.name = voice.into();
+    }
+    event
+}
+
+/// The style a class stands for: the one its `STYLE` rule named, or else the one named like it.
+fn class_style<'s, 'a>(
+    class: &str,
+    styles: &'s [Style<'a>],
+    classes: &BTreeMap<String, String>,
+) -> Option<&'s Style<'a>> {
+    let name = classes.get(class).map_or(class, String::as_str);
+    styles.iter().find(|s| s.name == name)
+}
+
+/// Whether every `</c>` in `body` closes a class span opened in it.
+fn closes_inside(body: &str) -> bool {
+    let mut depth = 0usize;
+    for (idx, _) in body.match_indices('<') {
+        let tag = &body[idx + 1..];
+        if tag.starts_with("/c>") {
+            match depth.checked_sub(1) {
+                Some(d) => depth = d,
+                None => return false,
+            }
+        } else if tag.starts_with("c.") || tag.starts_with("c>") || tag.starts_with("c ") {
+            depth += 1;
+        }
+    }
+    true
+}
+
+/// Rewrites WebVTT-only tags into the HTML-style markup [`html_to_ass`] understands: class spans
+/// with a colour become `<font color>`, and the first voice is returned separately.
+fn cue_markup(
+    text: &str,
+    styles: &[Style<'_>],
+    style_classes: &BTreeMap<String, String>,
+) -> (String, Option<String>) {
+    let mut out = String::with_capacity(text.len());
+    let mut voice = None;
+    let mut rest = text;
+
+    while let Some(idx) = rest.find('<') {
+        out.push_str(&rest[..idx]);
+        let Some((tag, tail)) = rest[idx + 1..].split_once('>') else {
+            out.push_str(&rest[idx..]);
+            rest = "";
+            break;
+        };
+        rest = tail;
+
+        if let Some(classes) = tag.strip_prefix('c') {
+            if classes.is_empty() || classes.starts_with('.') || classes.starts_with(' ') {
+                let classes = classes
+                    .split(char::is_whitespace)
+                    .next()
+                    .unwrap_or_default();
+                let color = classes.split('.').find_map(|class| {
+                    class_color(class).or_else(|| {
+                        class_style(class, styles, style_classes)
+                            .map(|s| s.primary_color)
+                            .or_else(|| {
+                                CUE_COLORS
+                                    .iter()
+                                    .find(|(name, _)| *name == class)
+                                    .map(|(_, color)| *color)
+                            })
+                    })
+                });
+                match color {
+                    Some(color) => {
+                        write!(out, "<font color=\"{}\">", write_html_color(color)).unwrap()
+                    }
+                    None => out.push_str("<font>"),
+                }
+                continue;
+            }
+        }
+
+        match tag {
+            "/c" => out.push_str("</font>"),
+            _ if tag.starts_with('v') && (tag.len() == 1 || tag[1..].starts_with([' ', '.'])) => {
+                if voice.is_none() {
+                    let name = tag[1..].split_once(' ').map_or("", |(_, name)| name);
+                    voice = Some(name.trim().to_owned()).filter(|v| !v.is_empty());
+                }
+            }
+            _ => write!(out, "<{tag}>").unwrap(),
+        }
+    }
+
+    out.push_str(rest);
+    (out, voice)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script_with(styles: &[&str]) -> Script<'static> {
+        let mut script = new_script();
+        script.styles = styles
+            .iter()
+            .map(|name| Style {
+                name: name.to_string().into(),
+                bold: true,
+                ..Style::named_default()
+            })
+            .collect();
+        for (idx, style) in styles.iter().enumerate() {
+            let mut event = new_event(
+                Timestamp::from_millis(idx as i64 * 1000),
+                Timestamp::from_millis(idx as i64 * 1000 + 500),
+                "text".to_owned(),
+            );
+            event.style = style.to_string().into();
+            script.events.push(event);
+        }
+        script
+    }
+
+    #[test]
+    fn no_style_block_without_rules() {
+        let mut script = script_with(&[]);
+        script.events.push(new_event(
+            Timestamp::ZERO,
+            Timestamp::from_millis(500),
+            "plain".to_owned(),
+        ));
+        let out = to_string(&script, &ExportOptions::default());
+        assert!(!out.contains("STYLE"), "{out}");
+    }
+
+    #[test]
+    fn style_names_round_trip() {
+        let script = script_with(&["Sign Top"]);
+        let out = to_string(&script, &ExportOptions::default());
+        assert!(
+            out.contains("::cue(.Sign_Top) {\n  /* Sign Top */"),
+            "{out}"
+        );
+
+        let back = parse(&out).unwrap();
+        assert!(back.styles.iter().any(|s| s.name == "Sign Top"));
+        assert_eq!(back.events[0].style, "Sign Top");
+    }
+
+    #[test]
+    fn colliding_names_get_unique_classes() {
+        let script = script_with(&["Sign Top", "Sign_Top"]);
+        let out = to_string(&script, &ExportOptions::default());
+        assert!(
+            out.contains("::cue(.Sign_Top) {\n  /* Sign Top */"),
+            "{out}"
+        );
+        assert!(
+            out.contains("::cue(.Sign_Top_2) {\n  /* Sign_Top */"),
+            "{out}"
+        );
+
+        let back = parse(&out).unwrap();
+        let styles: Vec<&str> = back.events.iter().map(|e| e.style.as_ref()).collect();
+        assert_eq!(styles, ["Sign Top", "Sign_Top"]);
+    }
+}
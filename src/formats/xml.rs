@@ -0,0 +1,322 @@
+use crate::{ParseError, Span};
+
+pub(crate) const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// An element with its namespace resolved. Unprefixed attributes have no namespace.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Element {
+    pub namespace: String,
+    pub name: String,
+    pub attributes: Vec<Attribute>,
+    pub children: Vec<Node>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Attribute {
+    pub namespace: String,
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum Node {
+    Element(Element),
+    Text(String),
+}
+
+impl Element {
+    pub fn is(&self, namespace: &str, name: &str) -> bool {
+        self.namespace == namespace && self.name == name
+    }
+
+    pub fn attribute(&self, namespace: &str, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|a| a.namespace == namespace && a.name == name)
+            .map(|a| a.value.as_str())
+    }
+
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|node| match node {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+
+    pub fn child(&self, namespace: &str, name: &str) -> Option<&Element> {
+        self.elements().find(|e| e.is(namespace, name))
+    }
+}
+
+/// Escapes text for use in element content and double-quoted attributes.
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn span_at(data: &str, offset: usize) -> Span {
+    let before = &data[..offset];
+    let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+    Span {
+        offset,
+        line: before.matches('\n').count() + 1,
+        column: before[line_start..].chars().count() + 1,
+    }
+}
+
+fn error(data: &str, offset: usize, message: &str) -> ParseError {
+    ParseError::InvalidValue {
+        field: "xml",
+        value: message.to_owned(),
+        span: span_at(data, offset),
+    }
+}
+
+fn decode(data: &str, text: &str, offset: usize) -> Result<String, ParseError> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(idx) = rest.find('&') {
+        out.push_str(&rest[..idx]);
+        let Some((entity, tail)) = rest[idx + 1..].split_once(';') else {
+            return Err(error(data, offset, "unterminated entity"));
+        };
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => match entity.strip_prefix('#') {
+                Some(hex) if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16)
+                    .ok()
+                    .and_then(char::from_u32),
+                Some(decimal) => decimal.parse().ok().and_then(char::from_u32),
+                None => None,
+            },
+        };
+        match decoded {
+            Some(c) => out.push(c),
+            None => return Err(error(data, offset, "unknown entity")),
+        }
+        rest = tail;
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn is_name_end(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '/' | '>' | '=')
+}
+
+struct OpenElement {
+    element: Element,
+    qualified_name: String,
+    /// Length of the namespace scope before this element's declarations.
+    scope: usize,
+}
+
+/// Reads a document into its root element. Comments, processing instructions and the doctype
+/// are skipped; CDATA sections become text.
+pub(crate) fn parse(data: &str) -> Result<Element, ParseError> {
+    let mut pos = if data.starts_with('\u{feff}') { 3 } else { 0 };
+    let mut open: Vec<OpenElement> = Vec::new();
+    let mut scope: Vec<(String, String)> = vec![("xml".to_owned(), XML_NAMESPACE.to_owned())];
+    let mut root = None;
+
+    let find = |from: usize, pattern: &str, message: &str| {
+        data[from..]
+            .find(pattern)
+            .map(|idx| from + idx)
+            .ok_or_else(|| error(data, from, message))
+    };
+
+    while pos < data.len() {
+        let rest = &data[pos..];
+
+        if rest.starts_with("<?") {
+            pos = find(pos, "?>", "unterminated processing instruction")? + 2;
+        } else if rest.starts_with("<!--") {
+            pos = find(pos + 4, "-->", "unterminated comment")? + 3;
+        } else if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+            let end = find(pos + 9, "]]>", "unterminated CDATA section")?;
+            let text = &cdata[..end - pos - 9];
+            match open.last_mut() {
+                Some(parent) => push_text(&mut parent.element, text.to_owned()),
+                None => return Err(error(data, pos, "text outside the root element")),
+            }
+            pos = end + 3;
+        } else if rest.starts_with("<!") {
+            // a doctype, possibly with an internal subset in brackets
+            let mut depth = 0;
+            let end = rest.char_indices().find(|(_, c)| {
+                match c {
+                    '[' => depth += 1,
+                    ']' => depth -= 1,
+                    '>' if depth == 0 => return true,
+                    _ => {}
+                }
+                false
+            });
+            pos += end
+                .ok_or_else(|| error(data, pos, "unterminated declaration"))?
+                .0
+                + 1;
+        } else if let Some(tag) = rest.strip_prefix("</") {
+            let name_len = tag.find(is_name_end).unwrap_or(tag.len());
+            let name = &tag[..name_len];
+            let close = find(pos, ">", "unterminated closing tag")?;
+            if !data[pos + 2 + name_len..close].trim().is_empty() {
+                return Err(error(data, pos, "unexpected content in closing tag"));
+            }
+            let element = match open.pop() {
+                Some(element) if element.qualified_name == name => element,
+                _ => return Err(error(data, pos, "mismatched closing tag")),
+            };
+            scope.truncate(element.scope);
+            attach(data, pos, &mut open, &mut root, element.element)?;
+            pos = close + 1;
+        } else if let Some(tag) = rest.strip_prefix('<') {
+            let (element, self_closing, len) = start_tag(data, pos, tag, &mut scope)?;
+            if self_closing {
+                scope.truncate(element.scope);
+                attach(data, pos, &mut open, &mut root, element.element)?;
+            } else {
+                open.push(element);
+            }
+            pos += 1 + len;
+        } else {
+            let end = rest.find('<').unwrap_or(rest.len());
+            let text = &rest[..end];
+            match open.last_mut() {
+                Some(parent) => push_text(&mut parent.element, decode(data, text, pos)?),
+                None if text.trim().is_empty() => {}
+                None => return Err(error(data, pos, "text outside the root element")),
+            }
+            pos += end;
+        }
+    }
+
+    if let Some(element) = open.last() {
+        return Err(error(data, element.element.span.offset, "unclosed element"));
+    }
+    root.ok_or_else(|| error(data, pos, "no root element"))
+}
+
+fn push_text(element: &mut Element, text: String) {
+    match element.children.last_mut() {
+        Some(Node::Text(last)) => last.push_str(&text),
+        _ => element.children.push(Node::Text(text)),
+    }
+}
+
+fn attach(
+    data: &str,
+    pos: usize,
+    open: &mut [OpenElement],
+    root: &mut Option<Element>,
+    element: Element,
+) -> Result<(), ParseError> {
+    match open.last_mut() {
+        Some(parent) => parent.element.children.push(Node::Element(element)),
+        None if root.is_none() => *root = Some(element),
+        None => return Err(error(data, pos, "more than one root element")),
+    }
+    Ok(())
+}
+
+/// Reads a start tag after its `<`. Returns the element, whether it closes itself and the
+/// length of the tag up to and including `>`.
+fn start_tag(
+    data: &str,
+    pos: usize,
+    tag: &str,
+    scope: &mut Vec<(String, String)>,
+) -> Result<(OpenElement, bool, usize), ParseError> {
+    let name_len = tag.find(is_name_end).unwrap_or(tag.len());
+    let qualified_name = &tag[..name_len];
+    if qualified_name.is_empty() {
+        return Err(error(data, pos, "missing element name"));
+    }
+
+    let mut raw = Vec::new();
+    let mut rest = &tag[name_len..];
+    let self_closing = loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix("/>") {
+            rest = after;
+            break true;
+        }
+        if let Some(after) = rest.strip_prefix('>') {
+            rest = after;
+            break false;
+        }
+
+        let offset = data.len() - rest.len();
+        let name_len = rest.find(is_name_end).unwrap_or(rest.len());
+        let name = &rest[..name_len];
+        let after = rest[name_len..].trim_start();
+        let value = after.strip_prefix('=').map(str::trim_start);
+        let (quote, value) = match value.and_then(|v| Some((v.chars().next()?, &v[1..]))) {
+            Some((quote @ ('"' | '\''), value)) if !name.is_empty() => (quote, value),
+            _ => return Err(error(data, offset, "malformed attribute")),
+        };
+        let Some((value, after)) = value.split_once(quote) else {
+            return Err(error(data, offset, "unterminated attribute value"));
+        };
+        raw.push((name, decode(data, value, offset)?));
+        rest = after;
+    };
+
+    let scope_len = scope.len();
+    for (name, value) in &raw {
+        if *name == "xmlns" {
+            scope.push((String::new(), value.clone()));
+        } else if let Some(prefix) = name.strip_prefix("xmlns:") {
+            scope.push((prefix.to_owned(), value.clone()));
+        }
+    }
+    let resolve = |prefix: &str| {
+        scope
+            .iter()
+            .rev()
+            .find(|(p, _)| p == prefix)
+            .map(|(_, namespace)| namespace.clone())
+            .ok_or_else(|| error(data, pos, "undeclared namespace prefix"))
+    };
+
+    let (namespace, name) = match qualified_name.split_once(':') {
+        Some((prefix, name)) => (resolve(prefix)?, name),
+        None => (resolve("").unwrap_or_default(), qualified_name),
+    };
+    let mut attributes = Vec::with_capacity(raw.len());
+    for (qualified, value) in raw {
+        if qualified == "xmlns" || qualified.starts_with("xmlns:") {
+            continue;
+        }
+        let (namespace, name) = match qualified.split_once(':') {
+            Some((prefix, name)) => (resolve(prefix)?, name),
+            None => (String::new(), qualified),
+        };
+        attributes.push(Attribute {
+            namespace,
+            name: name.to_owned(),
+            value,
+        });
+    }
+
+    let element = OpenElement {
+        element: Element {
+            namespace,
+            name: name.to_owned(),
+            attributes,
+            children: Vec::new(),
+            span: span_at(data, pos),
+        },
+        qualified_name: qualified_name.to_owned(),
+        scope: scope_len,
+    };
+    Ok((element, self_closing, tag.len() - rest.len()))
+}
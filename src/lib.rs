@@ -1,85 +1,218 @@
-use std::{array, marker::PhantomData, str::FromStr};
+use std::{array, iter::Peekable, marker::PhantomData, str::FromStr};
 
+use diagnostics::{Diagnostic, Diagnostics, ParseMode, Severity};
 use models::OptionStr;
 
+pub mod convert;
+pub mod cst;
+pub mod diagnostics;
+pub mod error;
+pub mod filter;
+pub mod formats;
+pub mod lint;
 pub mod models;
+pub mod resample;
+pub mod tags;
+pub mod timing;
+pub mod writer;
 
-struct FilteredLines<'a> {
-    lines: std::str::Lines<'a>,
+pub use error::{ParseError, Span};
+pub use models::{script::Script, timestamp::Timestamp};
+
+pub fn parse(data: &str) -> Result<Script<'_>, ParseError> {
+    Script::from_parser(&mut SSAParser::new(data))
+}
+
+pub fn parse_lenient(data: &str) -> Result<(Script<'_>, Vec<Diagnostic>), ParseError> {
+    let mut parser = SSAParser::lenient(data);
+    let script = Script::from_parser(&mut parser)?;
+    Ok((script, parser.diagnostics.take()))
+}
+
+struct SourceLines<'a> {
+    rest: &'a str,
+    offset: usize,
+    line: usize,
 }
 
-impl<'a> Iterator for FilteredLines<'a> {
-    type Item = &'a str;
+impl<'a> Iterator for SourceLines<'a> {
+    type Item = (&'a str, Span);
 
     fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            match self.lines.next() {
-                Some(v) if v.starts_with(';') => continue,
-                Some(v) => return Some(v),
-                None => return None,
-            }
+        if self.rest.is_empty() {
+            return None;
         }
+
+        let (line, rest) = match self.rest.split_once('\n') {
+            Some(v) => v,
+            None => (self.rest, ""),
+        };
+        let span = Span::start_of_line(self.offset, self.line);
+
+        self.offset += self.rest.len() - rest.len();
+        self.line += 1;
+        self.rest = rest;
+
+        Some((line.strip_suffix('\r').unwrap_or(line), span))
     }
 }
 
+pub(crate) fn is_section_header(line: &str) -> bool {
+    line.trim_start_matches('\u{feff}')
+        .trim_start()
+        .starts_with('[')
+}
+
+pub(crate) fn is_comment(line: &str) -> bool {
+    line.starts_with(';')
+}
+
+pub(crate) fn section_title(line: &str) -> Option<&str> {
+    line.trim()
+        .split_once('[')
+        .and_then(|(_, r)| r.split_once(']'))
+        .map(|(l, _)| l)
+}
+
 pub struct SSAParser<'a> {
-    pub(crate) lines: FilteredLines<'a>,
+    pub(crate) lines: Peekable<Box<dyn Iterator<Item = (&'a str, Span)> + 'a>>,
+    pub diagnostics: Diagnostics,
 }
 
 impl<'data> SSAParser<'data> {
     pub fn new(data: &'data str) -> SSAParser<'data> {
+        SSAParser::with_mode(data, ParseMode::Strict)
+    }
+
+    pub fn lenient(data: &'data str) -> SSAParser<'data> {
+        SSAParser::with_mode(data, ParseMode::Lenient)
+    }
+
+    pub fn with_mode(data: &'data str, mode: ParseMode) -> SSAParser<'data> {
+        let lines = SourceLines {
+            rest: data,
+            offset: 0,
+            line: 1,
+        };
+        SSAParser::from_lines(lines, Diagnostics::new(mode))
+    }
+
+    /// A parser over lines that are already split, without their line endings, each with the
+    /// span it starts at.
+    pub(crate) fn from_lines(
+        lines: impl Iterator<Item = (&'data str, Span)> + 'data,
+        diagnostics: Diagnostics,
+    ) -> SSAParser<'data> {
+        let lines: Box<dyn Iterator<Item = _>> = Box::new(lines);
         SSAParser {
-            lines: FilteredLines {
-                lines: data.lines(),
-            },
+            lines: lines.peekable(),
+            diagnostics,
         }
     }
 
-    pub fn section(&mut self) -> Option<RawSectionIterator<'data, '_>> {
-        let title_line = self
-            .lines
-            .next()
-            .map(str::trim)
-            .and_then(|v| v.split_once('['))
-            .and_then(|(_, r)| r.split_once(']'))
-            .map(|(l, _)| l)?;
-        Some(RawSectionIterator {
-            title: title_line,
-            parser: self,
-        })
+    pub fn section(&mut self) -> Result<Option<RawSectionIterator<'data, '_>>, ParseError> {
+        loop {
+            let (line, span) = match self.lines.next() {
+                Some((v, _)) if v.trim().is_empty() || is_comment(v) => continue,
+                Some(v) => v,
+                None => return Ok(None),
+            };
+
+            let Some(title) = section_title(line) else {
+                self.diagnostics
+                    .report(Severity::Error, ParseError::MissingSectionHeader { span })?;
+                while self.lines.next_if(|(v, _)| !is_section_header(v)).is_some() {}
+                continue;
+            };
+
+            return Ok(Some(RawSectionIterator {
+                title,
+                span: span.of_subslice(line, title),
+                parser: self,
+            }));
+        }
     }
 }
 
 pub struct RawSectionIterator<'data, 'borrow> {
     pub title: &'data str,
+    pub span: Span,
     pub parser: &'borrow mut SSAParser<'data>,
 }
 
 impl<'data, 'borrow> RawSectionIterator<'data, 'borrow> {
-    pub fn as_key_value<S: KeyValueSection<'data>>(self) -> Option<S::Output<'data, 'borrow>> {
+    pub fn as_key_value<S: KeyValueSection<'data>>(
+        self,
+    ) -> Result<S::Output<'data, 'borrow>, ParseError> {
         S::parse(KeyValueSectionIter::new(self))
     }
 
     pub fn as_stream_section<const MAX_FIELDS: usize, L: LineItemParser<MAX_FIELDS>>(
         self,
-    ) -> Option<LineStreamSectionIter<'data, 'borrow, MAX_FIELDS, L>> {
+    ) -> Result<LineStreamSectionIter<'data, 'borrow, MAX_FIELDS, L>, ParseError> {
         LineStreamSectionIter::start(self)
     }
 }
 
+impl<'data, 'borrow> RawSectionIterator<'data, 'borrow> {
+    /// Skips blank lines and comments and returns the next line of this section without
+    /// consuming it.
+    fn peek_line(&mut self) -> Option<(&'data str, Span)> {
+        loop {
+            match self.parser.lines.peek() {
+                Some((v, _)) if is_section_header(v) => return None,
+                Some((v, _)) if v.trim().is_empty() || is_comment(v) => {
+                    self.parser.lines.next();
+                }
+                Some(v) => return Some(*v),
+                None => return None,
+            }
+        }
+    }
+
+    pub fn diagnostics(&mut self) -> &mut Diagnostics {
+        &mut self.parser.diagnostics
+    }
+
+    /// Returns the next line of this section as-is, without splitting it into key and value.
+    pub fn next_raw_line(&mut self) -> Option<(&'data str, Span)> {
+        let line = self.peek_line()?;
+        self.parser.lines.next();
+        Some(line)
+    }
+
+    /// Returns the next line if it is uuencoded attachment data, which may start with `;` or
+    /// `[` and so would otherwise pass for a comment or section header.
+    pub fn next_data_line(&mut self) -> Option<(&'data str, Span)> {
+        self.parser
+            .lines
+            .next_if(|(line, _)| models::attachment::is_data(line))
+    }
+}
+
 impl<'data, 'borrow> Iterator for RawSectionIterator<'data, 'borrow> {
-    type Item = (&'data str, &'data str);
+    type Item = Result<(&'data str, &'data str, Span), ParseError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let line = match self.parser.lines.next() {
-            Some(v) if v.trim().is_empty() => return None,
-            Some(v) => v,
-            None => return None,
-        };
-
-        let (lhs, rhs) = line.split_once(':')?;
+        if self.parser.diagnostics.failed() {
+            return None;
+        }
 
-        Some((lhs.trim(), rhs.trim()))
+        loop {
+            let (line, span) = self.peek_line()?;
+            self.parser.lines.next();
+
+            let Some((lhs, rhs)) = line.split_once(':') else {
+                let error = ParseError::MissingKeyValueSeparator { span };
+                match self.parser.diagnostics.report(Severity::Error, error) {
+                    Ok(()) => continue,
+                    Err(e) => return Some(Err(e)),
+                }
+            };
+            let value = rhs.trim();
+
+            return Some(Ok((lhs.trim(), value, span.of_subslice(line, value))));
+        }
     }
 }
 
@@ -92,11 +225,12 @@ pub trait KeyValueSection<'data> {
 
     fn parse<'b>(
         source: KeyValueSectionIter<'data, 'b, Self::Fields>,
-    ) -> Option<Self::Output<'data, 'b>>;
+    ) -> Result<Self::Output<'data, 'b>, ParseError>;
 }
 
 pub struct KeyValueSectionIter<'data, 'borrow, Fields: FromStr> {
     pub title: &'data str,
+    pub span: Span,
     pub parser: RawSectionIterator<'data, 'borrow>,
     pub spooks: PhantomData<Fields>,
 }
@@ -107,31 +241,43 @@ impl<'data, 'borrow, Fields: FromStr> KeyValueSectionIter<'data, 'borrow, Fields
     ) -> KeyValueSectionIter<'data, 'borrow, Fields> {
         Self {
             title: v.title,
+            span: v.span,
             parser: v,
             spooks: PhantomData,
         }
     }
+
+    pub fn diagnostics(&mut self) -> &mut Diagnostics {
+        self.parser.diagnostics()
+    }
 }
 
+/// Yields each entry with its field, or with the key as written if no field matches it.
 impl<'data, 'borrow, Fields: FromStr> Iterator for KeyValueSectionIter<'data, 'borrow, Fields> {
-    type Item = (Fields, &'data str);
+    type Item = Result<(Result<Fields, &'data str>, &'data str, Span), ParseError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         self.parser
-            .find_map(|(k, v)| Fields::from_str(k).ok().map(|k| (k, v)))
+            .next()
+            .map(|line| line.map(|(k, v, span)| (Fields::from_str(k).map_err(|_| k), v, span)))
     }
 }
 
 pub trait LineItemParser<const MAX_FIELDS: usize> {
-    type Fields: FromStr + Default + Copy;
+    type Fields: FromStr + Default + Copy + Into<&'static str>;
     type Item<'a>;
 
+    /// Field order assumed when a section has no Format line in lenient mode.
+    const DEFAULT_FORMAT: &'static str;
+
     fn validate_section_name(name: &str) -> bool;
 
     fn parse_from_fields<'data>(
         key: &'data str,
-        fields: [(Self::Fields, OptionStr<'data>); MAX_FIELDS],
-    ) -> Option<Self::Item<'data>>;
+        fields: [(Self::Fields, OptionStr<'data>, Span); MAX_FIELDS],
+        span: Span,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<Self::Item<'data>, ParseError>;
 }
 
 pub trait LineItem<const MAX_FIELDS: usize> {
@@ -140,30 +286,93 @@ pub trait LineItem<const MAX_FIELDS: usize> {
 
 pub struct LineStreamParser<const MAX_FIELDS: usize, L: LineItemParser<MAX_FIELDS>> {
     field_order: [L::Fields; MAX_FIELDS],
+    /// The Format column each of the known fields is in.
+    columns: [usize; MAX_FIELDS],
+    field_count: usize,
+    column_count: usize,
 }
 
 impl<const MAX_FIELDS: usize, L: LineItemParser<MAX_FIELDS>> LineStreamParser<MAX_FIELDS, L> {
-    pub fn new(format_line: &str) -> Option<LineStreamParser<MAX_FIELDS, L>> {
+    /// Reads a Format line. Columns with an unknown name are reported as warnings and their
+    /// values skipped.
+    pub fn new(
+        format_line: &str,
+        span: Span,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<LineStreamParser<MAX_FIELDS, L>, ParseError> {
         let mut field_order = [L::Fields::default(); MAX_FIELDS];
-        for (idx, field) in format_line.split(',').enumerate() {
-            field_order[idx] = L::Fields::from_str(field.trim()).ok()?;
+        let mut columns = [0; MAX_FIELDS];
+        let mut field_count = 0;
+        let mut column_count = 0;
+
+        for field in format_line.split(',') {
+            let name = field.trim();
+            let span = span.of_subslice(format_line, name);
+
+            match L::Fields::from_str(name) {
+                Ok(_) if field_count == MAX_FIELDS => {
+                    let error = ParseError::TooManyFields {
+                        max: MAX_FIELDS,
+                        span,
+                    };
+                    diagnostics.report(Severity::Error, error)?;
+                    break;
+                }
+                Ok(field) => {
+                    field_order[field_count] = field;
+                    columns[field_count] = column_count;
+                    field_count += 1;
+                }
+                Err(_) => {
+                    let error = ParseError::UnknownFormatField {
+                        name: name.to_owned(),
+                        span,
+                    };
+                    diagnostics.report(Severity::Warning, error)?;
+                }
+            }
+            column_count += 1;
         }
 
-        Some(LineStreamParser { field_order })
+        Ok(LineStreamParser {
+            field_order,
+            columns,
+            field_count,
+            column_count,
+        })
     }
 
-    pub fn parse_line<'data>(&self, key: &'data str, values: &'data str) -> Option<L::Item<'data>> {
-        let mut fields: [(L::Fields, OptionStr<'data>); MAX_FIELDS] =
-            array::from_fn(|idx| (self.field_order[idx], None));
-        for (idx, value) in values.splitn(MAX_FIELDS, ',').enumerate() {
-            fields[idx].1 = Some(value.trim().into());
+    pub fn parse_line<'data>(
+        &self,
+        key: &'data str,
+        values: &'data str,
+        span: Span,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<L::Item<'data>, ParseError> {
+        let end = span.advance(values);
+        let mut fields: [(L::Fields, OptionStr<'data>, Span); MAX_FIELDS] =
+            array::from_fn(|idx| (self.field_order[idx], None, end));
+        let mut idx = 0;
+        for (column, value) in values.splitn(self.column_count, ',').enumerate() {
+            if idx == self.field_count || self.columns[idx] != column {
+                continue;
+            }
+            let value = value.trim();
+            fields[idx].1 = Some(value.into());
+            fields[idx].2 = span.of_subslice(values, value);
+            idx += 1;
         }
 
-        L::parse_from_fields(key, fields)
+        L::parse_from_fields(key, fields, span, diagnostics)
     }
 }
 
-pub struct LineStreamSectionIter<'data, 'borrow, const MAX_FIELDS: usize, L: LineItemParser<MAX_FIELDS>> {
+pub struct LineStreamSectionIter<
+    'data,
+    'borrow,
+    const MAX_FIELDS: usize,
+    L: LineItemParser<MAX_FIELDS>,
+> {
     pub title: &'data str,
     parser: LineStreamParser<MAX_FIELDS, L>,
     inner: RawSectionIterator<'data, 'borrow>,
@@ -174,19 +383,31 @@ impl<'data, 'borrow, const MAX_FIELDS: usize, L: LineItemParser<MAX_FIELDS>>
 {
     pub fn start(
         mut inner: RawSectionIterator<'data, 'borrow>,
-    ) -> Option<LineStreamSectionIter<'data, 'borrow, MAX_FIELDS, L>> {
+    ) -> Result<LineStreamSectionIter<'data, 'borrow, MAX_FIELDS, L>, ParseError> {
         if !L::validate_section_name(inner.title) {
-            return None;
-        }
-
-        let (format_key, format_line) = inner.next()?;
-        if !format_key.eq_ignore_ascii_case("format") {
-            return None;
+            return Err(ParseError::UnexpectedSection {
+                found: inner.title.to_owned(),
+                span: inner.span,
+            });
         }
 
-        let parser = LineStreamParser::new(format_line)?;
+        let has_format_line = inner.peek_line().is_some_and(|(line, _)| {
+            line.split_once(':')
+                .is_some_and(|(key, _)| key.trim().eq_ignore_ascii_case("format"))
+        });
+
+        let parser = if has_format_line {
+            let (_, format_line, span) = inner.next().expect("format line was peeked")?;
+            LineStreamParser::new(format_line, span, inner.diagnostics())?
+        } else {
+            let span = inner.peek_line().map_or(inner.span, |(_, span)| span);
+            inner
+                .diagnostics()
+                .report(Severity::Error, ParseError::MissingFormatLine { span })?;
+            LineStreamParser::new(L::DEFAULT_FORMAT, Span::default(), inner.diagnostics())?
+        };
 
-        Some(LineStreamSectionIter {
+        Ok(LineStreamSectionIter {
             title: inner.title,
             parser,
             inner,
@@ -197,10 +418,14 @@ impl<'data, 'borrow, const MAX_FIELDS: usize, L: LineItemParser<MAX_FIELDS>>
 impl<'data, 'borrow, const MAX_FIELDS: usize, L: LineItemParser<MAX_FIELDS>> Iterator
     for LineStreamSectionIter<'data, 'borrow, MAX_FIELDS, L>
 {
-    type Item = L::Item<'data>;
+    type Item = Result<L::Item<'data>, ParseError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.inner
-            .find_map(|(key, values)| self.parser.parse_line(key, values))
+        self.inner.next().map(|line| {
+            line.and_then(|(key, values, span)| {
+                self.parser
+                    .parse_line(key, values, span, self.inner.diagnostics())
+            })
+        })
     }
 }
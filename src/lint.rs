@@ -0,0 +1,520 @@
+use std::{collections::BTreeMap, fmt};
+
+use serde::{Deserialize, Serialize};
+use strum::{EnumIter, IntoEnumIterator, IntoStaticStr};
+
+use crate::{
+    diagnostics::Severity,
+    formats::play_res,
+    models::events::EventLine,
+    tags::{Num, OverrideTag, Token},
+    timing::index::EventIndex,
+    Script,
+};
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    EnumIter,
+    IntoStaticStr,
+    Serialize,
+    Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum Rule {
+    /// A line uses a style the script does not define.
+    UndefinedStyle,
+    /// Two styles have the same name; renderers only use one of them.
+    DuplicateStyle,
+    /// A line ends before it starts, or when it starts.
+    InvalidDuration,
+    /// Two lines with the same style and actor are shown at once.
+    Overlap,
+    /// A line has more characters per second than [`LintConfig::max_cps`].
+    ReadingSpeed,
+    /// A line of text is longer than [`LintConfig::max_line_length`].
+    LineLength,
+    /// A `{` is not closed, or a `}` was never opened.
+    UnbalancedBraces,
+    /// An override tag is not known or its arguments cannot be read.
+    UnknownTag,
+    /// A `\pos` or `\move` is outside the PlayRes.
+    PositionOutOfBounds,
+    /// `PlayResX` or `PlayResY` is missing, so renderers guess.
+    MissingPlayRes,
+    /// A style or `\fn` uses a font that is neither in [`LintConfig::fonts`] nor embedded in
+    /// the script's `[Fonts]` section.
+    MissingFont,
+}
+
+impl Rule {
+    pub fn default_severity(self) -> Severity {
+        match self {
+            Rule::UndefinedStyle | Rule::MissingFont => Severity::Error,
+            _ => Severity::Warning,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        self.into()
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RuleConfig {
+    pub enabled: bool,
+    /// `None` uses [`Rule::default_severity`].
+    pub severity: Option<Severity>,
+}
+
+impl Default for RuleConfig {
+    fn default() -> Self {
+        RuleConfig {
+            enabled: true,
+            severity: None,
+        }
+    }
+}
+
+/// Which rules run and how. Every field has a default, so a config file only needs the
+/// settings it changes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LintConfig {
+    /// Rules not listed here are enabled with their default severity.
+    pub rules: BTreeMap<Rule, RuleConfig>,
+    /// Characters per second, not counting whitespace.
+    pub max_cps: f64,
+    /// Characters per line of text, between `\N` breaks.
+    pub max_line_length: usize,
+    /// Names of the fonts that will be installed. `None` skips [`Rule::MissingFont`].
+    pub fonts: Option<Vec<String>>,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        LintConfig {
+            rules: BTreeMap::new(),
+            max_cps: 15.0,
+            max_line_length: 42,
+            fonts: None,
+        }
+    }
+}
+
+impl LintConfig {
+    pub fn rule(&self, rule: Rule) -> RuleConfig {
+        self.rules.get(&rule).copied().unwrap_or_default()
+    }
+
+    pub fn set_enabled(&mut self, rule: Rule, enabled: bool) {
+        self.rules.entry(rule).or_default().enabled = enabled;
+    }
+
+    pub fn set_severity(&mut self, rule: Rule, severity: Severity) {
+        self.rules.entry(rule).or_default().severity = Some(severity);
+    }
+}
+
+/// What a diagnostic is about, as an index into the script's styles or events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Location {
+    Script,
+    Style(usize),
+    Event(usize),
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Script => write!(f, "script"),
+            Location::Style(idx) => write!(f, "style {idx}"),
+            Location::Event(idx) => write!(f, "line {idx}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LintDiagnostic {
+    pub rule: Rule,
+    pub severity: Severity,
+    pub location: Location,
+    pub message: String,
+}
+
+impl fmt::Display for LintDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} [{}] {}: {}",
+            self.severity, self.rule, self.location, self.message
+        )
+    }
+}
+
+/// Runs the enabled rules over the script, returning their findings ordered by location.
+/// Comment lines are not checked.
+pub fn lint(script: &Script<'_>, config: &LintConfig) -> Vec<LintDiagnostic> {
+    let mut diagnostics = Vec::new();
+    for rule in Rule::iter() {
+        let rule_config = config.rule(rule);
+        if !rule_config.enabled {
+            continue;
+        }
+        let severity = rule_config
+            .severity
+            .unwrap_or_else(|| rule.default_severity());
+        diagnostics.extend(
+            check(rule, script, config)
+                .into_iter()
+                .map(|(location, message)| LintDiagnostic {
+                    rule,
+                    severity,
+                    location,
+                    message,
+                }),
+        );
+    }
+    diagnostics.sort_by_key(|diagnostic| diagnostic.location);
+    diagnostics
+}
+
+fn check(rule: Rule, script: &Script<'_>, config: &LintConfig) -> Vec<(Location, String)> {
+    match rule {
+        Rule::UndefinedStyle => undefined_styles(script),
+        Rule::DuplicateStyle => duplicate_styles(script),
+        Rule::InvalidDuration => invalid_durations(script),
+        Rule::Overlap => overlaps(script),
+        Rule::ReadingSpeed => reading_speed(script, config.max_cps),
+        Rule::LineLength => line_lengths(script, config.max_line_length),
+        Rule::UnbalancedBraces => unbalanced_braces(script),
+        Rule::UnknownTag => unknown_tags(script),
+        Rule::PositionOutOfBounds => positions_out_of_bounds(script),
+        Rule::MissingPlayRes => missing_play_res(script),
+        Rule::MissingFont => match &config.fonts {
+            Some(fonts) => missing_fonts(script, fonts),
+            None => Vec::new(),
+        },
+    }
+}
+
+fn dialogue<'s, 'a>(
+    script: &'s Script<'a>,
+) -> impl Iterator<Item = (usize, &'s EventLine<'a>)> + 's {
+    script
+        .events
+        .iter()
+        .enumerate()
+        .filter(|(_, event)| !event.is_comment)
+}
+
+/// Every override tag of the line, including those inside `\t`.
+fn tags<'e>(event: &'e EventLine<'_>) -> Vec<OverrideTag<'e>> {
+    fn flatten<'t>(tags: Vec<OverrideTag<'t>>, out: &mut Vec<OverrideTag<'t>>) {
+        for tag in tags {
+            if let OverrideTag::Transform { tags, .. } = tag {
+                flatten(tags, out);
+            } else {
+                out.push(tag);
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    for token in event.tokens() {
+        if let Token::Override(tags) = token {
+            flatten(tags, &mut out);
+        }
+    }
+    out
+}
+
+/// The text shown by the line, split at `\N`, without tags or drawings.
+fn visible_lines(event: &EventLine<'_>) -> Vec<String> {
+    let mut lines = vec![String::new()];
+    let mut drawing = false;
+    for token in event.tokens() {
+        let line = lines.last_mut().unwrap();
+        match token {
+            Token::Text(text) if !drawing => line.push_str(text),
+            Token::HardSpace | Token::SoftBreak => line.push(' '),
+            Token::HardBreak => lines.push(String::new()),
+            Token::Override(tags) => {
+                for tag in tags {
+                    if let OverrideTag::Drawing(level) = tag {
+                        drawing = level > 0;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    lines
+}
+
+fn undefined_styles(script: &Script<'_>) -> Vec<(Location, String)> {
+    // renderers ignore a leading `*` in style names
+    let defined = |name: &str| {
+        let name = name.trim_start_matches('*');
+        script.styles.iter().any(|style| {
+            style
+                .name
+                .trim_start_matches('*')
+                .eq_ignore_ascii_case(name)
+        })
+    };
+    dialogue(script)
+        .filter(|(_, event)| !defined(&event.style))
+        .map(|(idx, event)| {
+            (
+                Location::Event(idx),
+                format!("style `{}` is not defined", event.style),
+            )
+        })
+        .collect()
+}
+
+fn duplicate_styles(script: &Script<'_>) -> Vec<(Location, String)> {
+    let mut found = Vec::new();
+    for (idx, style) in script.styles.iter().enumerate() {
+        let first = script.styles[..idx]
+            .iter()
+            .position(|s| s.name.eq_ignore_ascii_case(&style.name));
+        if let Some(first) = first {
+            found.push((
+                Location::Style(idx),
+                format!("style `{}` is already defined by style {first}", style.name),
+            ));
+        }
+    }
+    found
+}
+
+fn invalid_durations(script: &Script<'_>) -> Vec<(Location, String)> {
+    dialogue(script)
+        .filter_map(|(idx, event)| {
+            let (start, end) = (event.start?, event.end?);
+            let message = if end < start {
+                format!("ends at {end}, before it starts at {start}")
+            } else if end == start {
+                format!("starts and ends at {start}")
+            } else {
+                return None;
+            };
+            Some((Location::Event(idx), message))
+        })
+        .collect()
+}
+
+fn overlaps(script: &Script<'_>) -> Vec<(Location, String)> {
+    let index = EventIndex::new(script);
+    let mut found = Vec::new();
+    for (idx, event) in dialogue(script) {
+        let (Some(start), Some(end)) = (event.start, event.end) else {
+            continue;
+        };
+        let earlier = index.overlapping(start, end).into_iter().filter(|&other| {
+            let other_event = &script.events[other];
+            other < idx && other_event.style == event.style && other_event.name == event.name
+        });
+        for other in earlier {
+            found.push((
+                Location::Event(idx),
+                format!("overlaps line {other}, which has the same style and actor"),
+            ));
+        }
+    }
+    found
+}
+
+fn reading_speed(script: &Script<'_>, max_cps: f64) -> Vec<(Location, String)> {
+    dialogue(script)
+        .filter_map(|(idx, event)| {
+            let duration = (event.end? - event.start?).millis();
+            if duration <= 0 {
+                return None;
+            }
+            let characters = visible_lines(event)
+                .iter()
+                .flat_map(|line| line.chars())
+                .filter(|c| !c.is_whitespace())
+                .count();
+            let cps = characters as f64 * 1000.0 / duration as f64;
+            (cps > max_cps).then(|| {
+                (
+                    Location::Event(idx),
+                    format!(
+                        "{} characters per second, more than {}",
+                        Num(cps),
+                        Num(max_cps)
+                    ),
+                )
+            })
+        })
+        .collect()
+}
+
+fn line_lengths(script: &Script<'_>, max_length: usize) -> Vec<(Location, String)> {
+    let mut found = Vec::new();
+    for (idx, event) in dialogue(script) {
+        for (line, text) in visible_lines(event).iter().enumerate() {
+            let length = text.trim().chars().count();
+            if length > max_length {
+                found.push((
+                    Location::Event(idx),
+                    format!(
+                        "text line {} has {length} characters, more than {max_length}",
+                        line + 1
+                    ),
+                ));
+            }
+        }
+    }
+    found
+}
+
+fn unbalanced_braces(script: &Script<'_>) -> Vec<(Location, String)> {
+    let mut found = Vec::new();
+    for (idx, event) in dialogue(script) {
+        let mut open = None;
+        for (offset, c) in event.text.char_indices() {
+            let problem = match (c, open) {
+                ('{', Some(previous)) => {
+                    format!("`{{` at byte {offset} opens a block inside the one at byte {previous}")
+                }
+                ('{', None) => {
+                    open = Some(offset);
+                    continue;
+                }
+                ('}', None) => format!("`}}` at byte {offset} closes no block"),
+                ('}', Some(_)) => {
+                    open = None;
+                    continue;
+                }
+                _ => continue,
+            };
+            found.push((Location::Event(idx), problem));
+        }
+        if let Some(offset) = open {
+            found.push((
+                Location::Event(idx),
+                format!("`{{` at byte {offset} is never closed"),
+            ));
+        }
+    }
+    found
+}
+
+fn unknown_tags(script: &Script<'_>) -> Vec<(Location, String)> {
+    let mut found = Vec::new();
+    for (idx, event) in dialogue(script) {
+        for tag in tags(event) {
+            if let OverrideTag::Unknown(raw) = tag {
+                found.push((
+                    Location::Event(idx),
+                    format!("`{raw}` is not a known override tag or has invalid arguments"),
+                ));
+            }
+        }
+    }
+    found
+}
+
+fn positions_out_of_bounds(script: &Script<'_>) -> Vec<(Location, String)> {
+    let (width, height) = play_res(script);
+    let outside = |x: f64, y: f64| !(0.0..=width).contains(&x) || !(0.0..=height).contains(&y);
+    let mut found = Vec::new();
+    for (idx, event) in dialogue(script) {
+        for tag in tags(event) {
+            let points = match tag {
+                OverrideTag::Position { x, y } => vec![(x, y)],
+                OverrideTag::Move { x1, y1, x2, y2, .. } => vec![(x1, y1), (x2, y2)],
+                _ => continue,
+            };
+            for (x, y) in points.into_iter().filter(|(x, y)| outside(*x, *y)) {
+                found.push((
+                    Location::Event(idx),
+                    format!(
+                        "`\\{}` to ({},{}) is outside the {width}x{height} PlayRes",
+                        tag.name(),
+                        Num(x),
+                        Num(y)
+                    ),
+                ));
+            }
+        }
+    }
+    found
+}
+
+fn missing_play_res(script: &Script<'_>) -> Vec<(Location, String)> {
+    let info = &script.info.play_info;
+    let (width, height) = play_res(script);
+    [
+        ("PlayResX", info.play_res_x, width),
+        ("PlayResY", info.play_res_y, height),
+    ]
+    .into_iter()
+    .filter(|(_, value, _)| value.is_none())
+    .map(|(key, _, assumed)| {
+        (
+            Location::Script,
+            format!("{key} is not set; renderers assume {assumed}"),
+        )
+    })
+    .collect()
+}
+
+fn missing_fonts(script: &Script<'_>, fonts: &[String]) -> Vec<(Location, String)> {
+    let embedded: Vec<String> = script
+        .fonts
+        .iter()
+        .flat_map(|font| font.font_names())
+        .collect();
+    // a leading `@` asks for the vertical variant of a font
+    let available = |name: &str| {
+        let name = name.trim().trim_start_matches('@');
+        fonts
+            .iter()
+            .chain(&embedded)
+            .any(|font| font.eq_ignore_ascii_case(name))
+    };
+    let mut found = Vec::new();
+    for (idx, style) in script.styles.iter().enumerate() {
+        if !available(&style.font_name) {
+            found.push((
+                Location::Style(idx),
+                format!("font `{}` is not available", style.font_name),
+            ));
+        }
+    }
+    for (idx, event) in dialogue(script) {
+        let mut reported = Vec::new();
+        for tag in tags(event) {
+            if let OverrideTag::FontName(Some(name)) = tag {
+                if !available(name) && !reported.contains(&name) {
+                    reported.push(name);
+                    found.push((
+                        Location::Event(idx),
+                        format!("font `{name}` is not available"),
+                    ));
+                }
+            }
+        }
+    }
+    found
+}
@@ -0,0 +1,336 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+use strum::{EnumString, IntoStaticStr};
+
+use crate::{
+    diagnostics::Diagnostics, error::ValueKind, writer::PROJECT_GARBAGE_FIELDS, KeyValueSection,
+    KeyValueSectionIter, ParseError, Span,
+};
+
+use super::{attachment, events::EventLine, parse_from_str, script::Script, FieldValue, OptionStr};
+
+pub const PROJECT_GARBAGE_TITLE: &str = "Aegisub Project Garbage";
+pub const EXTRADATA_TITLE: &str = "Aegisub Extradata";
+
+#[derive(EnumString, IntoStaticStr, Clone, Copy, Debug, PartialEq, Eq)]
+#[strum(ascii_case_insensitive, use_phf)]
+pub enum ProjectGarbageFields {
+    #[strum(serialize = "Automation Scripts")]
+    AutomationScripts,
+    #[strum(serialize = "Export Filters")]
+    ExportFilters,
+    #[strum(serialize = "Export Encoding")]
+    ExportEncoding,
+    #[strum(serialize = "Last Style Storage")]
+    LastStyleStorage,
+    #[strum(serialize = "Audio File", serialize = "Audio URI")]
+    AudioFile,
+    #[strum(serialize = "Video File")]
+    VideoFile,
+    #[strum(serialize = "Timecodes File")]
+    TimecodesFile,
+    #[strum(serialize = "Keyframes File")]
+    KeyframesFile,
+    #[strum(serialize = "Video AR Mode")]
+    VideoArMode,
+    #[strum(serialize = "Video AR Value")]
+    VideoArValue,
+    #[strum(serialize = "Video Zoom Percent")]
+    VideoZoomPercent,
+    #[strum(serialize = "Scroll Position")]
+    ScrollPosition,
+    #[strum(serialize = "Active Line")]
+    ActiveLine,
+    #[strum(serialize = "Video Position")]
+    VideoPosition,
+}
+
+/// Editor state Aegisub keeps with a script. None of it affects rendering.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct ProjectGarbage<'a> {
+    /// `|`-separated paths of the automation scripts loaded for this script.
+    #[serde(borrow)]
+    pub automation_scripts: OptionStr<'a>,
+    #[serde(borrow)]
+    pub export_filters: OptionStr<'a>,
+    #[serde(borrow)]
+    pub export_encoding: OptionStr<'a>,
+    #[serde(borrow)]
+    pub last_style_storage: OptionStr<'a>,
+    #[serde(borrow)]
+    pub audio_file: OptionStr<'a>,
+    #[serde(borrow)]
+    pub video_file: OptionStr<'a>,
+    #[serde(borrow)]
+    pub timecodes_file: OptionStr<'a>,
+    #[serde(borrow)]
+    pub keyframes_file: OptionStr<'a>,
+    /// 0 for the video's own aspect ratio, 1-3 for 4:3, 16:9 and 2.35, 4 for `video_ar_value`.
+    pub video_ar_mode: Option<i64>,
+    pub video_ar_value: Option<f64>,
+    /// Zoom as a fraction, where 1 is 100%.
+    pub video_zoom: Option<f64>,
+    /// Index of the first line shown in the grid.
+    pub scroll_position: Option<i64>,
+    pub active_line: Option<i64>,
+    /// Frame number the video was last seeked to.
+    pub video_position: Option<i64>,
+    /// Keys no field covers, in the order they appeared.
+    #[serde(borrow)]
+    pub extra: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+}
+
+impl<'data> KeyValueSection<'data> for ProjectGarbage<'data> {
+    type Output<'a, 'b>
+        = ProjectGarbage<'a>
+    where
+        'a: 'b,
+        'data: 'b;
+    type Fields = ProjectGarbageFields;
+
+    fn parse<'b>(
+        mut source: KeyValueSectionIter<'data, 'b, Self::Fields>,
+    ) -> Result<Self::Output<'data, 'b>, ParseError> {
+        if !source.title.eq_ignore_ascii_case(PROJECT_GARBAGE_TITLE) {
+            return Err(ParseError::UnexpectedSection {
+                found: source.title.to_owned(),
+                span: source.span,
+            });
+        }
+
+        let mut section = ProjectGarbage::default();
+
+        while let Some(line) = source.next() {
+            match line? {
+                (Ok(field), value, span) => {
+                    section.parse_field(field, value, span, source.diagnostics())?
+                }
+                (Err(key), value, _) => section.extra.push((key.into(), value.into())),
+            }
+        }
+
+        Ok(section)
+    }
+}
+
+impl<'a> ProjectGarbage<'a> {
+    pub fn is_empty(&self) -> bool {
+        self.extra.is_empty()
+            && PROJECT_GARBAGE_FIELDS
+                .iter()
+                .all(|(field, _)| self.field_text(*field).is_none())
+    }
+
+    pub fn parse_field(
+        &mut self,
+        field: ProjectGarbageFields,
+        value: &'a str,
+        span: Span,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<(), ParseError> {
+        let parsed = FieldValue::new(field, Some(value.into()), span, diagnostics);
+
+        use ProjectGarbageFields::*;
+        match field {
+            AutomationScripts => self.automation_scripts = Some(value.into()),
+            ExportFilters => self.export_filters = Some(value.into()),
+            ExportEncoding => self.export_encoding = Some(value.into()),
+            LastStyleStorage => self.last_style_storage = Some(value.into()),
+            AudioFile => self.audio_file = Some(value.into()),
+            VideoFile => self.video_file = Some(value.into()),
+            TimecodesFile => self.timecodes_file = Some(value.into()),
+            KeyframesFile => self.keyframes_file = Some(value.into()),
+            VideoArMode => {
+                self.video_ar_mode = parsed.parse_optional(ValueKind::Number, parse_from_str)?
+            }
+            VideoArValue => {
+                self.video_ar_value = parsed.parse_optional(ValueKind::Number, parse_from_str)?
+            }
+            VideoZoomPercent => {
+                self.video_zoom = parsed.parse_optional(ValueKind::Number, parse_from_str)?
+            }
+            ScrollPosition => {
+                self.scroll_position = parsed.parse_optional(ValueKind::Number, parse_from_str)?
+            }
+            ActiveLine => {
+                self.active_line = parsed.parse_optional(ValueKind::Number, parse_from_str)?
+            }
+            VideoPosition => {
+                self.video_position = parsed.parse_optional(ValueKind::Number, parse_from_str)?
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A value Aegisub or one of its automation scripts attached to event lines, which refer to it
+/// with a `{=id}` block at the start of their text.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Extradata<'a> {
+    pub id: u32,
+    #[serde(borrow)]
+    pub key: Cow<'a, str>,
+    pub value: Vec<u8>,
+}
+
+impl<'a> Extradata<'a> {
+    /// Reads the value of a `Data:` line: `id,key,value`, where the key is inline-string encoded
+    /// and the value is inline-string encoded after an `e` or uuencoded after a `u`.
+    pub fn parse(value: &'a str, span: Span) -> Result<Extradata<'a>, ParseError> {
+        let invalid = || ParseError::InvalidValue {
+            field: "Data",
+            value: value.to_owned(),
+            span,
+        };
+
+        let mut parts = value.splitn(3, ',');
+        let (Some(id), Some(key), Some(data)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(invalid());
+        };
+        let id = id.trim().parse().map_err(|_| invalid())?;
+        let data = match data.split_at_checked(1) {
+            Some(("e", data)) => inline_string_decode(data).into_owned().into_bytes(),
+            Some(("u", data)) => attachment::decode(data).ok_or_else(invalid)?,
+            _ => return Err(invalid()),
+        };
+
+        Ok(Extradata {
+            id,
+            key: inline_string_decode(key),
+            value: data,
+        })
+    }
+
+    /// The value of the `Data:` line for this entry, picking whichever value encoding is
+    /// shorter as Aegisub does.
+    pub fn encoded(&self) -> String {
+        let key = inline_string_encode(&self.key);
+        let inline = std::str::from_utf8(&self.value)
+            .ok()
+            .map(inline_string_encode)
+            .filter(|inline| 4 * self.value.len() >= 3 * inline.len());
+        match inline {
+            Some(inline) => format!("{},{key},e{inline}", self.id),
+            None => format!("{},{key},u{}", self.id, attachment::encode(&self.value)),
+        }
+    }
+}
+
+/// Escapes control characters and `#`, `,`, `:` and `|` as `#XX`.
+pub fn inline_string_encode(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c <= '\u{1f}' || matches!(c, '#' | ',' | ':' | '|') {
+            out.push_str(&format!("#{:02X}", c as u32));
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Reverses [`inline_string_encode`]. A `#` that is not followed by two hex digits is kept.
+pub fn inline_string_decode(text: &str) -> Cow<'_, str> {
+    if !text.contains('#') {
+        return text.into();
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(idx) = rest.find('#') {
+        out.push_str(&rest[..idx]);
+        let escaped = rest
+            .get(idx + 1..idx + 3)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .filter(u8::is_ascii);
+        match escaped {
+            Some(byte) => {
+                out.push(char::from(byte));
+                rest = &rest[idx + 3..];
+            }
+            None => {
+                out.push('#');
+                rest = &rest[idx + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out.into()
+}
+
+/// Splits the `{=id=id...}` block Aegisub puts at the start of event text from the rest.
+pub fn split_extradata_ids(text: &str) -> (Vec<u32>, &str) {
+    let block = text
+        .strip_prefix("{=")
+        .and_then(|rest| rest.split_once('}'))
+        .and_then(|(ids, rest)| {
+            let ids: Vec<u32> = ids
+                .split('=')
+                .map(|id| {
+                    (!id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()))
+                        .then(|| id.parse().ok())
+                        .flatten()
+                })
+                .collect::<Option<_>>()?;
+            Some((ids, rest))
+        });
+    block.unwrap_or((Vec::new(), text))
+}
+
+impl<'a> EventLine<'a> {
+    /// Ids of the extradata entries this line refers to.
+    pub fn extradata_ids(&self) -> Vec<u32> {
+        split_extradata_ids(&self.text).0
+    }
+
+    /// Replaces the line's `{=id}` block, removing it if `ids` is empty.
+    pub fn set_extradata_ids(&mut self, ids: &[u32]) {
+        let text = split_extradata_ids(&self.text).1;
+        let mut block: String = ids.iter().map(|id| format!("={id}")).collect();
+        if !block.is_empty() {
+            block = format!("{{{block}}}");
+        }
+        self.text = (block + text).into();
+    }
+}
+
+impl<'a> Script<'a> {
+    pub fn extradata(&self, id: u32) -> Option<&Extradata<'a>> {
+        self.extradata.iter().find(|entry| entry.id == id)
+    }
+
+    /// The extradata entries `event` refers to. Ids with no entry are skipped.
+    pub fn extradata_of<'s>(
+        &'s self,
+        event: &EventLine<'_>,
+    ) -> impl Iterator<Item = &'s Extradata<'a>> + 's {
+        event
+            .extradata_ids()
+            .into_iter()
+            .filter_map(|id| self.extradata(id))
+    }
+
+    /// Stores `value` under `key` and returns its id. An identical entry is reused, as Aegisub
+    /// does.
+    pub fn add_extradata(&mut self, key: impl Into<Cow<'a, str>>, value: Vec<u8>) -> u32 {
+        let key = key.into();
+        if let Some(entry) = self
+            .extradata
+            .iter()
+            .find(|entry| entry.key == key && entry.value == value)
+        {
+            return entry.id;
+        }
+
+        let id = self
+            .extradata
+            .iter()
+            .map(|entry| entry.id + 1)
+            .max()
+            .unwrap_or(1);
+        self.extradata.push(Extradata { id, key, value });
+        id
+    }
+}
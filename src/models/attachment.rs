@@ -0,0 +1,301 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ParseError, Span};
+
+/// A file embedded in a `[Fonts]` or `[Graphics]` section.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attachment<'a> {
+    /// The file name, e.g. `arial_0.ttf`.
+    #[serde(borrow)]
+    pub name: Cow<'a, str>,
+    pub data: Vec<u8>,
+}
+
+impl<'a> Attachment<'a> {
+    pub fn new(name: impl Into<Cow<'a, str>>, data: Vec<u8>) -> Attachment<'a> {
+        Attachment {
+            name: name.into(),
+            data,
+        }
+    }
+
+    /// The uuencoded data in lines of at most 80 characters, as it is written in a script.
+    pub fn encoded_lines(&self) -> Vec<String> {
+        let encoded = encode(&self.data);
+        encoded
+            .as_bytes()
+            .chunks(LINE_LENGTH)
+            .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+            .collect()
+    }
+
+    /// The family and full names of an embedded TrueType or OpenType font, read from its
+    /// `name` table. Falls back to the file name without Aegisub's `_<n>` suffix when the
+    /// data isn't a font that can be read.
+    pub fn font_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        match self.data.get(..4) {
+            Some(b"ttcf") => {
+                let count = read_u32(&self.data, 8).unwrap_or(0) as usize;
+                for idx in 0..count {
+                    if let Some(offset) = read_u32(&self.data, 12 + idx * 4) {
+                        sfnt_names(&self.data, offset as usize, &mut names);
+                    }
+                }
+            }
+            Some(_) => sfnt_names(&self.data, 0, &mut names),
+            None => {}
+        }
+        if names.is_empty() {
+            let stem = match self.name.rsplit_once('.') {
+                Some((stem, _)) => stem,
+                None => &self.name,
+            };
+            let stem = match stem.rsplit_once('_') {
+                Some((base, suffix)) if is_font_suffix(suffix) => base,
+                _ => stem,
+            };
+            names.push(stem.to_owned());
+        }
+        names
+    }
+}
+
+/// Aegisub appends `_<n>` to embedded font files; the SSA spec adds `B` and `I` for bold and
+/// italic before the number.
+fn is_font_suffix(suffix: &str) -> bool {
+    let digits = suffix.trim_start_matches(['B', 'I']);
+    !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn read_u16(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// Collects the family (1), full (4) and typographic family (16) names of the font whose
+/// table directory starts at `start`.
+fn sfnt_names(data: &[u8], start: usize, names: &mut Vec<String>) {
+    let Some(tables) = read_u16(data, start + 4) else {
+        return;
+    };
+    let name_table = (0..tables as usize)
+        .map(|idx| start + 12 + idx * 16)
+        .find(|&record| data.get(record..record + 4) == Some(b"name"))
+        .and_then(|record| read_u32(data, record + 8));
+    let Some(table) = name_table.map(|offset| offset as usize) else {
+        return;
+    };
+    let (Some(count), Some(strings)) = (read_u16(data, table + 2), read_u16(data, table + 4))
+    else {
+        return;
+    };
+    for idx in 0..count as usize {
+        let record = table + 6 + idx * 12;
+        let (Some(platform), Some(name_id), Some(length), Some(offset)) = (
+            read_u16(data, record),
+            read_u16(data, record + 6),
+            read_u16(data, record + 8),
+            read_u16(data, record + 10),
+        ) else {
+            return;
+        };
+        if !matches!(name_id, 1 | 4 | 16) {
+            continue;
+        }
+        let at = table + strings as usize + offset as usize;
+        let Some(bytes) = data.get(at..at + length as usize) else {
+            continue;
+        };
+        let name = match platform {
+            // Unicode and Windows names are UTF-16BE
+            0 | 3 => {
+                let units: Vec<u16> = bytes
+                    .chunks_exact(2)
+                    .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                    .collect();
+                String::from_utf16_lossy(&units)
+            }
+            // Mac Roman matches Latin-1 for the ASCII names fonts use in practice
+            1 => bytes.iter().map(|&b| b as char).collect(),
+            _ => continue,
+        };
+        let name = name.trim();
+        if !name.is_empty() && !names.iter().any(|known| known == name) {
+            names.push(name.to_owned());
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttachmentKind {
+    Font,
+    Graphic,
+}
+
+impl AttachmentKind {
+    pub(crate) fn of(title: &str) -> Option<AttachmentKind> {
+        [AttachmentKind::Font, AttachmentKind::Graphic]
+            .into_iter()
+            .find(|kind| title.eq_ignore_ascii_case(kind.section_title()))
+    }
+
+    pub fn section_title(self) -> &'static str {
+        match self {
+            AttachmentKind::Font => "Fonts",
+            AttachmentKind::Graphic => "Graphics",
+        }
+    }
+
+    /// Key of the line that starts each attachment.
+    pub fn key(self) -> &'static str {
+        match self {
+            AttachmentKind::Font => "fontname",
+            AttachmentKind::Graphic => "filename",
+        }
+    }
+
+    /// The file name if `line` starts an attachment.
+    pub(crate) fn entry_name(self, line: &str) -> Option<&str> {
+        let (key, name) = line.split_once(':')?;
+        key.trim()
+            .eq_ignore_ascii_case(self.key())
+            .then(|| name.trim())
+    }
+}
+
+/// Length of every line of data but the last.
+const LINE_LENGTH: usize = 80;
+
+/// Encodes `data` with SSA's uuencode variant: each 6 bits become a character from `!` to `` ` ``,
+/// with no length prefixes or padding. A trailing byte or two becomes two or three characters.
+pub fn encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let src = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let dst = [
+            src[0] >> 2,
+            (src[0] & 0x3) << 4 | src[1] >> 4,
+            (src[1] & 0xf) << 2 | src[2] >> 6,
+            src[2] & 0x3f,
+        ];
+        for value in &dst[..chunk.len() + 1] {
+            out.push(char::from(value + 33));
+        }
+    }
+    out
+}
+
+/// Decodes SSA-uuencoded text. Line breaks are ignored; any other character outside `!` to
+/// `` ` `` makes the text invalid. A lone trailing character carries no whole byte and is dropped.
+pub fn decode(text: &str) -> Option<Vec<u8>> {
+    let values: Vec<u8> = text
+        .bytes()
+        .filter(|b| !matches!(b, b'\r' | b'\n'))
+        .map(|b| is_data_byte(b).then(|| b - 33))
+        .collect::<Option<_>>()?;
+
+    let mut out = Vec::with_capacity(values.len() / 4 * 3 + 2);
+    for chunk in values.chunks(4).filter(|chunk| chunk.len() > 1) {
+        let src = [
+            chunk[0],
+            chunk[1],
+            chunk.get(2).copied().unwrap_or(0),
+            chunk.get(3).copied().unwrap_or(0),
+        ];
+        out.push(src[0] << 2 | src[1] >> 4);
+        if chunk.len() > 2 {
+            out.push((src[1] & 0xf) << 4 | src[2] >> 2);
+        }
+        if chunk.len() > 3 {
+            out.push((src[2] & 0x3) << 6 | src[3]);
+        }
+    }
+    Some(out)
+}
+
+fn is_data_byte(b: u8) -> bool {
+    (b'!'..=b'`').contains(&b)
+}
+
+pub(crate) fn is_data(line: &str) -> bool {
+    !line.is_empty() && line.bytes().all(is_data_byte)
+}
+
+/// Whether `line` is more data for the attachment whose latest line was `previous`: data
+/// follows the line naming the attachment and every full-length line of data.
+pub(crate) fn continues_entry(kind: AttachmentKind, previous: &str, line: &str) -> bool {
+    is_data(line)
+        && (kind.entry_name(previous).is_some()
+            || (is_data(previous) && previous.len() == LINE_LENGTH))
+}
+
+/// Collects the attachments of one section from its lines.
+pub(crate) struct AttachmentReader<'a> {
+    kind: AttachmentKind,
+    previous: &'a str,
+    current: Option<(&'a str, String)>,
+    attachments: Vec<Attachment<'a>>,
+}
+
+impl<'a> AttachmentReader<'a> {
+    pub fn new(kind: AttachmentKind) -> AttachmentReader<'a> {
+        AttachmentReader {
+            kind,
+            previous: "",
+            current: None,
+            attachments: Vec::new(),
+        }
+    }
+
+    /// Whether the next line may be data, even if it looks like a comment or section header.
+    pub fn expects_data(&self) -> bool {
+        self.kind.entry_name(self.previous).is_some()
+            || (is_data(self.previous) && self.previous.len() == LINE_LENGTH)
+    }
+
+    /// Takes the next line of the section. Lines that neither name an attachment nor continue
+    /// the current one's data are rejected with an error for `span`.
+    pub fn push(&mut self, line: &'a str, span: Span) -> Result<(), ParseError> {
+        if continues_entry(self.kind, self.previous, line) {
+            if let Some((_, data)) = &mut self.current {
+                data.push_str(line);
+            }
+        } else if let Some(name) = self.kind.entry_name(line) {
+            self.flush();
+            self.current = Some((name, String::new()));
+        } else {
+            self.previous = "";
+            return Err(match line.split_once(':') {
+                Some((key, _)) => ParseError::UnexpectedLineKind {
+                    key: key.trim().to_owned(),
+                    span,
+                },
+                None => ParseError::MissingKeyValueSeparator { span },
+            });
+        }
+        self.previous = line;
+        Ok(())
+    }
+
+    fn flush(&mut self) {
+        if let Some((name, data)) = self.current.take() {
+            let data = decode(&data).expect("only data lines are collected");
+            self.attachments.push(Attachment::new(name, data));
+        }
+    }
+
+    pub fn finish(mut self) -> Vec<Attachment<'a>> {
+        self.flush();
+        self.attachments
+    }
+}
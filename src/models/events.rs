@@ -1,13 +1,15 @@
-use std::{borrow::Cow, time::Duration};
+use std::borrow::Cow;
 
 use arraystring::ArrayString;
 use serde::{Deserialize, Serialize};
-use strum::EnumString;
+use strum::{EnumString, IntoStaticStr};
 
-use crate::{LineItem, LineItemParser};
-use std::str::FromStr;
-
-use super::OptionStr;
+use super::{parse_from_str, timestamp::Timestamp, FieldValue, OptionStr};
+use crate::{
+    diagnostics::{Diagnostics, Severity},
+    error::ValueKind,
+    LineItem, LineItemParser, ParseError, Span,
+};
 
 pub const MAX_FIELDS: usize = 10;
 
@@ -18,8 +20,8 @@ pub struct EventLine<'a> {
     #[serde(borrow)]
     pub marked: OptionStr<'a>,
     pub layer: Option<i64>,
-    pub start: Option<Duration>,
-    pub end: Option<Duration>,
+    pub start: Option<Timestamp>,
+    pub end: Option<Timestamp>,
     #[serde(borrow)]
     pub style: Cow<'a, str>,
     #[serde(borrow)]
@@ -33,7 +35,7 @@ pub struct EventLine<'a> {
     pub text: Cow<'a, str>,
 }
 
-#[derive(Copy, Clone, EnumString, Debug)]
+#[derive(Copy, Clone, EnumString, IntoStaticStr, Debug)]
 #[strum(ascii_case_insensitive, use_phf)]
 #[repr(i8)]
 pub enum EventFields {
@@ -49,7 +51,7 @@ pub enum EventFields {
     MarginV = 7,
     Effect = 8,
     Text = 9,
-    #[strum(default)]
+    #[strum(disabled)]
     Other(ArrayString<arraystring::typenum::U32>) = 10,
 }
 
@@ -70,53 +72,64 @@ impl<const FIELDS: usize> LineItemParser<FIELDS> for EventLineParser {
 
     type Item<'a> = EventLine<'a>;
 
+    const DEFAULT_FORMAT: &'static str =
+        "Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text";
+
     fn parse_from_fields<'a>(
         key: &'a str,
-        fields: [(Self::Fields, OptionStr<'a>); FIELDS],
-    ) -> Option<Self::Item<'a>> {
-        let mut event = EventLine::default();
-        event.is_comment = key.eq_ignore_ascii_case("Comment");
+        fields: [(Self::Fields, OptionStr<'a>, Span); FIELDS],
+        span: Span,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<Self::Item<'a>, ParseError> {
+        // SSA's Picture, Sound, Movie and Command lines are not rendered by anything. Matroska
+        // blocks carry the fields of a dialogue line without a key.
+        let known = key.is_empty()
+            || key.eq_ignore_ascii_case("Dialogue")
+            || key.eq_ignore_ascii_case("Comment");
+        if !known {
+            let error = ParseError::UnexpectedLineKind {
+                key: key.to_owned(),
+                span,
+            };
+            diagnostics.report(Severity::Error, error)?;
+        }
+
+        let mut event = EventLine {
+            is_comment: key.eq_ignore_ascii_case("Comment"),
+            ..Default::default()
+        };
 
-        for (field, value) in fields {
+        for (field, value, span) in fields {
             use EventFields::*;
+            if let Other(_) = field {
+                continue;
+            }
+            let value = FieldValue::new(field, value, span, diagnostics);
             match field {
-                ReadOrder => event.read_order = value.and_then(|v| u64::from_str(&v).ok()),
-                Layer => event.layer = value.and_then(|v| i64::from_str(&v).ok()),
-                Marked => event.marked = value,
-                Start => event.start = value.and_then(parse_time),
-                End => event.end = value.and_then(parse_time),
-                Style => event.style = value?,
-                Name => event.name = value?,
-                MarginL => event.margin_left = value.and_then(|v| i64::from_str(&v).ok())?,
-                MarginR => event.margin_right = value.and_then(|v| i64::from_str(&v).ok())?,
-                MarginV => event.margin_vertical = value.and_then(|v| i64::from_str(&v).ok())?,
-                Effect => event.effect = value?,
-                Text => event.text = value?,
-                Other(_) => continue,
+                ReadOrder => {
+                    event.read_order = value.parse_optional(ValueKind::Number, parse_from_str)?
+                }
+                Layer => event.layer = value.parse_optional(ValueKind::Number, parse_from_str)?,
+                Marked => event.marked = value.optional(),
+                Start => event.start = value.parse_time()?,
+                End => event.end = value.parse_time()?,
+                Style => event.style = value.required()?,
+                Name => event.name = value.required()?,
+                MarginL => event.margin_left = value.parse(ValueKind::Number, parse_from_str)?,
+                MarginR => event.margin_right = value.parse(ValueKind::Number, parse_from_str)?,
+                MarginV => {
+                    event.margin_vertical = value.parse(ValueKind::Number, parse_from_str)?
+                }
+                Effect => event.effect = value.required()?,
+                Text => event.text = value.required()?,
+                Other(_) => unreachable!(),
             }
         }
 
-        Some(event)
+        Ok(event)
     }
 
     fn validate_section_name(name: &str) -> bool {
         name.eq_ignore_ascii_case("Events")
     }
 }
-
-fn parse_time(s: impl AsRef<str>) -> Option<Duration> {
-    let mut time_split = s
-        .as_ref()
-        .splitn(4, &[':', '.'])
-        .filter_map(|v| u64::from_str(v).ok());
-
-    let (hours, mins, secs, hundredths) = (
-        time_split.next()?,
-        time_split.next()?,
-        time_split.next()?,
-        time_split.next()?,
-    );
-    Some(Duration::from_millis(
-        hours * 3_600_000 + mins * 60_000 + secs * 1_000 + hundredths * 10,
-    ))
-}
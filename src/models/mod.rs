@@ -1,13 +1,124 @@
-use std::{borrow::Cow, str::FromStr};
+use std::{borrow::Cow, fmt, str::FromStr};
 
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    diagnostics::Diagnostics,
+    error::{ParseError, ValueKind},
+    Span,
+};
+
+pub mod aegisub;
+pub mod attachment;
 pub mod events;
+pub mod script;
 pub mod script_info;
 pub mod style;
+pub mod timestamp;
 pub(crate) type OptionStr<'a> = Option<Cow<'a, str>>;
 
-#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub(crate) struct FieldValue<'a, 'd> {
+    name: &'static str,
+    value: OptionStr<'a>,
+    span: Span,
+    diagnostics: &'d mut Diagnostics,
+}
+
+impl<'a, 'd> FieldValue<'a, 'd> {
+    pub(crate) fn new(
+        field: impl Into<&'static str>,
+        value: OptionStr<'a>,
+        span: Span,
+        diagnostics: &'d mut Diagnostics,
+    ) -> Self {
+        FieldValue {
+            name: field.into(),
+            value,
+            span,
+            diagnostics,
+        }
+    }
+
+    fn missing(&self) -> ParseError {
+        ParseError::MissingField {
+            field: self.name,
+            span: self.span,
+        }
+    }
+
+    pub(crate) fn optional(self) -> OptionStr<'a> {
+        self.value
+    }
+
+    pub(crate) fn required(self) -> Result<Cow<'a, str>, ParseError> {
+        let result = self.value.ok_or(ParseError::MissingField {
+            field: self.name,
+            span: self.span,
+        });
+        self.diagnostics.recover(result, Cow::default)
+    }
+
+    pub(crate) fn parse<T: Default>(
+        self,
+        kind: ValueKind,
+        parse: impl Fn(&str) -> Option<T>,
+    ) -> Result<T, ParseError> {
+        self.parse_or(kind, parse, |_| None)
+    }
+
+    /// Like [`FieldValue::parse`], but in lenient mode tries `recover` on an invalid value
+    /// before falling back to the default.
+    pub(crate) fn parse_or<T: Default>(
+        self,
+        kind: ValueKind,
+        parse: impl Fn(&str) -> Option<T>,
+        recover: impl FnOnce(&str) -> Option<T>,
+    ) -> Result<T, ParseError> {
+        let result = match self.value.as_deref() {
+            Some(value) => parse(value).ok_or_else(|| kind.error(self.name, value, self.span)),
+            None => Err(self.missing()),
+        };
+        self.diagnostics.recover(result, || {
+            self.value.as_deref().and_then(recover).unwrap_or_default()
+        })
+    }
+
+    pub(crate) fn parse_optional<T>(
+        self,
+        kind: ValueKind,
+        parse: impl FnOnce(&str) -> Option<T>,
+    ) -> Result<Option<T>, ParseError> {
+        let result = match self.value.as_deref() {
+            None | Some("") => Ok(None),
+            Some(value) => parse(value)
+                .map(Some)
+                .ok_or_else(|| kind.error(self.name, value, self.span)),
+        };
+        self.diagnostics.recover(result, || None)
+    }
+
+    /// An optional event time. Lenient mode reports times that are not in the canonical form and
+    /// keeps them if they can still be read.
+    pub(crate) fn parse_time(self) -> Result<Option<timestamp::Timestamp>, ParseError> {
+        let result = match self.value.as_deref() {
+            None | Some("") => Ok(None),
+            Some(value) => timestamp::Timestamp::parse_strict(value)
+                .map(Some)
+                .ok_or_else(|| ValueKind::Timestamp.error(self.name, value, self.span)),
+        };
+        self.diagnostics.recover(result, || {
+            self.value
+                .as_deref()
+                .and_then(timestamp::Timestamp::parse_lenient)
+        })
+    }
+}
+
+pub(crate) fn parse_from_str<T: FromStr>(value: &str) -> Option<T> {
+    T::from_str(value).ok()
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Color {
     pub alpha: Option<u8>,
     pub red: u8,
@@ -19,21 +130,66 @@ impl FromStr for Color {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let s = s.strip_prefix("&H").ok_or(())?;
-        if s.len() == 8 {
-            Ok(Color {
-                alpha: Some(u8::from_str_radix(&s[0..2], 16).map_err(|_| ())?),
-                blue: u8::from_str_radix(&s[2..4], 16).map_err(|_| ())?,
-                green: u8::from_str_radix(&s[4..6], 16).map_err(|_| ())?,
-                red: u8::from_str_radix(&s[6..8], 16).map_err(|_| ())?,
-            })
-        } else {
-            Ok(Color {
-                alpha: None,
-                blue: u8::from_str_radix(&s[0..2], 16).map_err(|_| ())?,
-                green: u8::from_str_radix(&s[2..4], 16).map_err(|_| ())?,
-                red: u8::from_str_radix(&s[4..6], 16).map_err(|_| ())?,
-            })
+        // SSA v4 writes colours as decimal BGR values
+        if !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) {
+            let [alpha, blue, green, red] = s.parse::<u32>().map_err(|_| ())?.to_be_bytes();
+            return Ok(Color {
+                alpha: (alpha != 0).then_some(alpha),
+                red,
+                green,
+                blue,
+            });
+        }
+
+        let s = s
+            .strip_prefix("&H")
+            .or_else(|| s.strip_prefix("&h"))
+            .ok_or(())?;
+        let s = s.strip_suffix('&').unwrap_or(s);
+        if s.is_empty() || s.len() > 8 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(());
+        }
+
+        let [alpha, blue, green, red] = u32::from_str_radix(s, 16).map_err(|_| ())?.to_be_bytes();
+        Ok(Color {
+            alpha: (s.len() > 6).then_some(alpha),
+            red,
+            green,
+            blue,
+        })
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "&H")?;
+        if let Some(alpha) = self.alpha {
+            write!(f, "{alpha:02X}")?;
+        }
+        write!(f, "{:02X}{:02X}{:02X}", self.blue, self.green, self.red)
+    }
+}
+
+impl Color {
+    pub const fn rgb(red: u8, green: u8, blue: u8) -> Color {
+        Color {
+            alpha: None,
+            red,
+            green,
+            blue,
+        }
+    }
+
+    /// The colour as the decimal `BGR` number SSA v4 styles use, without alpha.
+    pub fn bgr(self) -> u32 {
+        u32::from_be_bytes([0, self.blue, self.green, self.red])
+    }
+
+    /// The colour with an explicit alpha byte, as style lines require.
+    pub fn with_alpha(self) -> Color {
+        Color {
+            alpha: Some(self.alpha.unwrap_or(0)),
+            ..self
         }
     }
 }
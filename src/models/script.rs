@@ -0,0 +1,198 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{diagnostics::Severity, LineItemParser, ParseError, SSAParser, Span};
+
+use super::{
+    aegisub::{Extradata, ProjectGarbage, EXTRADATA_TITLE, PROJECT_GARBAGE_TITLE},
+    attachment::{Attachment, AttachmentKind, AttachmentReader},
+    events::{self, EventLine, EventLineParser},
+    script_info::ScriptInfo,
+    style::{self, Style, StyleParser},
+};
+
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct Script<'a> {
+    #[serde(borrow)]
+    pub info: ScriptInfo<'a>,
+    #[serde(borrow)]
+    pub project: ProjectGarbage<'a>,
+    #[serde(borrow)]
+    pub styles: Vec<Style<'a>>,
+    #[serde(borrow)]
+    pub events: Vec<EventLine<'a>>,
+    #[serde(borrow)]
+    pub fonts: Vec<Attachment<'a>>,
+    #[serde(borrow)]
+    pub graphics: Vec<Attachment<'a>>,
+    #[serde(borrow)]
+    pub extradata: Vec<Extradata<'a>>,
+    #[serde(borrow)]
+    pub extra_sections: Vec<RawSection<'a>>,
+}
+
+/// A section this crate has no model for, kept line by line.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct RawSection<'a> {
+    #[serde(borrow)]
+    pub title: Cow<'a, str>,
+    #[serde(borrow)]
+    pub lines: Vec<Cow<'a, str>>,
+}
+
+impl<'data> Script<'data> {
+    /// Reads every remaining section from `parser`, in whatever order they appear.
+    pub fn from_parser(parser: &mut SSAParser<'data>) -> Result<Script<'data>, ParseError> {
+        let mut script = Script::default();
+
+        while let Some(mut section) = parser.section()? {
+            let title = section.title;
+            match SectionKind::of(title) {
+                SectionKind::ScriptInfo => {
+                    script.info = section.as_key_value::<ScriptInfo<'_>>()?;
+                }
+                SectionKind::Styles => {
+                    for style in
+                        section.as_stream_section::<{ style::MAX_FIELDS }, StyleParser>()?
+                    {
+                        script.styles.push(style?);
+                    }
+                }
+                SectionKind::Events => {
+                    for event in
+                        section.as_stream_section::<{ events::MAX_FIELDS }, EventLineParser>()?
+                    {
+                        script.events.push(event?);
+                    }
+                }
+                SectionKind::ProjectGarbage => {
+                    script.project = section.as_key_value::<ProjectGarbage<'_>>()?;
+                }
+                SectionKind::Extradata => {
+                    while let Some(line) = section.next() {
+                        let (key, value, span) = line?;
+                        match parse_extradata(key, value, span) {
+                            Ok(entry) => script.extradata.push(entry),
+                            Err(error) => section.diagnostics().report(Severity::Error, error)?,
+                        }
+                    }
+                }
+                SectionKind::Attachments(kind) => {
+                    let mut reader = AttachmentReader::new(kind);
+                    loop {
+                        let data = reader
+                            .expects_data()
+                            .then(|| section.next_data_line())
+                            .flatten();
+                        let Some((line, span)) = data.or_else(|| section.next_raw_line()) else {
+                            break;
+                        };
+                        if let Err(error) = reader.push(line, span) {
+                            section.diagnostics().report(Severity::Error, error)?;
+                        }
+                    }
+                    script.attachments_mut(kind).extend(reader.finish());
+                }
+                SectionKind::Other => {
+                    let mut raw = RawSection {
+                        title: title.into(),
+                        lines: Vec::new(),
+                    };
+                    while let Some((line, _)) = section.next_raw_line() {
+                        raw.lines.push(line.into());
+                    }
+                    script.extra_sections.push(raw);
+                }
+            }
+        }
+
+        Ok(script)
+    }
+
+    pub fn attachments(&self, kind: AttachmentKind) -> &[Attachment<'data>] {
+        match kind {
+            AttachmentKind::Font => &self.fonts,
+            AttachmentKind::Graphic => &self.graphics,
+        }
+    }
+
+    pub fn attachments_mut(&mut self, kind: AttachmentKind) -> &mut Vec<Attachment<'data>> {
+        match kind {
+            AttachmentKind::Font => &mut self.fonts,
+            AttachmentKind::Graphic => &mut self.graphics,
+        }
+    }
+
+    pub fn section(&self, title: &str) -> Option<&RawSection<'data>> {
+        self.extra_sections
+            .iter()
+            .find(|section| section.title.eq_ignore_ascii_case(title))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SectionKind {
+    ScriptInfo,
+    Styles,
+    Events,
+    ProjectGarbage,
+    Extradata,
+    Attachments(AttachmentKind),
+    Other,
+}
+
+impl SectionKind {
+    /// Position among the sections in the order Aegisub writes them.
+    pub(crate) fn order(self) -> u8 {
+        match self {
+            SectionKind::ScriptInfo => 0,
+            SectionKind::ProjectGarbage => 1,
+            SectionKind::Styles => 2,
+            SectionKind::Attachments(AttachmentKind::Font) => 3,
+            SectionKind::Attachments(AttachmentKind::Graphic) => 4,
+            SectionKind::Events => 5,
+            SectionKind::Extradata => 6,
+            SectionKind::Other => 7,
+        }
+    }
+
+    pub(crate) fn of(title: &str) -> SectionKind {
+        if is_script_info(title) {
+            SectionKind::ScriptInfo
+        } else if StyleParser::validate_section_name(title) {
+            SectionKind::Styles
+        } else if <EventLineParser as LineItemParser<{ events::MAX_FIELDS }>>::validate_section_name(
+            title,
+        ) {
+            SectionKind::Events
+        } else if title.eq_ignore_ascii_case(PROJECT_GARBAGE_TITLE) {
+            SectionKind::ProjectGarbage
+        } else if title.eq_ignore_ascii_case(EXTRADATA_TITLE) {
+            SectionKind::Extradata
+        } else if let Some(kind) = AttachmentKind::of(title) {
+            SectionKind::Attachments(kind)
+        } else {
+            SectionKind::Other
+        }
+    }
+}
+
+pub(crate) fn is_script_info(title: &str) -> bool {
+    title.eq_ignore_ascii_case("Script Info") || title.eq_ignore_ascii_case("ScriptInfo")
+}
+
+/// Reads a line of the `[Aegisub Extradata]` section.
+pub(crate) fn parse_extradata<'a>(
+    key: &str,
+    value: &'a str,
+    span: Span,
+) -> Result<Extradata<'a>, ParseError> {
+    if !key.eq_ignore_ascii_case("Data") {
+        return Err(ParseError::UnexpectedLineKind {
+            key: key.to_owned(),
+            span,
+        });
+    }
+    Extradata::parse(value, span)
+}
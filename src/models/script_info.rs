@@ -1,12 +1,16 @@
 use serde::{Deserialize, Serialize};
 use std::{borrow::Cow, str::FromStr};
-use strum::{EnumString, FromRepr};
+use strum::{Display, EnumString, FromRepr, IntoStaticStr};
 
-use crate::KeyValueSection;
+use crate::{
+    diagnostics::{Diagnostics, Severity},
+    error::ValueKind,
+    KeyValueSection, ParseError, Span,
+};
 
-use super::OptionStr;
+use super::{parse_from_str, script::is_script_info, FieldValue, OptionStr};
 
-#[derive(EnumString, Clone, Copy)]
+#[derive(EnumString, IntoStaticStr, Clone, Copy, Debug, PartialEq, Eq)]
 #[strum(ascii_case_insensitive, use_phf)]
 pub enum ScriptInfoFields {
     Title,
@@ -38,6 +42,26 @@ pub enum ScriptInfoFields {
     ScaledBorderAndShadow,
     #[strum(serialize = "WrapStyle", serialize = "Wrap Style")]
     WrapStyle,
+    #[strum(serialize = "YCbCr Matrix", serialize = "YCbCrMatrix")]
+    YCbCrMatrix,
+    LayoutResX,
+    LayoutResY,
+    Kerning,
+    Language,
+    #[strum(serialize = "Video File")]
+    VideoFile,
+    #[strum(serialize = "Audio File", serialize = "Audio URI")]
+    AudioFile,
+    #[strum(serialize = "Video Aspect Ratio")]
+    VideoAspectRatio,
+    #[strum(serialize = "Video Zoom")]
+    VideoZoom,
+    #[strum(serialize = "Video Position")]
+    VideoPosition,
+    #[strum(serialize = "Last Style Storage")]
+    LastStyleStorage,
+    #[strum(serialize = "Keyframes File")]
+    KeyframesFile,
 }
 
 #[derive(Default, Debug, Serialize, Deserialize)]
@@ -50,62 +74,184 @@ pub struct ScriptInfo<'a> {
     pub synch_point: OptionStr<'a>,
     #[serde(borrow)]
     pub script_type: OptionStr<'a>,
-    pub collisions: CollisionHandling,
+    pub collisions: Option<CollisionHandling>,
     #[serde(borrow)]
     pub play_info: PlayInfo<'a>,
     pub timer: Option<f64>,
-    pub scaled_border_and_shadow: bool,
+    /// Whether borders and shadows scale with the video rather than the PlayRes. Renderers
+    /// read a missing key as no.
+    pub scaled_border_and_shadow: Option<bool>,
     pub wrap_style: Option<WrapStyle>,
+    /// Colour matrix of the video the script was made for.
+    pub ycbcr_matrix: Option<YCbCrMatrix>,
+    /// libass only: use the font's kerning. libass defaults to yes.
+    pub kerning: Option<bool>,
+    /// libass only: language of the script, as a BCP 47 tag.
+    #[serde(borrow)]
+    pub language: OptionStr<'a>,
+    /// Where older Aegisub versions recorded the video; newer ones use [`ProjectGarbage`].
+    ///
+    /// [`ProjectGarbage`]: super::aegisub::ProjectGarbage
+    #[serde(borrow)]
+    pub video_file: OptionStr<'a>,
+    #[serde(borrow)]
+    pub audio_file: OptionStr<'a>,
+    /// Older Aegisub versions only: `0` for the video's own aspect ratio or `c` followed by
+    /// a custom ratio. Kept as written since the format changed between versions.
+    #[serde(borrow)]
+    pub video_aspect_ratio: OptionStr<'a>,
+    /// Older Aegisub versions only: a zoom level index in some versions and a percentage in
+    /// others, so it is kept as written.
+    #[serde(borrow)]
+    pub video_zoom: OptionStr<'a>,
+    /// Older Aegisub versions only: frame number the video was last seeked to.
+    pub video_position: Option<i64>,
+    /// Older Aegisub versions only: name of the style catalog last used.
+    #[serde(borrow)]
+    pub last_style_storage: OptionStr<'a>,
+    /// Older Aegisub versions only.
+    #[serde(borrow)]
+    pub keyframes_file: OptionStr<'a>,
+    /// Keys no field covers, in the order they appeared.
+    #[serde(borrow)]
+    pub extra: Vec<(Cow<'a, str>, Cow<'a, str>)>,
 }
 
 impl<'data> KeyValueSection<'data> for ScriptInfo<'data> {
-    type Output<'a, 'b> = ScriptInfo<'a> where 'a: 'b, 'data: 'b;
+    type Output<'a, 'b>
+        = ScriptInfo<'a>
+    where
+        'a: 'b,
+        'data: 'b;
     type Fields = ScriptInfoFields;
 
     fn parse<'b>(
-        source: crate::KeyValueSectionIter<'data, 'b, Self::Fields>,
-    ) -> Option<Self::Output<'data, 'b>> {
-        if !(source.title.eq_ignore_ascii_case("Script Info")
-            || source.title.eq_ignore_ascii_case("ScriptInfo"))
-        {
-            return None;
+        mut source: crate::KeyValueSectionIter<'data, 'b, Self::Fields>,
+    ) -> Result<Self::Output<'data, 'b>, ParseError> {
+        if !is_script_info(source.title) {
+            return Err(ParseError::UnexpectedSection {
+                found: source.title.to_owned(),
+                span: source.span,
+            });
         }
 
         let mut section = ScriptInfo::default();
 
-        for (field, value) in source {
-            use ScriptInfoFields::*;
-            match field {
-                Title => section.title = value.into(),
-                OriginalScript => section.authors.script = Some(value.into()),
-                OriginalTranslation => section.authors.translation = Some(value.into()),
-                OriginalEditing => section.authors.editing = Some(value.into()),
-                OriginalTiming => section.authors.timing = Some(value.into()),
-                SynchPoint => section.synch_point = Some(value.into()),
-                ScriptUpdatedBy => section.authors.updated_by = Some(value.into()),
-                UpdateDetails => section.authors.update_details = Some(value.into()),
-                ScriptType => section.script_type = Some(value.into()),
-                Collisions => {
-                    if let Ok(col) = CollisionHandling::from_str(value) {
-                        section.collisions = col;
-                    }
+        while let Some(line) = source.next() {
+            match line? {
+                (Ok(field), value, span) => {
+                    section.parse_field(field, value, span, source.diagnostics())?
                 }
-                PlayResX => section.play_info.play_res_x = value.parse().ok(),
-                PlayResY => section.play_info.play_res_y = value.parse().ok(),
-                PlayDepth => section.play_info.play_depth = Some(value.into()),
-                Timer => section.timer = value.parse().ok(),
-                ScaledBorderAndShadow => {
-                    section.scaled_border_and_shadow = value.eq_ignore_ascii_case("yes")
+                (Err(key), value, _) => section.extra.push((key.into(), value.into())),
+            }
+        }
+
+        Ok(section)
+    }
+}
+
+impl<'a> ScriptInfo<'a> {
+    /// Whether `ScriptType` marks the script as SSA v4 rather than ASS (v4+).
+    pub fn is_ssa(&self) -> bool {
+        self.script_type
+            .as_deref()
+            .is_some_and(|t| t.trim().eq_ignore_ascii_case("v4.00"))
+    }
+
+    pub fn parse_field(
+        &mut self,
+        field: ScriptInfoFields,
+        value: &'a str,
+        span: Span,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<(), ParseError> {
+        let parsed = FieldValue::new(field, Some(value.into()), span, diagnostics);
+
+        use ScriptInfoFields::*;
+        match field {
+            Title => self.title = value.into(),
+            OriginalScript => self.authors.script = Some(value.into()),
+            OriginalTranslation => self.authors.translation = Some(value.into()),
+            OriginalEditing => self.authors.editing = Some(value.into()),
+            OriginalTiming => self.authors.timing = Some(value.into()),
+            SynchPoint => self.synch_point = Some(value.into()),
+            ScriptUpdatedBy => self.authors.updated_by = Some(value.into()),
+            UpdateDetails => self.authors.update_details = Some(value.into()),
+            ScriptType => self.script_type = Some(value.into()),
+            Collisions => {
+                self.collisions = parsed.parse_optional(ValueKind::Other, parse_from_str)?;
+                if let Some(CollisionHandling::Other(_)) = self.collisions {
+                    warn_unknown(field, value, span, diagnostics)?;
                 }
-                WrapStyle => {
-                    section.wrap_style = u8::from_str(value)
-                        .ok()
-                        .and_then(self::WrapStyle::from_repr)
+            }
+            PlayResX => {
+                self.play_info.play_res_x =
+                    parsed.parse_optional(ValueKind::Number, parse_from_str)?
+            }
+            PlayResY => {
+                self.play_info.play_res_y =
+                    parsed.parse_optional(ValueKind::Number, parse_from_str)?
+            }
+            PlayDepth => self.play_info.play_depth = Some(value.into()),
+            Timer => self.timer = parsed.parse_optional(ValueKind::Number, parse_from_str)?,
+            ScaledBorderAndShadow => {
+                self.scaled_border_and_shadow =
+                    parsed.parse_optional(ValueKind::Boolean, parse_yes_no)?
+            }
+            WrapStyle => {
+                self.wrap_style = parsed.parse_optional(ValueKind::Other, |v| {
+                    u8::from_str(v).ok().and_then(self::WrapStyle::from_repr)
+                })?
+            }
+            YCbCrMatrix => {
+                self.ycbcr_matrix = parsed.parse_optional(ValueKind::Other, parse_from_str)?;
+                if let Some(self::YCbCrMatrix::Other(_)) = self.ycbcr_matrix {
+                    warn_unknown(field, value, span, diagnostics)?;
                 }
             }
+            LayoutResX => {
+                self.play_info.layout_res_x =
+                    parsed.parse_optional(ValueKind::Number, parse_from_str)?
+            }
+            LayoutResY => {
+                self.play_info.layout_res_y =
+                    parsed.parse_optional(ValueKind::Number, parse_from_str)?
+            }
+            Kerning => self.kerning = parsed.parse_optional(ValueKind::Boolean, parse_yes_no)?,
+            Language => self.language = Some(value.into()),
+            VideoFile => self.video_file = Some(value.into()),
+            AudioFile => self.audio_file = Some(value.into()),
+            VideoAspectRatio => self.video_aspect_ratio = Some(value.into()),
+            VideoZoom => self.video_zoom = Some(value.into()),
+            VideoPosition => {
+                self.video_position = parsed.parse_optional(ValueKind::Number, parse_from_str)?
+            }
+            LastStyleStorage => self.last_style_storage = Some(value.into()),
+            KeyframesFile => self.keyframes_file = Some(value.into()),
         }
 
-        Some(section)
+        Ok(())
+    }
+}
+
+/// A value newer renderers may know, kept as written so it round-trips.
+fn warn_unknown(
+    field: ScriptInfoFields,
+    value: &str,
+    span: Span,
+    diagnostics: &mut Diagnostics,
+) -> Result<(), ParseError> {
+    let error = ValueKind::Other.error(field.into(), value, span);
+    diagnostics.report(Severity::Warning, error)
+}
+
+fn parse_yes_no(v: &str) -> Option<bool> {
+    if v.eq_ignore_ascii_case("yes") {
+        Some(true)
+    } else if v.eq_ignore_ascii_case("no") {
+        Some(false)
+    } else {
+        None
     }
 }
 
@@ -131,18 +277,25 @@ pub struct PlayInfo<'a> {
     pub play_res_y: Option<i64>,
     #[serde(borrow)]
     pub play_depth: OptionStr<'a>,
+    /// libass only: the storage resolution the script was laid out for, used for aspect ratio
+    /// correction instead of the video's.
+    pub layout_res_x: Option<i64>,
+    pub layout_res_y: Option<i64>,
 }
 
-#[derive(EnumString, Debug, Serialize, Deserialize)]
+#[derive(EnumString, Display, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[strum(ascii_case_insensitive)]
 #[derive(Default)]
 pub enum CollisionHandling {
     #[default]
     Normal,
     Reverse,
+    /// A value this crate doesn't know, as written.
+    #[strum(default)]
+    Other(String),
 }
 
-#[derive(FromRepr, Debug, Serialize, Deserialize)]
+#[derive(FromRepr, Clone, Copy, Debug, Serialize, Deserialize)]
 #[repr(u8)]
 #[derive(Default)]
 pub enum WrapStyle {
@@ -152,3 +305,30 @@ pub enum WrapStyle {
     NoWrapping = 2,
     WiderLowerLine = 3,
 }
+
+/// The `YCbCr Matrix` of a script: `TV` or `PC` range with a BT.601, BT.709, FCC or
+/// SMPTE 240M matrix, or `None` to use colours as written.
+#[derive(EnumString, Display, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[strum(ascii_case_insensitive)]
+pub enum YCbCrMatrix {
+    None,
+    #[strum(serialize = "TV.601")]
+    Tv601,
+    #[strum(serialize = "PC.601")]
+    Pc601,
+    #[strum(serialize = "TV.709")]
+    Tv709,
+    #[strum(serialize = "PC.709")]
+    Pc709,
+    #[strum(serialize = "TV.FCC")]
+    TvFcc,
+    #[strum(serialize = "PC.FCC")]
+    PcFcc,
+    #[strum(serialize = "TV.240M")]
+    Tv240m,
+    #[strum(serialize = "PC.240M")]
+    Pc240m,
+    /// A matrix this crate doesn't know, as written. Colours are not converted from or to it.
+    #[strum(default)]
+    Other(String),
+}
@@ -1,12 +1,16 @@
-use std::{borrow::Cow, str::FromStr};
+use std::borrow::Cow;
 
 use arraystring::ArrayString;
 use serde::{Deserialize, Serialize};
-use strum::EnumString;
+use strum::{EnumString, IntoStaticStr};
 
-use crate::{LineItem, LineItemParser};
+use crate::{
+    diagnostics::{Diagnostics, Severity},
+    error::ValueKind,
+    LineItem, LineItemParser, ParseError, Span,
+};
 
-use super::{Color, OptionStr};
+use super::{parse_from_str, Color, FieldValue, OptionStr};
 
 pub const MAX_FIELDS: usize = 23;
 
@@ -16,7 +20,7 @@ pub struct Style<'a> {
     pub name: Cow<'a, str>,
     #[serde(borrow)]
     pub font_name: Cow<'a, str>,
-    pub font_size: i64,
+    pub font_size: f64,
     pub primary_color: Color,
     pub secondary_color: Color,
     pub outline_color: Option<Color>,
@@ -27,20 +31,22 @@ pub struct Style<'a> {
     pub strikeout: Option<bool>,
     pub scale_x: Option<f64>,
     pub scale_y: Option<f64>,
-    pub spacing: Option<i64>,
+    pub spacing: Option<f64>,
     pub angle: Option<f64>,
     pub border_style: i64,
-    pub outline: i64,
-    pub shadow: i64,
+    pub outline: f64,
+    pub shadow: f64,
     pub alignment: i64,
     pub margin_left: i64,
     pub margin_right: i64,
     pub margin_vertical: i64,
     #[serde(borrow)]
     pub encoding: OptionStr<'a>,
+    /// SSA v4 transparency for the whole style; v4+ styles carry alpha in each colour instead.
+    pub alpha_level: Option<i64>,
 }
 
-#[derive(Copy, Clone, EnumString, Debug)]
+#[derive(Copy, Clone, EnumString, IntoStaticStr, Debug)]
 #[strum(ascii_case_insensitive, use_phf)]
 #[repr(u8)]
 pub enum StyleFields {
@@ -52,7 +58,7 @@ pub enum StyleFields {
     #[strum(serialize = "SecondaryColour", serialize = "SecondaryColor")]
     SecondaryColor = 4,
     #[strum(
-        serialize = "OutlineColour",
+        to_string = "OutlineColour",
         serialize = "OutlineColor",
         serialize = "TertiaryColour"
     )]
@@ -62,6 +68,7 @@ pub enum StyleFields {
     Bold = 7,
     Italic = 8,
     Underline = 9,
+    #[strum(to_string = "StrikeOut")]
     Strikeout = 10,
     ScaleX = 11,
     ScaleY = 12,
@@ -75,8 +82,9 @@ pub enum StyleFields {
     MarginR = 20,
     MarginV = 21,
     Encoding = 22,
-    #[strum(default)]
-    Other(ArrayString<arraystring::typenum::U32>) = 23,
+    AlphaLevel = 23,
+    #[strum(disabled)]
+    Other(ArrayString<arraystring::typenum::U32>) = 24,
 }
 
 impl Default for StyleFields {
@@ -85,6 +93,29 @@ impl Default for StyleFields {
     }
 }
 
+impl<'a> Style<'a> {
+    /// The classic `Default` style: white 20pt Arial with a 2px black outline and shadow,
+    /// bottom centre.
+    pub fn named_default() -> Style<'a> {
+        Style {
+            name: "Default".into(),
+            font_name: "Arial".into(),
+            font_size: 20.0,
+            primary_color: Color::rgb(255, 255, 255),
+            secondary_color: Color::rgb(255, 0, 0),
+            border_style: 1,
+            outline: 2.0,
+            shadow: 2.0,
+            alignment: 2,
+            margin_left: 10,
+            margin_right: 10,
+            margin_vertical: 10,
+            encoding: Some("1".into()),
+            ..Default::default()
+        }
+    }
+}
+
 pub struct StyleParser;
 
 impl<'a> LineItem<MAX_FIELDS> for Style<'a> {
@@ -96,51 +127,87 @@ impl LineItemParser<MAX_FIELDS> for StyleParser {
 
     type Item<'a> = Style<'a>;
 
+    const DEFAULT_FORMAT: &'static str = "Name, Fontname, Fontsize, PrimaryColour, \
+        SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, \
+        ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, \
+        MarginV, Encoding";
+
     fn parse_from_fields<'a>(
         key: &'a str,
-        fields: [(Self::Fields, OptionStr<'a>); MAX_FIELDS],
-    ) -> Option<Self::Item<'a>> {
+        fields: [(Self::Fields, OptionStr<'a>, Span); MAX_FIELDS],
+        span: Span,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<Self::Item<'a>, ParseError> {
         if !key.eq_ignore_ascii_case("Style") {
-            return None;
+            let error = ParseError::UnexpectedLineKind {
+                key: key.to_owned(),
+                span,
+            };
+            diagnostics.report(Severity::Error, error)?;
         }
 
         let mut style = Style::default();
 
-        for (field, value) in fields {
+        for (field, value, span) in fields {
             use StyleFields::*;
+            if let Other(_) = field {
+                continue;
+            }
+            let value = FieldValue::new(field, value, span, diagnostics);
             match field {
-                Name => style.name = value?,
-                Fontname => style.font_name = value?,
-                Fontsize => style.font_size = value.and_then(|v| v.parse().ok())?,
+                Name => style.name = value.required()?,
+                Fontname => style.font_name = value.required()?,
+                Fontsize => style.font_size = value.parse(ValueKind::Number, parse_from_str)?,
                 PrimaryColor => {
-                    style.primary_color = value.and_then(|v| Color::from_str(&v).ok())?
+                    style.primary_color = value.parse(ValueKind::Color, parse_from_str)?
                 }
                 SecondaryColor => {
-                    style.secondary_color = value.and_then(|v| Color::from_str(&v).ok())?
+                    style.secondary_color = value.parse(ValueKind::Color, parse_from_str)?
+                }
+                OutlineColor => {
+                    style.outline_color = value.parse_optional(ValueKind::Color, parse_from_str)?
+                }
+                BackColor => style.back_color = value.parse(ValueKind::Color, parse_from_str)?,
+                Bold => style.bold = value.parse_or(ValueKind::Boolean, bool_from_int, nonzero)?,
+                Italic => {
+                    style.italic = value.parse_or(ValueKind::Boolean, bool_from_int, nonzero)?
+                }
+                Underline => {
+                    style.underline = value.parse_optional(ValueKind::Boolean, bool_from_int)?
                 }
-                OutlineColor => style.outline_color = value.and_then(|v| Color::from_str(&v).ok()),
-                BackColor => style.back_color = value.and_then(|v| Color::from_str(&v).ok())?,
-                Bold => style.bold = value.and_then(bool_from_int)?,
-                Italic => style.italic = value.and_then(bool_from_int)?,
-                Underline => style.underline = value.and_then(bool_from_int),
-                Strikeout => style.strikeout = value.and_then(bool_from_int),
-                ScaleX => style.scale_x = value.and_then(|v| f64::from_str(&v).ok()),
-                ScaleY => style.scale_y = value.and_then(|v| f64::from_str(&v).ok()),
-                Spacing => style.spacing = value.and_then(|v| i64::from_str(&v).ok()),
-                Angle => style.angle = value.and_then(|v| f64::from_str(&v).ok()),
-                BorderStyle => style.border_style = value.and_then(|v| i64::from_str(&v).ok())?,
-                Outline => style.outline = value.and_then(|v| i64::from_str(&v).ok())?,
-                Shadow => style.shadow = value.and_then(|v| i64::from_str(&v).ok())?,
-                Alignment => style.alignment = value.and_then(|v| i64::from_str(&v).ok())?,
-                MarginL => style.margin_left = value.and_then(|v| i64::from_str(&v).ok())?,
-                MarginR => style.margin_right = value.and_then(|v| i64::from_str(&v).ok())?,
-                MarginV => style.margin_vertical = value.and_then(|v| i64::from_str(&v).ok())?,
-                Encoding => style.encoding = value,
-                Other(_) => continue,
+                Strikeout => {
+                    style.strikeout = value.parse_optional(ValueKind::Boolean, bool_from_int)?
+                }
+                ScaleX => {
+                    style.scale_x = value.parse_optional(ValueKind::Number, parse_from_str)?
+                }
+                ScaleY => {
+                    style.scale_y = value.parse_optional(ValueKind::Number, parse_from_str)?
+                }
+                Spacing => {
+                    style.spacing = value.parse_optional(ValueKind::Number, parse_from_str)?
+                }
+                Angle => style.angle = value.parse_optional(ValueKind::Number, parse_from_str)?,
+                BorderStyle => {
+                    style.border_style = value.parse(ValueKind::Number, parse_from_str)?
+                }
+                Outline => style.outline = value.parse(ValueKind::Number, parse_from_str)?,
+                Shadow => style.shadow = value.parse(ValueKind::Number, parse_from_str)?,
+                Alignment => style.alignment = value.parse(ValueKind::Number, parse_from_str)?,
+                MarginL => style.margin_left = value.parse(ValueKind::Number, parse_from_str)?,
+                MarginR => style.margin_right = value.parse(ValueKind::Number, parse_from_str)?,
+                MarginV => {
+                    style.margin_vertical = value.parse(ValueKind::Number, parse_from_str)?
+                }
+                Encoding => style.encoding = value.optional(),
+                AlphaLevel => {
+                    style.alpha_level = value.parse_optional(ValueKind::Number, parse_from_str)?
+                }
+                Other(_) => unreachable!(),
             }
         }
 
-        Some(style)
+        Ok(style)
     }
 
     fn validate_section_name(name: &str) -> bool {
@@ -150,10 +217,14 @@ impl LineItemParser<MAX_FIELDS> for StyleParser {
     }
 }
 
-fn bool_from_int(v: impl AsRef<str>) -> Option<bool> {
-    match v.as_ref() {
+fn bool_from_int(v: &str) -> Option<bool> {
+    match v {
         "-1" => Some(true),
         "0" => Some(false),
         _ => None,
     }
 }
+
+fn nonzero(v: &str) -> Option<bool> {
+    v.parse::<i64>().ok().map(|v| v != 0)
+}
@@ -0,0 +1,330 @@
+use std::{
+    fmt,
+    ops::{Add, AddAssign, Neg, Sub, SubAssign},
+    str::FromStr,
+    time::Duration,
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// An event time in centiseconds, the precision ASS stores times with. Can be negative.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(pub i64);
+
+impl Timestamp {
+    pub const ZERO: Timestamp = Timestamp(0);
+
+    pub const fn from_centis(centis: i64) -> Timestamp {
+        Timestamp(centis)
+    }
+
+    /// Rounds to the nearest centisecond, halves away from zero.
+    pub const fn from_millis(millis: i64) -> Timestamp {
+        let rounded = if millis < 0 { millis - 5 } else { millis + 5 };
+        Timestamp(rounded / 10)
+    }
+
+    pub const fn centis(self) -> i64 {
+        self.0
+    }
+
+    pub const fn millis(self) -> i64 {
+        self.0.saturating_mul(10)
+    }
+
+    pub const fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    /// `None` for negative times.
+    pub fn to_duration(self) -> Option<Duration> {
+        u64::try_from(self.0)
+            .ok()
+            .map(|cs| Duration::from_millis(cs.saturating_mul(10)))
+    }
+
+    /// Moves the time by `delta`, stopping at zero instead of going negative.
+    pub fn shift(self, delta: Timestamp) -> Timestamp {
+        (self + delta).max(Timestamp::ZERO)
+    }
+
+    /// Parses the canonical `H:MM:SS.cc` form, optionally negative. Fractions with more than two
+    /// digits are rounded to centiseconds.
+    pub fn parse_strict(s: &str) -> Option<Timestamp> {
+        let (negative, s) = match s.strip_prefix('-') {
+            Some(s) => (true, s),
+            None => (false, s),
+        };
+        let (hms, fraction) = s.split_once('.')?;
+        let mut parts = hms.split(':');
+        let (hours, minutes, seconds) = (parts.next()?, parts.next()?, parts.next()?);
+        if parts.next().is_some()
+            || hours.is_empty()
+            || minutes.len() != 2
+            || seconds.len() != 2
+            || fraction.is_empty()
+        {
+            return None;
+        }
+
+        let minutes = digits(minutes).filter(|v| *v < 60)?;
+        let seconds = digits(seconds).filter(|v| *v < 60)?;
+        let centis = hours_to_centis(digits(hours)?, minutes, seconds, fraction_centis(fraction)?)?;
+        Some(Timestamp(if negative { -centis } else { centis }))
+    }
+
+    /// Accepts the malformed times players tolerate: surrounding whitespace, missing hour or
+    /// minute fields, a missing fraction, `,` as the decimal separator and out of range minutes
+    /// or seconds, which carry over.
+    pub fn parse_lenient(s: &str) -> Option<Timestamp> {
+        let s = s.trim();
+        let (negative, s) = match s.strip_prefix('-') {
+            Some(s) => (true, s.trim_start()),
+            None => (false, s),
+        };
+        let (hms, fraction) = match s.split_once(['.', ',']) {
+            Some((hms, fraction)) => (hms, fraction.trim()),
+            None => (s, "0"),
+        };
+
+        let mut fields = [0u64; 3];
+        let parts: Vec<&str> = hms.split(':').map(str::trim).collect();
+        if parts.len() > fields.len() {
+            return None;
+        }
+        for (slot, part) in fields[3 - parts.len()..].iter_mut().zip(&parts) {
+            *slot = if part.is_empty() { 0 } else { digits(part)? };
+        }
+
+        let fraction = if fraction.is_empty() {
+            0
+        } else {
+            fraction_centis(fraction)?
+        };
+        let centis = hours_to_centis(fields[0], fields[1], fields[2], fraction)?;
+        Some(Timestamp(if negative { -centis } else { centis }))
+    }
+}
+
+fn digits(s: &str) -> Option<u64> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// The fractional part of a second as centiseconds, rounded half up.
+fn fraction_centis(s: &str) -> Option<u64> {
+    if !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let padded = |idx: usize| s.as_bytes().get(idx).map_or(0, |b| u64::from(b - b'0'));
+    Some(padded(0) * 10 + padded(1) + u64::from(padded(2) >= 5))
+}
+
+fn hours_to_centis(hours: u64, minutes: u64, seconds: u64, centis: u64) -> Option<i64> {
+    let total = hours
+        .checked_mul(360_000)?
+        .checked_add(minutes.checked_mul(6_000)?)?
+        .checked_add(seconds.checked_mul(100)?)?
+        .checked_add(centis)?;
+    i64::try_from(total).ok()
+}
+
+impl FromStr for Timestamp {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Timestamp::parse_strict(s).ok_or(())
+    }
+}
+
+/// `H:MM:SS.cc`, with a leading `-` for negative times. ASS has no negative times, so the
+/// writer clamps them to zero.
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_negative() {
+            f.write_str("-")?;
+        }
+        let cs = self.0.unsigned_abs();
+        write!(
+            f,
+            "{}:{:02}:{:02}.{:02}",
+            cs / 360_000,
+            cs / 6_000 % 60,
+            cs / 100 % 60,
+            cs % 100
+        )
+    }
+}
+
+impl From<Duration> for Timestamp {
+    fn from(value: Duration) -> Self {
+        Timestamp::from_millis(i64::try_from(value.as_millis()).unwrap_or(i64::MAX - 5))
+    }
+}
+
+impl Add for Timestamp {
+    type Output = Timestamp;
+
+    fn add(self, rhs: Timestamp) -> Timestamp {
+        Timestamp(self.0.saturating_add(rhs.0))
+    }
+}
+
+impl Sub for Timestamp {
+    type Output = Timestamp;
+
+    fn sub(self, rhs: Timestamp) -> Timestamp {
+        Timestamp(self.0.saturating_sub(rhs.0))
+    }
+}
+
+impl AddAssign for Timestamp {
+    fn add_assign(&mut self, rhs: Timestamp) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for Timestamp {
+    fn sub_assign(&mut self, rhs: Timestamp) {
+        *self = *self - rhs;
+    }
+}
+
+impl Neg for Timestamp {
+    type Output = Timestamp;
+
+    fn neg(self) -> Timestamp {
+        Timestamp(self.0.saturating_neg())
+    }
+}
+
+/// Written as the `H:MM:SS.cc` string; read from that string or from a number of centiseconds.
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = Timestamp;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a H:MM:SS.cc timestamp or a number of centiseconds")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Timestamp, E> {
+                Timestamp::parse_lenient(v)
+                    .ok_or_else(|| E::invalid_value(de::Unexpected::Str(v), &self))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Timestamp, E> {
+                Ok(Timestamp(v))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Timestamp, E> {
+                i64::try_from(v)
+                    .map(Timestamp)
+                    .map_err(|_| E::invalid_value(de::Unexpected::Unsigned(v), &self))
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strict_parsing() {
+        assert_eq!(
+            Timestamp::parse_strict("1:02:03.45"),
+            Some(Timestamp(372_345))
+        );
+        assert_eq!(Timestamp::parse_strict("0:00:00.5"), Some(Timestamp(50)));
+        assert_eq!(Timestamp::parse_strict("0:00:00.125"), Some(Timestamp(13)));
+        assert_eq!(Timestamp::parse_strict("0:00:00.995"), Some(Timestamp(100)));
+        assert_eq!(
+            Timestamp::parse_strict("-0:00:01.00"),
+            Some(Timestamp(-100))
+        );
+        assert_eq!(
+            Timestamp::parse_strict("123:00:00.00"),
+            Some(Timestamp(123 * 360_000))
+        );
+        for bad in [
+            "0:00:00",
+            "0:0:00.00",
+            "0:00:60.00",
+            "0:60:00.00",
+            ":00:00.00",
+            "0:00:00.",
+            " 0:00:00.00",
+            "0:00:00,00",
+            "0:00:00:00.00",
+            "0:00:0a.00",
+        ] {
+            assert_eq!(Timestamp::parse_strict(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn lenient_parsing() {
+        assert_eq!(
+            Timestamp::parse_lenient(" 0:01:02.5 "),
+            Some(Timestamp(6_250))
+        );
+        assert_eq!(Timestamp::parse_lenient("1:02,03"), Some(Timestamp(6_203)));
+        assert_eq!(Timestamp::parse_lenient("5"), Some(Timestamp(500)));
+        assert_eq!(Timestamp::parse_lenient("0:90.00"), Some(Timestamp(9_000)));
+        assert_eq!(Timestamp::parse_lenient("0:0:75."), Some(Timestamp(7_500)));
+        assert_eq!(
+            Timestamp::parse_lenient("- 0:00:01.00"),
+            Some(Timestamp(-100))
+        );
+        assert_eq!(Timestamp::parse_lenient("::.5"), Some(Timestamp(50)));
+        for bad in ["0:0:0:0.00", "a:00:00.00", "0:00:00.x", "+0:00:00.00"] {
+            assert_eq!(Timestamp::parse_lenient(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn hour_overflow() {
+        let too_many = format!("{}:00:00.00", u64::MAX / 360_000 + 1);
+        assert_eq!(Timestamp::parse_strict(&too_many), None);
+        assert_eq!(Timestamp::parse_lenient(&too_many), None);
+        let above_i64 = format!("{}:00:00.00", i64::MAX as u64 / 360_000 + 1);
+        assert_eq!(Timestamp::parse_strict(&above_i64), None);
+        assert_eq!(
+            Timestamp::parse_strict("99999999999999999999:00:00.00"),
+            None
+        );
+    }
+
+    #[test]
+    fn negative_times() {
+        assert!(Timestamp(-1).is_negative());
+        assert_eq!(Timestamp(-1).to_duration(), None);
+        assert_eq!(Timestamp::from_millis(-15), Timestamp(-2));
+        assert_eq!(Timestamp(50).shift(Timestamp(-100)), Timestamp::ZERO);
+        assert_eq!(-Timestamp(i64::MIN), Timestamp(i64::MAX));
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Timestamp(372_345).to_string(), "1:02:03.45");
+        assert_eq!(Timestamp::ZERO.to_string(), "0:00:00.00");
+        assert_eq!(Timestamp(-6_205).to_string(), "-0:01:02.05");
+        assert_eq!(Timestamp(100 * 360_000).to_string(), "100:00:00.00");
+        for time in [Timestamp(0), Timestamp(372_345), Timestamp(-6_205)] {
+            assert_eq!(Timestamp::parse_strict(&time.to_string()), Some(time));
+        }
+    }
+}
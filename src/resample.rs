@@ -0,0 +1,379 @@
+use std::{fmt::Write, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    formats::play_res,
+    models::{events::EventLine, script_info::YCbCrMatrix, style::Style, Color},
+    tags::{ClipShape, DrawingError, DrawingPath, OverrideTag, Token},
+    writer::WriteAss,
+    Script,
+};
+
+/// Luma coefficients and whether the matrix uses the full (PC) range.
+fn matrix_params(matrix: &YCbCrMatrix) -> Option<(f64, f64, bool)> {
+    let (kr, kb) = match matrix {
+        YCbCrMatrix::None | YCbCrMatrix::Other(_) => return None,
+        YCbCrMatrix::Tv601 | YCbCrMatrix::Pc601 => (0.299, 0.114),
+        YCbCrMatrix::Tv709 | YCbCrMatrix::Pc709 => (0.2126, 0.0722),
+        YCbCrMatrix::TvFcc | YCbCrMatrix::PcFcc => (0.3, 0.11),
+        YCbCrMatrix::Tv240m | YCbCrMatrix::Pc240m => (0.212, 0.087),
+    };
+    let full = matches!(
+        matrix,
+        YCbCrMatrix::Pc601 | YCbCrMatrix::Pc709 | YCbCrMatrix::PcFcc | YCbCrMatrix::Pc240m
+    );
+    Some((kr, kb, full))
+}
+
+/// Converts `color` so that video encoded with `to` shows it as video encoded with `from`
+/// showed the original: the colour is taken to YCbCr with `from` and back to RGB with `to`.
+/// Colours are left alone if either matrix is `None` or unknown.
+pub fn convert_color(color: Color, from: &YCbCrMatrix, to: &YCbCrMatrix) -> Color {
+    let (Some((kr, kb, full)), Some((to_kr, to_kb, to_full))) =
+        (matrix_params(from), matrix_params(to))
+    else {
+        return color;
+    };
+    if (kr, kb, full) == (to_kr, to_kb, to_full) {
+        return color;
+    }
+
+    let [r, g, b] = [color.red, color.green, color.blue].map(|v| f64::from(v) / 255.0);
+    let y = kr * r + (1.0 - kr - kb) * g + kb * b;
+    let cb = (b - y) / (2.0 * (1.0 - kb));
+    let cr = (r - y) / (2.0 * (1.0 - kr));
+    // the 8-bit values the video ends up with
+    let (y, cb, cr) = if full {
+        (255.0 * y, 128.0 + 255.0 * cb, 128.0 + 255.0 * cr)
+    } else {
+        (16.0 + 219.0 * y, 128.0 + 224.0 * cb, 128.0 + 224.0 * cr)
+    };
+
+    let (y, cb, cr) = if to_full {
+        (y / 255.0, (cb - 128.0) / 255.0, (cr - 128.0) / 255.0)
+    } else {
+        (
+            (y - 16.0) / 219.0,
+            (cb - 128.0) / 224.0,
+            (cr - 128.0) / 224.0,
+        )
+    };
+    let r = y + 2.0 * (1.0 - to_kr) * cr;
+    let b = y + 2.0 * (1.0 - to_kb) * cb;
+    let g = (y - to_kr * r - to_kb * b) / (1.0 - to_kr - to_kb);
+
+    let byte = |v: f64| (v * 255.0).round().clamp(0.0, 255.0) as u8;
+    Color {
+        red: byte(r),
+        green: byte(g),
+        blue: byte(b),
+        ..color
+    }
+}
+
+/// Moves every style and override colour of the script from its `YCbCr Matrix` to `to` with
+/// [`convert_color`], then sets the header to `to`. Scripts without the header are taken to be
+/// `TV.601`, as VSFilter does.
+pub fn convert_matrix(script: &mut Script<'_>, to: YCbCrMatrix) {
+    let from = script
+        .info
+        .ycbcr_matrix
+        .take()
+        .unwrap_or(YCbCrMatrix::Tv601);
+    let convert = |color: Color| convert_color(color, &from, &to);
+    for style in &mut script.styles {
+        for color in [
+            Some(&mut style.primary_color),
+            Some(&mut style.secondary_color),
+            style.outline_color.as_mut(),
+            Some(&mut style.back_color),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *color = convert(*color);
+        }
+    }
+
+    for event in &mut script.events {
+        event.map_tags(|tag| match tag {
+            OverrideTag::Color(_, Some(color)) => {
+                let converted = convert(*color);
+                std::mem::replace(color, converted) != converted
+            }
+            _ => false,
+        });
+    }
+    script.info.ycbcr_matrix = Some(to);
+}
+
+/// How to fit a script into a resolution of a different shape.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AspectRatioMode {
+    /// Scale each axis on its own, stretching the text horizontally with `\fscx`.
+    #[default]
+    Stretch,
+    /// Keep the shape and fill the new width, adding space above and below, or cutting it off
+    /// if the new resolution is wider.
+    Letterbox,
+    /// Keep the shape and fill the new height, adding space at the sides, or cutting it off if
+    /// the new resolution is narrower.
+    Pillarbox,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResampleOptions {
+    pub width: u32,
+    pub height: u32,
+    pub aspect_ratio: AspectRatioMode,
+    /// Space to add around the old resolution before fitting it, as left, right, top and
+    /// bottom. Negative values crop.
+    pub margins: [f64; 4],
+    /// Converts colours to this matrix with [`convert_matrix`].
+    pub ycbcr_matrix: Option<YCbCrMatrix>,
+}
+
+/// Scale factors from the old resolution to the new one.
+struct Scale {
+    x: f64,
+    y: f64,
+    /// Extra horizontal scale for text when stretching.
+    stretch: f64,
+    /// Left and top margins, which move every position.
+    left: f64,
+    top: f64,
+}
+
+impl Scale {
+    fn x(&self, x: f64) -> f64 {
+        (x + self.left) * self.x
+    }
+
+    fn y(&self, y: f64) -> f64 {
+        (y + self.top) * self.y
+    }
+}
+
+/// Scales the script from its PlayRes to `options.width` by `options.height`, as Aegisub's
+/// Resample Resolution does: style sizes and margins, event margins, positions, clips,
+/// drawings and size overrides all follow, and the PlayRes is updated. Lines in karaoke
+/// templates (comments with a `template` or `code` effect) are left as written.
+///
+/// Returns the indices of the events with a drawing or vector clip that could not be read,
+/// which are left unscaled while the rest of the line is resampled.
+pub fn resample(script: &mut Script<'_>, options: &ResampleOptions) -> Vec<usize> {
+    let (mut width, mut height) = play_res(script);
+    let [mut left, mut right, mut top, mut bottom] = options.margins;
+    let (new_width, new_height) = (f64::from(options.width), f64::from(options.height));
+
+    let old_ar = (width + left + right) / (height + top + bottom);
+    let new_ar = new_width / new_height;
+    let mut stretch = 1.0;
+    // close enough ratios are scaled as they are
+    if (old_ar - new_ar).abs() / new_ar > 0.01 {
+        match options.aspect_ratio {
+            AspectRatioMode::Stretch => stretch = new_ar / old_ar,
+            AspectRatioMode::Letterbox => {
+                let border = ((width + left + right) / new_ar - (height + top + bottom)) / 2.0;
+                top += border;
+                bottom += border;
+            }
+            AspectRatioMode::Pillarbox => {
+                let border = ((height + top + bottom) * new_ar - (width + left + right)) / 2.0;
+                left += border;
+                right += border;
+            }
+        }
+    }
+    width += left + right;
+    height += top + bottom;
+
+    let scale = Scale {
+        x: new_width / width,
+        y: new_height / height,
+        stretch,
+        left,
+        top,
+    };
+
+    for style in &mut script.styles {
+        resample_style(style, &scale, right);
+    }
+    let mut unreadable = Vec::new();
+    for (idx, event) in script.events.iter_mut().enumerate() {
+        if !resample_event(event, &scale, right) {
+            unreadable.push(idx);
+        }
+    }
+
+    let info = &mut script.info.play_info;
+    info.play_res_x = Some(options.width.into());
+    info.play_res_y = Some(options.height.into());
+    if let Some(matrix) = &options.ycbcr_matrix {
+        convert_matrix(script, matrix.clone());
+    }
+    unreadable
+}
+
+fn round(v: f64) -> i64 {
+    v.round() as i64
+}
+
+fn resample_style(style: &mut Style<'_>, scale: &Scale, right: f64) {
+    style.font_size *= scale.y;
+    style.outline *= scale.y;
+    style.shadow *= scale.y;
+    style.spacing = style.spacing.map(|v| v * scale.x);
+    if scale.stretch != 1.0 {
+        style.scale_x = Some(style.scale_x.unwrap_or(100.0) * scale.stretch);
+    }
+    style.margin_left = round(scale.x(style.margin_left as f64));
+    style.margin_right = round((style.margin_right as f64 + right) * scale.x);
+    style.margin_vertical = round(scale.y(style.margin_vertical as f64));
+}
+
+/// Returns false if a drawing or vector clip could not be read and was copied as it is.
+fn resample_event(event: &mut EventLine<'_>, scale: &Scale, right: f64) -> bool {
+    if event.is_comment
+        && (event.effect.starts_with("template") || event.effect.starts_with("code"))
+    {
+        return true;
+    }
+
+    // zero margins defer to the style
+    if event.margin_left != 0 {
+        event.margin_left = round(scale.x(event.margin_left as f64));
+    }
+    if event.margin_right != 0 {
+        event.margin_right = round((event.margin_right as f64 + right) * scale.x);
+    }
+    if event.margin_vertical != 0 {
+        event.margin_vertical = round(scale.y(event.margin_vertical as f64));
+    }
+
+    let mut text = String::with_capacity(event.text.len());
+    let mut drawing = false;
+    let mut readable = true;
+    for token in event.tokens() {
+        match token {
+            Token::Override(tags) => {
+                text.push('{');
+                for tag in tags {
+                    if let OverrideTag::Drawing(level) = tag {
+                        drawing = level > 0;
+                    }
+                    match &tag {
+                        OverrideTag::Clip {
+                            inverse,
+                            shape:
+                                ClipShape::Vector {
+                                    scale: level,
+                                    commands,
+                                },
+                        } => match resample_clip(*level, commands, scale) {
+                            Ok(path) => {
+                                let name = if *inverse { "iclip" } else { "clip" };
+                                let level = level.map(|l| format!("{l},")).unwrap_or_default();
+                                write!(text, "\\{name}({level}{path})").unwrap();
+                            }
+                            Err(_) => {
+                                readable = false;
+                                tag.write_ass(&mut text).unwrap()
+                            }
+                        },
+                        _ => resample_tag(tag, scale).write_ass(&mut text).unwrap(),
+                    }
+                }
+                text.push('}');
+            }
+            Token::Text(drawing_text) if drawing => match DrawingPath::from_str(drawing_text) {
+                Ok(mut path) => {
+                    path.scale(scale.x, scale.y);
+                    write!(text, "{path}").unwrap();
+                }
+                Err(_) => {
+                    readable = false;
+                    text.push_str(drawing_text)
+                }
+            },
+            token => token.write_ass(&mut text).unwrap(),
+        }
+    }
+    event.text = text.into();
+    readable
+}
+
+/// The path of a vector clip moved and scaled in the clip's own coordinate units.
+fn resample_clip(
+    level: Option<u32>,
+    commands: &str,
+    scale: &Scale,
+) -> Result<DrawingPath, DrawingError> {
+    let mut path = DrawingPath::from_str(commands)?;
+    let unit = 2f64.powi(level.unwrap_or(1).max(1) as i32 - 1);
+    path.translate(scale.left * unit, scale.top * unit);
+    path.scale(scale.x, scale.y);
+    Ok(path)
+}
+
+fn resample_tag<'a>(tag: OverrideTag<'a>, scale: &Scale) -> OverrideTag<'a> {
+    use OverrideTag::*;
+    let x = |v: Option<f64>| v.map(|v| v * scale.x);
+    let y = |v: Option<f64>| v.map(|v| v * scale.y);
+    match tag {
+        Border(v) => Border(y(v)),
+        XBorder(v) => XBorder(x(v)),
+        YBorder(v) => YBorder(y(v)),
+        Shadow(v) => Shadow(y(v)),
+        XShadow(v) => XShadow(x(v)),
+        YShadow(v) => YShadow(y(v)),
+        Blur(v) => Blur(y(v)),
+        FontSize(v) => FontSize(y(v)),
+        Spacing(v) => Spacing(x(v)),
+        FontScaleX(v) => FontScaleX(v.map(|v| v * scale.stretch)),
+        BaselineOffset(v) => BaselineOffset(v * scale.y),
+        Position { x, y } => Position {
+            x: scale.x(x),
+            y: scale.y(y),
+        },
+        Move {
+            x1,
+            y1,
+            x2,
+            y2,
+            times,
+        } => Move {
+            x1: scale.x(x1),
+            y1: scale.y(y1),
+            x2: scale.x(x2),
+            y2: scale.y(y2),
+            times,
+        },
+        Origin { x, y } => Origin {
+            x: scale.x(x),
+            y: scale.y(y),
+        },
+        Clip {
+            inverse,
+            shape: ClipShape::Rect { x1, y1, x2, y2 },
+        } => Clip {
+            inverse,
+            shape: ClipShape::Rect {
+                x1: scale.x(x1),
+                y1: scale.y(y1),
+                x2: scale.x(x2),
+                y2: scale.y(y2),
+            },
+        },
+        Transform { times, accel, tags } => Transform {
+            times,
+            accel,
+            tags: tags
+                .into_iter()
+                .map(|tag| resample_tag(tag, scale))
+                .collect(),
+        },
+        tag => tag,
+    }
+}
@@ -0,0 +1,428 @@
+use std::{fmt, str::FromStr};
+
+use super::{write::Num, ClipShape};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Point {
+    pub fn new(x: f64, y: f64) -> Point {
+        Point { x, y }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DrawCommand {
+    /// `m`, closes the current shape and starts a new one.
+    Move(Point),
+    /// `n`, moves without closing the current shape.
+    MoveNoClose(Point),
+    /// `l`
+    Line(Vec<Point>),
+    /// `b`, cubic béziers as two control points and an end point each.
+    Bezier(Vec<[Point; 3]>),
+    /// `s`, a cubic b-spline through at least three points.
+    Spline(Vec<Point>),
+    /// `p`, extends the preceding b-spline.
+    ExtendSpline(Vec<Point>),
+    /// `c`, closes the b-spline.
+    Close,
+}
+
+impl DrawCommand {
+    pub fn points(&self) -> &[Point] {
+        match self {
+            DrawCommand::Move(p) | DrawCommand::MoveNoClose(p) => std::slice::from_ref(p),
+            DrawCommand::Line(points)
+            | DrawCommand::Spline(points)
+            | DrawCommand::ExtendSpline(points) => points,
+            DrawCommand::Bezier(curves) => curves.as_flattened(),
+            DrawCommand::Close => &[],
+        }
+    }
+
+    pub fn points_mut(&mut self) -> &mut [Point] {
+        match self {
+            DrawCommand::Move(p) | DrawCommand::MoveNoClose(p) => std::slice::from_mut(p),
+            DrawCommand::Line(points)
+            | DrawCommand::Spline(points)
+            | DrawCommand::ExtendSpline(points) => points,
+            DrawCommand::Bezier(curves) => curves.as_flattened_mut(),
+            DrawCommand::Close => &mut [],
+        }
+    }
+
+    fn letter(&self) -> char {
+        match self {
+            DrawCommand::Move(_) => 'm',
+            DrawCommand::MoveNoClose(_) => 'n',
+            DrawCommand::Line(_) => 'l',
+            DrawCommand::Bezier(_) => 'b',
+            DrawCommand::Spline(_) => 's',
+            DrawCommand::ExtendSpline(_) => 'p',
+            DrawCommand::Close => 'c',
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl BoundingBox {
+    pub fn width(&self) -> f64 {
+        self.max.x - self.min.x
+    }
+
+    pub fn height(&self) -> f64 {
+        self.max.y - self.min.y
+    }
+}
+
+/// A shape in the ASS drawing language, as used in `\p` drawings and vector `\clip`s.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DrawingPath {
+    pub commands: Vec<DrawCommand>,
+}
+
+impl DrawingPath {
+    pub fn points(&self) -> impl Iterator<Item = &Point> {
+        self.commands.iter().flat_map(DrawCommand::points)
+    }
+
+    pub fn points_mut(&mut self) -> impl Iterator<Item = &mut Point> {
+        self.commands.iter_mut().flat_map(DrawCommand::points_mut)
+    }
+
+    /// The box around every point of the path, control points included.
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        let mut points = self.points();
+        let first = *points.next()?;
+        Some(points.fold(
+            BoundingBox {
+                min: first,
+                max: first,
+            },
+            |bb, p| BoundingBox {
+                min: Point::new(bb.min.x.min(p.x), bb.min.y.min(p.y)),
+                max: Point::new(bb.max.x.max(p.x), bb.max.y.max(p.y)),
+            },
+        ))
+    }
+
+    pub fn translate(&mut self, dx: f64, dy: f64) {
+        for p in self.points_mut() {
+            p.x += dx;
+            p.y += dy;
+        }
+    }
+
+    pub fn scale(&mut self, sx: f64, sy: f64) {
+        for p in self.points_mut() {
+            p.x *= sx;
+            p.y *= sy;
+        }
+    }
+
+    /// Converts coordinates written for `\p<from>` (or a clip scale) into `\p<to>` ones. Each
+    /// level above 1 halves the size of a coordinate unit.
+    pub fn rescale_level(&mut self, from: u32, to: u32) {
+        let factor = 2f64.powi(to.max(1) as i32 - from.max(1) as i32);
+        self.scale(factor, factor);
+    }
+}
+
+/// Why a drawing could not be read. Offsets are in bytes from the start of the drawing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DrawingError {
+    /// A character that is neither a command letter nor part of a number.
+    UnexpectedChar { found: char, offset: usize },
+    /// Coordinates before the first command.
+    MissingCommand { offset: usize },
+    /// A command followed by a number of coordinates it can't take.
+    WrongCoordinateCount { command: char, offset: usize },
+}
+
+impl DrawingError {
+    pub fn offset(&self) -> usize {
+        match self {
+            DrawingError::UnexpectedChar { offset, .. }
+            | DrawingError::MissingCommand { offset }
+            | DrawingError::WrongCoordinateCount { offset, .. } => *offset,
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            DrawingError::UnexpectedChar { .. } => "unexpected character in drawing",
+            DrawingError::MissingCommand { .. } => "drawing coordinates before any command",
+            DrawingError::WrongCoordinateCount { .. } => "wrong number of coordinates for command",
+        }
+    }
+}
+
+impl fmt::Display for DrawingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.message(), self.offset())
+    }
+}
+
+impl std::error::Error for DrawingError {}
+
+/// Splits a drawing into command letters and numbers. Neither needs whitespace around it, so
+/// `m0 0l100 0` reads the same as `m 0 0 l 100 0`.
+struct DrawingTokens<'a> {
+    s: &'a str,
+    offset: usize,
+}
+
+enum DrawingToken {
+    Command(char),
+    Number(f64),
+}
+
+impl Iterator for DrawingTokens<'_> {
+    type Item = Result<(DrawingToken, usize), DrawingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = &self.s[self.offset..];
+        let trimmed = rest.trim_start();
+        self.offset += rest.len() - trimmed.len();
+        let start = self.offset;
+        let found = trimmed.chars().next()?;
+
+        if found.is_ascii_alphabetic() {
+            self.offset += 1;
+            return Some(Ok((
+                DrawingToken::Command(found.to_ascii_lowercase()),
+                start,
+            )));
+        }
+
+        let sign = usize::from(trimmed.starts_with(['-', '+']));
+        let digits = |s: &str| s.bytes().take_while(u8::is_ascii_digit).count();
+        let mut len = sign + digits(&trimmed[sign..]);
+        if trimmed[len..].starts_with('.') {
+            len += 1 + digits(&trimmed[len + 1..]);
+        }
+        match trimmed[..len].parse::<f64>() {
+            Ok(n) => {
+                self.offset += len;
+                Some(Ok((DrawingToken::Number(n), start)))
+            }
+            Err(_) => {
+                // stop after the first error
+                self.offset = self.s.len();
+                Some(Err(DrawingError::UnexpectedChar {
+                    found,
+                    offset: start,
+                }))
+            }
+        }
+    }
+}
+
+impl FromStr for DrawingPath {
+    type Err = DrawingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut commands = Vec::new();
+        let mut tokens = DrawingTokens { s, offset: 0 }.peekable();
+
+        while let Some(token) = tokens.next() {
+            let (command, offset) = match token? {
+                (DrawingToken::Command(command), offset) => (command, offset),
+                (DrawingToken::Number(_), offset) => {
+                    return Err(DrawingError::MissingCommand { offset })
+                }
+            };
+            // numbers up to the next letter all belong to this command
+            let mut numbers = Vec::new();
+            while let Some(Ok((DrawingToken::Number(n), _))) = tokens.peek() {
+                numbers.push(*n);
+                tokens.next();
+            }
+            if let Some(Err(error)) = tokens.peek() {
+                return Err(error.clone());
+            }
+            let wrong_count = DrawingError::WrongCoordinateCount { command, offset };
+            if !numbers.len().is_multiple_of(2) {
+                return Err(wrong_count);
+            }
+            let points: Vec<Point> = numbers
+                .chunks_exact(2)
+                .map(|xy| Point::new(xy[0], xy[1]))
+                .collect();
+
+            match command {
+                'm' | 'n' if !points.is_empty() => {
+                    commands.extend(points.into_iter().map(|p| match command {
+                        'm' => DrawCommand::Move(p),
+                        _ => DrawCommand::MoveNoClose(p),
+                    }))
+                }
+                'l' if !points.is_empty() => commands.push(DrawCommand::Line(points)),
+                'b' if !points.is_empty() && points.len().is_multiple_of(3) => {
+                    let curves = points.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect();
+                    commands.push(DrawCommand::Bezier(curves))
+                }
+                's' if points.len() >= 3 => commands.push(DrawCommand::Spline(points)),
+                'p' if !points.is_empty() => commands.push(DrawCommand::ExtendSpline(points)),
+                'c' if points.is_empty() => commands.push(DrawCommand::Close),
+                'm' | 'n' | 'l' | 'b' | 's' | 'p' | 'c' => return Err(wrong_count),
+                found => return Err(DrawingError::UnexpectedChar { found, offset }),
+            }
+        }
+
+        Ok(DrawingPath { commands })
+    }
+}
+
+impl fmt::Display for DrawingPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, command) in self.commands.iter().enumerate() {
+            if idx > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}", command.letter())?;
+            for p in command.points() {
+                write!(f, " {} {}", Num(p.x), Num(p.y))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> ClipShape<'a> {
+    /// The path of a vector clip, scaled to plain coordinates.
+    pub fn path(&self) -> Option<DrawingPath> {
+        let ClipShape::Vector { scale, commands } = self else {
+            return None;
+        };
+        let mut path = DrawingPath::from_str(commands).ok()?;
+        path.rescale_level(scale.unwrap_or(1), 1);
+        Some(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(s: &str) -> DrawingPath {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_spaced_and_compact_drawings() {
+        let expected = DrawingPath {
+            commands: vec![
+                DrawCommand::Move(Point::new(0.0, 0.0)),
+                DrawCommand::Line(vec![Point::new(100.0, 0.0), Point::new(100.0, -100.5)]),
+                DrawCommand::Close,
+            ],
+        };
+        assert_eq!(path("m 0 0 l 100 0 100 -100.5 c"), expected);
+        assert_eq!(path("m0 0l100 0 100-100.5c"), expected);
+        assert_eq!(path("  M 0 0\tL 100 0 100 -100.5 C "), expected);
+    }
+
+    #[test]
+    fn parse_commands() {
+        let parsed = path("m 1 2 3 4 n 5 6 b 0 0 1 1 2 2 s 0 0 1 0 1 1 p 0 1 c");
+        assert_eq!(
+            parsed.commands,
+            vec![
+                DrawCommand::Move(Point::new(1.0, 2.0)),
+                DrawCommand::Move(Point::new(3.0, 4.0)),
+                DrawCommand::MoveNoClose(Point::new(5.0, 6.0)),
+                DrawCommand::Bezier(vec![[
+                    Point::new(0.0, 0.0),
+                    Point::new(1.0, 1.0),
+                    Point::new(2.0, 2.0)
+                ]]),
+                DrawCommand::Spline(vec![
+                    Point::new(0.0, 0.0),
+                    Point::new(1.0, 0.0),
+                    Point::new(1.0, 1.0)
+                ]),
+                DrawCommand::ExtendSpline(vec![Point::new(0.0, 1.0)]),
+                DrawCommand::Close,
+            ]
+        );
+        assert_eq!(path("").commands, vec![]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = |s: &str| s.parse::<DrawingPath>().unwrap_err();
+        assert_eq!(err("0 0 l 1 1"), DrawingError::MissingCommand { offset: 0 });
+        assert_eq!(
+            err("m 0 0 l 1"),
+            DrawingError::WrongCoordinateCount {
+                command: 'l',
+                offset: 6
+            }
+        );
+        assert_eq!(
+            err("m 0 0 b 1 1 2 2"),
+            DrawingError::WrongCoordinateCount {
+                command: 'b',
+                offset: 6
+            }
+        );
+        assert_eq!(
+            err("m 0 0 x 1 1"),
+            DrawingError::UnexpectedChar {
+                found: 'x',
+                offset: 6
+            }
+        );
+        assert_eq!(
+            err("m 0 0 l 1 #"),
+            DrawingError::UnexpectedChar {
+                found: '#',
+                offset: 10
+            }
+        );
+    }
+
+    #[test]
+    fn display_writes_spaced_commands() {
+        assert_eq!(
+            path("m0 0l100 0 100 50.25b1 2 3 4 5 6c").to_string(),
+            "m 0 0 l 100 0 100 50.25 b 1 2 3 4 5 6 c"
+        );
+        assert_eq!(path("m 0.1234 -1 l 2 2").to_string(), "m 0.123 -1 l 2 2");
+    }
+
+    #[test]
+    fn bounding_box_includes_control_points() {
+        let bb = path("m 10 20 b 0 50 30 -5 20 20").bounding_box().unwrap();
+        assert_eq!(bb.min, Point::new(0.0, -5.0));
+        assert_eq!(bb.max, Point::new(30.0, 50.0));
+        assert_eq!((bb.width(), bb.height()), (30.0, 55.0));
+        assert_eq!(path("").bounding_box(), None);
+    }
+
+    #[test]
+    fn scale_and_rescale_level() {
+        let mut drawing = path("m 10 20 l 30 40");
+        drawing.scale(2.0, 0.5);
+        assert_eq!(drawing, path("m 20 10 l 60 20"));
+
+        // \p3 coordinates are a quarter the size of \p1 ones
+        drawing.rescale_level(1, 3);
+        assert_eq!(drawing, path("m 80 40 l 240 80"));
+        drawing.rescale_level(3, 1);
+        assert_eq!(drawing, path("m 20 10 l 60 20"));
+        // levels 0 and 1 both use plain coordinates
+        drawing.rescale_level(0, 1);
+        assert_eq!(drawing, path("m 20 10 l 60 20"));
+    }
+}
@@ -0,0 +1,211 @@
+use crate::models::{events::EventLine, Color};
+
+mod drawing;
+mod parse;
+mod write;
+
+pub use drawing::{BoundingBox, DrawCommand, DrawingError, DrawingPath, Point};
+pub use parse::parse_block;
+pub(crate) use write::Num;
+
+/// A piece of event text. Everything borrows from the text it was tokenized from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token<'a> {
+    Text(&'a str),
+    /// `\N`
+    HardBreak,
+    /// `\n`, a break only honoured with wrap style 2
+    SoftBreak,
+    /// `\h`
+    HardSpace,
+    Override(Vec<OverrideTag<'a>>),
+    /// A `{...}` block without any tags in it.
+    Comment(&'a str),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSlot {
+    Primary = 1,
+    Secondary = 2,
+    Outline = 3,
+    Shadow = 4,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KaraokeKind {
+    /// `\k`
+    Plain,
+    /// `\kf` or `\K`
+    Fill,
+    /// `\ko`
+    Outline,
+    /// `\kt`
+    Time,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClipShape<'a> {
+    Rect {
+        x1: f64,
+        y1: f64,
+        x2: f64,
+        y2: f64,
+    },
+    Vector {
+        scale: Option<u32>,
+        commands: &'a str,
+    },
+}
+
+/// A single override tag. `None` arguments are tags written without a value, which reset the
+/// property to the style's default.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OverrideTag<'a> {
+    /// `\b`: 0, 1, or a font weight.
+    Bold(Option<i32>),
+    Italic(Option<bool>),
+    Underline(Option<bool>),
+    StrikeOut(Option<bool>),
+    Border(Option<f64>),
+    XBorder(Option<f64>),
+    YBorder(Option<f64>),
+    Shadow(Option<f64>),
+    XShadow(Option<f64>),
+    YShadow(Option<f64>),
+    /// `\be`
+    EdgeBlur(Option<f64>),
+    Blur(Option<f64>),
+    FontName(Option<&'a str>),
+    FontSize(Option<f64>),
+    FontScaleX(Option<f64>),
+    FontScaleY(Option<f64>),
+    Spacing(Option<f64>),
+    RotationX(Option<f64>),
+    RotationY(Option<f64>),
+    /// `\frz` or `\fr`
+    RotationZ(Option<f64>),
+    ShearX(Option<f64>),
+    ShearY(Option<f64>),
+    /// `\fe`
+    Encoding(Option<i32>),
+    /// `\c` is the same as `\1c`.
+    Color(ColorSlot, Option<Color>),
+    /// `\alpha` (no slot) sets all four alphas.
+    Alpha(Option<ColorSlot>, Option<u8>),
+    /// `\an`, numpad layout
+    Alignment(Option<u8>),
+    /// `\a`, SSA layout
+    LegacyAlignment(Option<u8>),
+    /// Duration in centiseconds.
+    Karaoke(KaraokeKind, f64),
+    WrapStyle(Option<u8>),
+    Reset(Option<&'a str>),
+    Position {
+        x: f64,
+        y: f64,
+    },
+    /// `\move`, with optional start and end times in milliseconds.
+    Move {
+        x1: f64,
+        y1: f64,
+        x2: f64,
+        y2: f64,
+        times: Option<(f64, f64)>,
+    },
+    Origin {
+        x: f64,
+        y: f64,
+    },
+    /// `\fad`, durations in milliseconds.
+    Fad {
+        fade_in: f64,
+        fade_out: f64,
+    },
+    /// `\fade`, three alphas and four times in milliseconds.
+    Fade {
+        alpha: [i32; 3],
+        times: [f64; 4],
+    },
+    Clip {
+        inverse: bool,
+        shape: ClipShape<'a>,
+    },
+    /// `\t`, with optional times in milliseconds and acceleration.
+    Transform {
+        times: Option<(f64, f64)>,
+        accel: Option<f64>,
+        tags: Vec<OverrideTag<'a>>,
+    },
+    /// `\p`; 0 ends drawing mode.
+    Drawing(u32),
+    /// `\pbo`
+    BaselineOffset(f64),
+    /// Text inside an override block that is not part of a tag.
+    Comment(&'a str),
+    /// Anything that isn't a recognised tag, kept verbatim including the backslash.
+    Unknown(&'a str),
+}
+
+pub struct Tokens<'a> {
+    rest: &'a str,
+}
+
+pub fn tokenize(text: &str) -> Tokens<'_> {
+    Tokens { rest: text }
+}
+
+impl<'a> Tokens<'a> {
+    /// The text not tokenized yet.
+    pub fn rest(&self) -> &'a str {
+        self.rest
+    }
+}
+
+impl<'a> EventLine<'a> {
+    pub fn tokens(&self) -> Tokens<'_> {
+        tokenize(&self.text)
+    }
+}
+
+fn is_break_escape(bytes: &[u8], idx: usize) -> bool {
+    bytes[idx] == b'\\' && matches!(bytes.get(idx + 1), Some(b'N' | b'n' | b'h'))
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = self.rest;
+        let bytes = rest.as_bytes();
+        match bytes.first()? {
+            b'{' => {
+                let Some(end) = rest.find('}') else {
+                    self.rest = "";
+                    return Some(Token::Text(rest));
+                };
+                let content = &rest[1..end];
+                self.rest = &rest[end + 1..];
+                if content.contains('\\') {
+                    Some(Token::Override(parse_block(content)))
+                } else {
+                    Some(Token::Comment(content))
+                }
+            }
+            b'\\' if is_break_escape(bytes, 0) => {
+                self.rest = &rest[2..];
+                Some(match bytes[1] {
+                    b'N' => Token::HardBreak,
+                    b'n' => Token::SoftBreak,
+                    _ => Token::HardSpace,
+                })
+            }
+            _ => {
+                let end = (1..bytes.len())
+                    .find(|&idx| bytes[idx] == b'{' || is_break_escape(bytes, idx))
+                    .unwrap_or(bytes.len());
+                self.rest = &rest[end..];
+                Some(Token::Text(&rest[..end]))
+            }
+        }
+    }
+}
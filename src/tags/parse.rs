@@ -0,0 +1,427 @@
+use crate::models::Color;
+
+use super::{ClipShape, ColorSlot, KaraokeKind, OverrideTag};
+
+/// Tag names in the order they are matched. Names that are a prefix of another name come after it.
+const TAG_NAMES: [&str; 53] = [
+    "xbord", "ybord", "xshad", "yshad", "iclip", "alpha", "blur", "bord", "shad", "fscx", "fscy",
+    "fade", "clip", "move", "fsp", "fax", "fay", "frx", "fry", "frz", "fad", "pos", "pbo", "org",
+    "1c", "2c", "3c", "4c", "1a", "2a", "3a", "4a", "an", "be", "fs", "fn", "fe", "fr", "kf", "ko",
+    "kt", "a", "b", "c", "i", "u", "s", "K", "k", "q", "r", "t", "p",
+];
+
+enum Arg<'a> {
+    Plain(&'a str),
+    Parens(&'a str),
+}
+
+/// Parses the contents of an override block, without the surrounding braces.
+pub fn parse_block(block: &str) -> Vec<OverrideTag<'_>> {
+    let mut tags = Vec::new();
+    let mut rest = block;
+
+    while !rest.is_empty() {
+        let start = rest.find('\\').unwrap_or(rest.len());
+        let text = &rest[..start];
+        if !text.trim().is_empty() {
+            tags.push(OverrideTag::Comment(text));
+        }
+        rest = &rest[start..];
+        if rest.is_empty() {
+            break;
+        }
+
+        let (tag, remaining) = parse_tag(rest);
+        tags.push(tag);
+        rest = remaining;
+    }
+
+    tags
+}
+
+/// The name of the tag written as `raw`, or the letters after its backslash if the name is
+/// not a known one.
+pub(super) fn raw_name(raw: &str) -> &str {
+    let body = raw.strip_prefix('\\').unwrap_or(raw);
+    let letters = body
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(body.len());
+    TAG_NAMES
+        .iter()
+        .copied()
+        .find(|name| body.starts_with(name))
+        .unwrap_or(&body[..letters])
+}
+
+/// Parses the tag at the start of `s`, which begins with a backslash. Returns the tag and the
+/// text after it.
+fn parse_tag(s: &str) -> (OverrideTag<'_>, &str) {
+    let body = &s[1..];
+    let name = TAG_NAMES
+        .iter()
+        .copied()
+        .find(|name| body.starts_with(name))
+        .unwrap_or("");
+    let after_name = &body[name.len()..];
+
+    let trimmed = after_name.trim_start();
+    let (arg, end) = if let Some(inner) = trimmed.strip_prefix('(') {
+        let open = s.len() - trimmed.len();
+        match matching_paren(trimmed) {
+            Some(close) => (Arg::Parens(&trimmed[1..close]), open + close + 1),
+            None => (Arg::Parens(inner), s.len()),
+        }
+    } else {
+        let len = after_name.find('\\').unwrap_or(after_name.len());
+        (Arg::Plain(&after_name[..len]), 1 + name.len() + len)
+    };
+
+    let raw = &s[..end];
+    let tag = build_tag(name, arg).unwrap_or(OverrideTag::Unknown(raw));
+    (tag, &s[end..])
+}
+
+/// Index of the `)` that closes the `(` at the start of `s`.
+fn matching_paren(s: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    for (idx, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(idx);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn number(s: &str) -> Option<f64> {
+    s.trim().parse::<f64>().ok().filter(|v| v.is_finite())
+}
+
+fn integer<T: std::str::FromStr>(s: &str) -> Option<T> {
+    s.trim().parse().ok()
+}
+
+/// An argument that may be left out: `Some(None)` for an empty argument.
+fn optional<'a, T>(s: &'a str, parse: impl FnOnce(&'a str) -> Option<T>) -> Option<Option<T>> {
+    if s.trim().is_empty() {
+        Some(None)
+    } else {
+        parse(s).map(Some)
+    }
+}
+
+fn flag(s: &str) -> Option<bool> {
+    integer::<i32>(s).map(|v| v != 0)
+}
+
+/// A `&HBBGGRR&` style hex value; the `&`s and the `H` are optional.
+fn hex(s: &str) -> Option<u32> {
+    let s = s.trim().trim_start_matches('&');
+    let s = s
+        .strip_prefix('H')
+        .or_else(|| s.strip_prefix('h'))
+        .unwrap_or(s);
+    let s = s.trim_end_matches('&');
+    if s.is_empty() || s.len() > 8 {
+        return None;
+    }
+    u32::from_str_radix(s, 16).ok()
+}
+
+fn color(s: &str) -> Option<Color> {
+    hex(s).map(|v| Color {
+        alpha: None,
+        red: v as u8,
+        green: (v >> 8) as u8,
+        blue: (v >> 16) as u8,
+    })
+}
+
+fn numbers<const N: usize>(args: &[&str]) -> Option<[f64; N]> {
+    if args.len() != N {
+        return None;
+    }
+    let mut out = [0.0; N];
+    for (slot, arg) in out.iter_mut().zip(args) {
+        *slot = number(arg)?;
+    }
+    Some(out)
+}
+
+fn slot(digit: u8) -> ColorSlot {
+    match digit {
+        b'2' => ColorSlot::Secondary,
+        b'3' => ColorSlot::Outline,
+        b'4' => ColorSlot::Shadow,
+        _ => ColorSlot::Primary,
+    }
+}
+
+fn build_tag<'a>(name: &str, arg: Arg<'a>) -> Option<OverrideTag<'a>> {
+    use OverrideTag::*;
+
+    let arg = match arg {
+        Arg::Plain(arg) => arg,
+        Arg::Parens(args) => return build_function(name, args),
+    };
+
+    Some(match name {
+        "b" => Bold(optional(arg, integer)?),
+        "i" => Italic(optional(arg, flag)?),
+        "u" => Underline(optional(arg, flag)?),
+        "s" => StrikeOut(optional(arg, flag)?),
+        "bord" => Border(optional(arg, number)?),
+        "xbord" => XBorder(optional(arg, number)?),
+        "ybord" => YBorder(optional(arg, number)?),
+        "shad" => Shadow(optional(arg, number)?),
+        "xshad" => XShadow(optional(arg, number)?),
+        "yshad" => YShadow(optional(arg, number)?),
+        "be" => EdgeBlur(optional(arg, number)?),
+        "blur" => Blur(optional(arg, number)?),
+        "fn" => FontName(optional(arg, |v| Some(v.trim()))?),
+        "fs" => FontSize(optional(arg, number)?),
+        "fscx" => FontScaleX(optional(arg, number)?),
+        "fscy" => FontScaleY(optional(arg, number)?),
+        "fsp" => Spacing(optional(arg, number)?),
+        "frx" => RotationX(optional(arg, number)?),
+        "fry" => RotationY(optional(arg, number)?),
+        "frz" | "fr" => RotationZ(optional(arg, number)?),
+        "fax" => ShearX(optional(arg, number)?),
+        "fay" => ShearY(optional(arg, number)?),
+        "fe" => Encoding(optional(arg, integer)?),
+        "c" => Color(ColorSlot::Primary, optional(arg, color)?),
+        "1c" | "2c" | "3c" | "4c" => Color(slot(name.as_bytes()[0]), optional(arg, color)?),
+        "alpha" => Alpha(None, optional(arg, |v| hex(v).map(|v| v as u8))?),
+        "1a" | "2a" | "3a" | "4a" => Alpha(
+            Some(slot(name.as_bytes()[0])),
+            optional(arg, |v| hex(v).map(|v| v as u8))?,
+        ),
+        "an" => Alignment(optional(arg, |v| {
+            integer(v).filter(|v| (1..=9).contains(v))
+        })?),
+        "a" => LegacyAlignment(optional(arg, |v| {
+            integer(v).filter(|v| matches!(v, 1..=3 | 5..=7 | 9..=11))
+        })?),
+        "k" => Karaoke(KaraokeKind::Plain, number(arg)?),
+        "K" | "kf" => Karaoke(KaraokeKind::Fill, number(arg)?),
+        "ko" => Karaoke(KaraokeKind::Outline, number(arg)?),
+        "kt" => Karaoke(KaraokeKind::Time, number(arg)?),
+        "q" => WrapStyle(optional(arg, |v| integer(v).filter(|v| *v <= 3))?),
+        "r" => Reset(optional(arg, |v| Some(v.trim()))?),
+        "p" => Drawing(integer(arg)?),
+        "pbo" => BaselineOffset(number(arg)?),
+        _ => return None,
+    })
+}
+
+fn build_function<'a>(name: &str, args: &'a str) -> Option<OverrideTag<'a>> {
+    use OverrideTag::*;
+
+    if name == "t" {
+        return transform(args);
+    }
+
+    let split: Vec<&str> = args.split(',').map(str::trim).collect();
+    Some(match name {
+        "pos" => {
+            let [x, y] = numbers(&split)?;
+            Position { x, y }
+        }
+        "org" => {
+            let [x, y] = numbers(&split)?;
+            Origin { x, y }
+        }
+        "move" => match split.len() {
+            4 => {
+                let [x1, y1, x2, y2] = numbers(&split)?;
+                Move {
+                    x1,
+                    y1,
+                    x2,
+                    y2,
+                    times: None,
+                }
+            }
+            _ => {
+                let [x1, y1, x2, y2, t1, t2] = numbers(&split)?;
+                Move {
+                    x1,
+                    y1,
+                    x2,
+                    y2,
+                    times: Some((t1, t2)),
+                }
+            }
+        },
+        "fad" => {
+            let [fade_in, fade_out] = numbers(&split)?;
+            Fad { fade_in, fade_out }
+        }
+        "fade" => {
+            if split.len() != 7 {
+                return None;
+            }
+            let mut alpha = [0; 3];
+            for (slot, arg) in alpha.iter_mut().zip(&split) {
+                *slot = integer(arg)?;
+            }
+            Fade {
+                alpha,
+                times: numbers(&split[3..])?,
+            }
+        }
+        "clip" | "iclip" => Clip {
+            inverse: name == "iclip",
+            shape: clip_shape(args, &split)?,
+        },
+        _ => return None,
+    })
+}
+
+fn clip_shape<'a>(args: &'a str, split: &[&str]) -> Option<ClipShape<'a>> {
+    match split.len() {
+        4 => {
+            let [x1, y1, x2, y2] = numbers(split)?;
+            Some(ClipShape::Rect { x1, y1, x2, y2 })
+        }
+        1 if !args.trim().is_empty() => Some(ClipShape::Vector {
+            scale: None,
+            commands: args.trim(),
+        }),
+        2 => {
+            let (scale, commands) = args.split_once(',')?;
+            Some(ClipShape::Vector {
+                scale: Some(integer(scale)?),
+                commands: commands.trim(),
+            })
+        }
+        _ => None,
+    }
+}
+
+fn transform(args: &str) -> Option<OverrideTag<'_>> {
+    let tags_start = args.find('\\').unwrap_or(args.len());
+    let params: Vec<&str> = args[..tags_start]
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .collect();
+
+    let (times, accel) = match params.len() {
+        0 => (None, None),
+        1 => (None, Some(number(params[0])?)),
+        2 => {
+            let [t1, t2] = numbers(&params)?;
+            (Some((t1, t2)), None)
+        }
+        3 => {
+            let [t1, t2, accel] = numbers(&params)?;
+            (Some((t1, t2)), Some(accel))
+        }
+        _ => return None,
+    };
+
+    Some(OverrideTag::Transform {
+        times,
+        accel,
+        tags: parse_block(&args[tags_start..]),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aliases_parse_to_the_same_tag() {
+        assert_eq!(parse_block(r"\c&H0000FF&"), parse_block(r"\1c&H0000FF&"));
+        assert_eq!(
+            parse_block(r"\1c&H0000FF&"),
+            [OverrideTag::Color(
+                ColorSlot::Primary,
+                Some(Color::rgb(255, 0, 0))
+            )]
+        );
+        assert_eq!(parse_block(r"\fr45"), parse_block(r"\frz45"));
+        assert_eq!(parse_block(r"\fr45"), [OverrideTag::RotationZ(Some(45.0))]);
+        assert_eq!(parse_block(r"\K20"), parse_block(r"\kf20"));
+    }
+
+    #[test]
+    fn transforms_with_and_without_times() {
+        let tags = vec![OverrideTag::FontSize(Some(20.0))];
+        let cases = [
+            (r"\t(\fs20)", None, None),
+            (r"\t(0.5,\fs20)", None, Some(0.5)),
+            (r"\t(100,500,\fs20)", Some((100.0, 500.0)), None),
+            (r"\t(100,500,2,\fs20)", Some((100.0, 500.0)), Some(2.0)),
+        ];
+        for (block, times, accel) in cases {
+            assert_eq!(
+                parse_block(block),
+                [OverrideTag::Transform {
+                    times,
+                    accel,
+                    tags: tags.clone(),
+                }],
+                "{block}"
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_arguments_are_unknown() {
+        for block in [
+            r"\bordx",
+            r"\pos(1)",
+            r"\move(1,2,3)",
+            r"\an10",
+            r"\t(1,2,3,4,\fs1)",
+        ] {
+            assert_eq!(parse_block(block), [OverrideTag::Unknown(block)], "{block}");
+        }
+        assert_eq!(
+            parse_block(r"\foo\bord2"),
+            [
+                OverrideTag::Unknown(r"\foo"),
+                OverrideTag::Border(Some(2.0))
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_arguments_reset() {
+        assert_eq!(
+            parse_block(r"\bord\c\r"),
+            [
+                OverrideTag::Border(None),
+                OverrideTag::Color(ColorSlot::Primary, None),
+                OverrideTag::Reset(None),
+            ]
+        );
+    }
+
+    #[test]
+    fn comments_inside_blocks() {
+        assert_eq!(
+            parse_block(r"note\pos(1,2) more\b1"),
+            [
+                OverrideTag::Comment("note"),
+                OverrideTag::Position { x: 1.0, y: 2.0 },
+                OverrideTag::Comment(" more"),
+                OverrideTag::Bold(Some(1)),
+            ]
+        );
+        // a plain argument runs to the next tag, so text after it spoils the tag
+        assert_eq!(
+            parse_block(r"\i1 more"),
+            [OverrideTag::Unknown(r"\i1 more")]
+        );
+    }
+}
@@ -0,0 +1,366 @@
+use std::fmt::{self, Write};
+
+use crate::{models::events::EventLine, writer::WriteAss};
+
+use super::{tokenize, ClipShape, ColorSlot, KaraokeKind, OverrideTag, Token};
+
+/// Numbers are written with at most three decimals and without trailing zeros.
+pub(crate) struct Num(pub f64);
+
+impl fmt::Display for Num {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rounded = (self.0 * 1000.0).round() / 1000.0;
+        // adding zero turns -0 into 0
+        write!(f, "{}", rounded + 0.0)
+    }
+}
+
+fn write_opt<W: Write, T>(
+    out: &mut W,
+    name: &str,
+    value: &Option<T>,
+    write: impl FnOnce(&mut W, &T) -> fmt::Result,
+) -> fmt::Result {
+    write!(out, "\\{name}")?;
+    match value {
+        Some(v) => write(out, v),
+        None => Ok(()),
+    }
+}
+
+fn write_num<W: Write>(out: &mut W, name: &str, value: &Option<f64>) -> fmt::Result {
+    write_opt(out, name, value, |out, v| write!(out, "{}", Num(*v)))
+}
+
+fn write_display<W: Write, T: fmt::Display>(
+    out: &mut W,
+    name: &str,
+    value: &Option<T>,
+) -> fmt::Result {
+    write_opt(out, name, value, |out, v| write!(out, "{v}"))
+}
+
+fn write_flag<W: Write>(out: &mut W, name: &str, value: &Option<bool>) -> fmt::Result {
+    write_opt(out, name, value, |out, v| {
+        out.write_char(if *v { '1' } else { '0' })
+    })
+}
+
+fn write_args<W: Write>(out: &mut W, name: &str, args: &[f64]) -> fmt::Result {
+    write!(out, "\\{name}(")?;
+    for (idx, arg) in args.iter().enumerate() {
+        if idx > 0 {
+            out.write_char(',')?;
+        }
+        write!(out, "{}", Num(*arg))?;
+    }
+    out.write_char(')')
+}
+
+impl<'a> WriteAss for OverrideTag<'a> {
+    fn write_ass<W: Write>(&self, out: &mut W) -> fmt::Result {
+        use OverrideTag::*;
+        match self {
+            Bold(v) => write_display(out, "b", v),
+            Italic(v) => write_flag(out, "i", v),
+            Underline(v) => write_flag(out, "u", v),
+            StrikeOut(v) => write_flag(out, "s", v),
+            Border(v) => write_num(out, "bord", v),
+            XBorder(v) => write_num(out, "xbord", v),
+            YBorder(v) => write_num(out, "ybord", v),
+            Shadow(v) => write_num(out, "shad", v),
+            XShadow(v) => write_num(out, "xshad", v),
+            YShadow(v) => write_num(out, "yshad", v),
+            EdgeBlur(v) => write_num(out, "be", v),
+            Blur(v) => write_num(out, "blur", v),
+            FontName(v) => write_display(out, "fn", v),
+            FontSize(v) => write_num(out, "fs", v),
+            FontScaleX(v) => write_num(out, "fscx", v),
+            FontScaleY(v) => write_num(out, "fscy", v),
+            Spacing(v) => write_num(out, "fsp", v),
+            RotationX(v) => write_num(out, "frx", v),
+            RotationY(v) => write_num(out, "fry", v),
+            RotationZ(v) => write_num(out, "frz", v),
+            ShearX(v) => write_num(out, "fax", v),
+            ShearY(v) => write_num(out, "fay", v),
+            Encoding(v) => write_display(out, "fe", v),
+            Color(slot, v) => {
+                let name = match slot {
+                    ColorSlot::Primary => "c".to_owned(),
+                    slot => format!("{}c", *slot as u8),
+                };
+                write_opt(out, &name, v, |out, c| {
+                    write!(out, "&H{:02X}{:02X}{:02X}&", c.blue, c.green, c.red)
+                })
+            }
+            Alpha(slot, v) => {
+                let name = match slot {
+                    Some(slot) => format!("{}a", *slot as u8),
+                    None => "alpha".to_owned(),
+                };
+                write_opt(out, &name, v, |out, a| write!(out, "&H{a:02X}&"))
+            }
+            Alignment(v) => write_display(out, "an", v),
+            LegacyAlignment(v) => write_display(out, "a", v),
+            Karaoke(kind, duration) => {
+                let name = match kind {
+                    KaraokeKind::Plain => "k",
+                    KaraokeKind::Fill => "kf",
+                    KaraokeKind::Outline => "ko",
+                    KaraokeKind::Time => "kt",
+                };
+                write!(out, "\\{name}{}", Num(*duration))
+            }
+            WrapStyle(v) => write_display(out, "q", v),
+            Reset(v) => write_display(out, "r", v),
+            Position { x, y } => write_args(out, "pos", &[*x, *y]),
+            Move {
+                x1,
+                y1,
+                x2,
+                y2,
+                times: None,
+            } => write_args(out, "move", &[*x1, *y1, *x2, *y2]),
+            Move {
+                x1,
+                y1,
+                x2,
+                y2,
+                times: Some((t1, t2)),
+            } => write_args(out, "move", &[*x1, *y1, *x2, *y2, *t1, *t2]),
+            Origin { x, y } => write_args(out, "org", &[*x, *y]),
+            Fad { fade_in, fade_out } => write_args(out, "fad", &[*fade_in, *fade_out]),
+            Fade { alpha, times } => {
+                let [a1, a2, a3] = alpha;
+                let [t1, t2, t3, t4] = times;
+                write!(
+                    out,
+                    "\\fade({a1},{a2},{a3},{},{},{},{})",
+                    Num(*t1),
+                    Num(*t2),
+                    Num(*t3),
+                    Num(*t4)
+                )
+            }
+            Clip { inverse, shape } => {
+                let name = if *inverse { "iclip" } else { "clip" };
+                match shape {
+                    ClipShape::Rect { x1, y1, x2, y2 } => {
+                        write_args(out, name, &[*x1, *y1, *x2, *y2])
+                    }
+                    ClipShape::Vector {
+                        scale: Some(scale),
+                        commands,
+                    } => write!(out, "\\{name}({scale},{commands})"),
+                    ClipShape::Vector {
+                        scale: None,
+                        commands,
+                    } => write!(out, "\\{name}({commands})"),
+                }
+            }
+            Transform { times, accel, tags } => {
+                out.write_str("\\t(")?;
+                if let Some((t1, t2)) = times {
+                    write!(out, "{},{},", Num(*t1), Num(*t2))?;
+                }
+                if let Some(accel) = accel {
+                    write!(out, "{},", Num(*accel))?;
+                }
+                for tag in tags {
+                    tag.write_ass(out)?;
+                }
+                out.write_char(')')
+            }
+            Drawing(level) => write!(out, "\\p{level}"),
+            BaselineOffset(v) => write!(out, "\\pbo{}", Num(*v)),
+            Comment(text) | Unknown(text) => out.write_str(text),
+        }
+    }
+}
+
+impl<'a> OverrideTag<'a> {
+    /// The tag's name as it is written, without the backslash: `pos`, `c`, `3c`, `alpha`.
+    /// Comments have an empty name.
+    pub fn name(&self) -> &'a str {
+        use OverrideTag::*;
+        match self {
+            Bold(_) => "b",
+            Italic(_) => "i",
+            Underline(_) => "u",
+            StrikeOut(_) => "s",
+            Border(_) => "bord",
+            XBorder(_) => "xbord",
+            YBorder(_) => "ybord",
+            Shadow(_) => "shad",
+            XShadow(_) => "xshad",
+            YShadow(_) => "yshad",
+            EdgeBlur(_) => "be",
+            Blur(_) => "blur",
+            FontName(_) => "fn",
+            FontSize(_) => "fs",
+            FontScaleX(_) => "fscx",
+            FontScaleY(_) => "fscy",
+            Spacing(_) => "fsp",
+            RotationX(_) => "frx",
+            RotationY(_) => "fry",
+            RotationZ(_) => "frz",
+            ShearX(_) => "fax",
+            ShearY(_) => "fay",
+            Encoding(_) => "fe",
+            Color(slot, _) => match slot {
+                ColorSlot::Primary => "c",
+                ColorSlot::Secondary => "2c",
+                ColorSlot::Outline => "3c",
+                ColorSlot::Shadow => "4c",
+            },
+            Alpha(slot, _) => match slot {
+                None => "alpha",
+                Some(ColorSlot::Primary) => "1a",
+                Some(ColorSlot::Secondary) => "2a",
+                Some(ColorSlot::Outline) => "3a",
+                Some(ColorSlot::Shadow) => "4a",
+            },
+            Alignment(_) => "an",
+            LegacyAlignment(_) => "a",
+            Karaoke(kind, _) => match kind {
+                KaraokeKind::Plain => "k",
+                KaraokeKind::Fill => "kf",
+                KaraokeKind::Outline => "ko",
+                KaraokeKind::Time => "kt",
+            },
+            WrapStyle(_) => "q",
+            Reset(_) => "r",
+            Position { .. } => "pos",
+            Move { .. } => "move",
+            Origin { .. } => "org",
+            Fad { .. } => "fad",
+            Fade { .. } => "fade",
+            Clip { inverse: false, .. } => "clip",
+            Clip { inverse: true, .. } => "iclip",
+            Transform { .. } => "t",
+            Drawing(_) => "p",
+            BaselineOffset(_) => "pbo",
+            Comment(_) => "",
+            Unknown(raw) => super::parse::raw_name(raw),
+        }
+    }
+}
+
+impl<'a> WriteAss for Token<'a> {
+    fn write_ass<W: Write>(&self, out: &mut W) -> fmt::Result {
+        match self {
+            Token::Text(text) => out.write_str(text),
+            Token::HardBreak => out.write_str("\\N"),
+            Token::SoftBreak => out.write_str("\\n"),
+            Token::HardSpace => out.write_str("\\h"),
+            Token::Override(tags) => {
+                out.write_char('{')?;
+                for tag in tags {
+                    tag.write_ass(out)?;
+                }
+                out.write_char('}')
+            }
+            Token::Comment(text) => write!(out, "{{{text}}}"),
+        }
+    }
+}
+
+impl<'a> WriteAss for [Token<'a>] {
+    fn write_ass<W: Write>(&self, out: &mut W) -> fmt::Result {
+        self.iter().try_for_each(|token| token.write_ass(out))
+    }
+}
+
+impl<'a> EventLine<'a> {
+    /// Replaces the text of this line with `tokens`.
+    pub fn set_tokens(&mut self, tokens: &[Token<'_>]) {
+        self.text = tokens.to_ass_string().into();
+    }
+
+    /// Calls `convert` on every override tag, including those inside `\t`, to change it in
+    /// place; it returns whether it did. Only the override blocks with a changed tag are
+    /// rewritten, the rest of the text is kept as it was.
+    pub fn map_tags(&mut self, mut convert: impl FnMut(&mut OverrideTag<'_>) -> bool) {
+        let source = &*self.text;
+        let mut text = String::new();
+        // the end of the source text already in `text`
+        let mut copied = 0;
+        let mut tokens = tokenize(source);
+        loop {
+            let start = source.len() - tokens.rest().len();
+            let Some(token) = tokens.next() else {
+                break;
+            };
+            if let Token::Override(mut tags) = token {
+                if map_tags(&mut tags, &mut convert) {
+                    text.push_str(&source[copied..start]);
+                    Token::Override(tags).write_ass(&mut text).unwrap();
+                    copied = source.len() - tokens.rest().len();
+                }
+            }
+        }
+
+        if copied > 0 {
+            text.push_str(&source[copied..]);
+            self.text = text.into();
+        }
+    }
+}
+
+fn map_tags(
+    tags: &mut [OverrideTag<'_>],
+    convert: &mut impl FnMut(&mut OverrideTag<'_>) -> bool,
+) -> bool {
+    let mut changed = false;
+    for tag in tags {
+        changed |= convert(tag);
+        if let OverrideTag::Transform { tags, .. } = tag {
+            changed |= map_tags(tags, convert);
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(text: &str) -> EventLine<'_> {
+        EventLine {
+            text: text.into(),
+            ..Default::default()
+        }
+    }
+
+    fn double_font_size(tag: &mut OverrideTag<'_>) -> bool {
+        match tag {
+            OverrideTag::FontSize(Some(size)) => {
+                *size *= 2.0;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn map_tags_keeps_untouched_blocks() {
+        let mut event = line(r"{\1c&H0000FF&\fr10}a{\fs20}b\N{comment}c");
+        event.map_tags(double_font_size);
+        assert_eq!(event.text, r"{\1c&H0000FF&\fr10}a{\fs40}b\N{comment}c");
+    }
+
+    #[test]
+    fn map_tags_changes_tags_inside_transforms() {
+        let mut event = line(r"{\an8}x{\t(0,500,\fs10)}y");
+        event.map_tags(double_font_size);
+        assert_eq!(event.text, r"{\an8}x{\t(0,500,\fs20)}y");
+    }
+
+    #[test]
+    fn map_tags_without_changes_keeps_text() {
+        let mut event = line(r"{\frz10 \bord2}a{unclosed");
+        event.map_tags(double_font_size);
+        assert_eq!(event.text, r"{\frz10 \bord2}a{unclosed");
+        assert!(matches!(event.text, std::borrow::Cow::Borrowed(_)));
+    }
+}
@@ -0,0 +1,249 @@
+use crate::{Script, Timestamp};
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    start: Timestamp,
+    end: Timestamp,
+    layer: i64,
+    index: usize,
+}
+
+/// Finds the events shown at a time without scanning every line. Comments and lines without a
+/// positive duration are never shown, so they are left out.
+///
+/// The index holds positions into the script's events, so it has to be rebuilt after lines are
+/// added, removed or retimed.
+#[derive(Debug, Clone, Default)]
+pub struct EventIndex {
+    /// Sorted by start time.
+    entries: Vec<Entry>,
+    /// The latest end among the entries of the implicit binary tree rooted at each position:
+    /// the root of `lo..hi` is its midpoint.
+    max_end: Vec<Timestamp>,
+}
+
+impl EventIndex {
+    pub fn new(script: &Script<'_>) -> EventIndex {
+        let mut entries: Vec<Entry> = script
+            .events
+            .iter()
+            .enumerate()
+            .filter(|(_, event)| !event.is_comment)
+            .filter_map(|(index, event)| {
+                let (start, end) = (event.start?, event.end?);
+                (end > start).then_some(Entry {
+                    start,
+                    end,
+                    layer: event.layer.unwrap_or(0),
+                    index,
+                })
+            })
+            .collect();
+        entries.sort_by_key(|entry| (entry.start, entry.index));
+
+        let mut index = EventIndex {
+            max_end: vec![Timestamp::ZERO; entries.len()],
+            entries,
+        };
+        index.build(0, index.entries.len());
+        index
+    }
+
+    fn build(&mut self, lo: usize, hi: usize) -> Timestamp {
+        if lo >= hi {
+            return Timestamp(i64::MIN);
+        }
+        let mid = (lo + hi) / 2;
+        let max = self
+            .build(lo, mid)
+            .max(self.build(mid + 1, hi))
+            .max(self.entries[mid].end);
+        self.max_end[mid] = max;
+        max
+    }
+
+    /// Collects the entries among the first `count` of `lo..hi` that end after `after`.
+    fn collect(&self, lo: usize, hi: usize, count: usize, after: Timestamp, out: &mut Vec<Entry>) {
+        if lo >= hi.min(count) {
+            return;
+        }
+        let mid = (lo + hi) / 2;
+        if self.max_end[mid] <= after {
+            return;
+        }
+        self.collect(lo, mid, count, after, out);
+        if mid < count && self.entries[mid].end > after {
+            out.push(self.entries[mid]);
+        }
+        self.collect(mid + 1, hi, count, after, out);
+    }
+
+    /// Indices of the events shown at some point from `from` up to `to`, in the order renderers
+    /// draw them: by layer, then by position in the script.
+    pub fn overlapping(&self, from: Timestamp, to: Timestamp) -> Vec<usize> {
+        let count = self.entries.partition_point(|entry| entry.start < to);
+        let mut found = Vec::new();
+        self.collect(0, self.entries.len(), count, from, &mut found);
+        found.sort_by_key(|entry| (entry.layer, entry.index));
+        found.into_iter().map(|entry| entry.index).collect()
+    }
+
+    /// Indices of the events shown at `time`, in drawing order. A line is shown from its start
+    /// up to, but not including, its end.
+    pub fn active_at(&self, time: Timestamp) -> Vec<usize> {
+        self.overlapping(time, time + Timestamp::from_centis(1))
+    }
+
+    /// The times at which the set of shown events changes, in order.
+    pub fn change_points(&self) -> Vec<Timestamp> {
+        let mut times: Vec<Timestamp> = self
+            .entries
+            .iter()
+            .flat_map(|entry| [entry.start, entry.end])
+            .collect();
+        times.sort();
+        times.dedup();
+        times
+    }
+
+    /// How many events the index holds.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::events::EventLine;
+
+    fn event(start: i64, end: i64, layer: i64) -> EventLine<'static> {
+        EventLine {
+            start: Some(Timestamp(start)),
+            end: Some(Timestamp(end)),
+            layer: Some(layer),
+            ..EventLine::default()
+        }
+    }
+
+    /// The events shown from `from` up to `to`, found by looking at every line.
+    fn scan(script: &Script<'_>, from: Timestamp, to: Timestamp) -> Vec<usize> {
+        let mut found: Vec<(i64, usize)> = script
+            .events
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| !e.is_comment)
+            .filter_map(|(idx, e)| {
+                let (start, end) = (e.start?, e.end?);
+                (start < end && start < to && end > from).then_some((e.layer.unwrap_or(0), idx))
+            })
+            .collect();
+        found.sort();
+        found.into_iter().map(|(_, idx)| idx).collect()
+    }
+
+    /// A script with lines from a fixed pseudo-random sequence, including equal starts,
+    /// zero-length and reversed lines, comments and lines without times.
+    fn random_script() -> Script<'static> {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut next = |bound: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % bound) as i64
+        };
+        let mut script = Script::default();
+        for idx in 0..300 {
+            let start = next(50) * 10;
+            let end = start + next(120) - 10;
+            let mut line = event(start, end, next(4) - 1);
+            match idx % 23 {
+                0 => line.is_comment = true,
+                1 => line.end = None,
+                _ => {}
+            }
+            script.events.push(line);
+        }
+        script
+    }
+
+    #[test]
+    fn overlapping_matches_a_scan() {
+        let script = random_script();
+        let index = EventIndex::new(&script);
+        for from in (-20..620).step_by(7) {
+            for length in [0, 1, 5, 40, 300] {
+                let (from, to) = (Timestamp(from), Timestamp(from + length));
+                assert_eq!(
+                    index.overlapping(from, to),
+                    scan(&script, from, to),
+                    "{from}-{to}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn active_at_matches_a_scan() {
+        let script = random_script();
+        let index = EventIndex::new(&script);
+        for time in -5..620 {
+            let time = Timestamp(time);
+            assert_eq!(
+                index.active_at(time),
+                scan(&script, time, time + Timestamp(1)),
+                "{time}"
+            );
+        }
+    }
+
+    #[test]
+    fn change_points_are_where_the_scan_changes() {
+        let script = random_script();
+        let index = EventIndex::new(&script);
+        let points = index.change_points();
+        let mut expected = Vec::new();
+        let mut shown = scan(&script, Timestamp(-1), Timestamp(0));
+        for time in 0..700 {
+            let now = scan(&script, Timestamp(time), Timestamp(time + 1));
+            if now != shown {
+                expected.push(Timestamp(time));
+                shown = now;
+            }
+        }
+        assert_eq!(points, expected);
+    }
+
+    #[test]
+    fn equal_starts_and_layers() {
+        let script = Script {
+            events: vec![
+                event(0, 100, 1),
+                event(0, 100, 0),
+                event(0, 0, 0),
+                event(0, 50, 1),
+                event(50, 50, 0),
+                event(100, 200, -1),
+            ],
+            ..Script::default()
+        };
+        let index = EventIndex::new(&script);
+        assert_eq!(index.len(), 4);
+        assert_eq!(index.active_at(Timestamp(0)), [1, 0, 3]);
+        assert_eq!(index.active_at(Timestamp(50)), [1, 0]);
+        assert_eq!(index.active_at(Timestamp(100)), [5]);
+        assert_eq!(
+            index.overlapping(Timestamp(40), Timestamp(110)),
+            [5, 1, 0, 3]
+        );
+        assert_eq!(
+            index.change_points(),
+            [Timestamp(0), Timestamp(50), Timestamp(100), Timestamp(200)]
+        );
+        assert!(EventIndex::new(&Script::default()).is_empty());
+    }
+}
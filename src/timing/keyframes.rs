@@ -0,0 +1,322 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{models::events::EventLine, Span};
+
+use super::{FrameTime, Framerate, TimecodeLines};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyframeError {
+    UnknownFormat { span: Span },
+    InvalidLine { span: Span },
+}
+
+impl KeyframeError {
+    pub fn span(&self) -> Span {
+        match self {
+            KeyframeError::UnknownFormat { span } | KeyframeError::InvalidLine { span } => *span,
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            KeyframeError::UnknownFormat { .. } => "not a recognised keyframe file",
+            KeyframeError::InvalidLine { .. } => "invalid keyframe line",
+        }
+    }
+}
+
+impl fmt::Display for KeyframeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}", self.message(), self.span())
+    }
+}
+
+impl std::error::Error for KeyframeError {}
+
+/// Sorted keyframe numbers.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Keyframes {
+    frames: Vec<i64>,
+    /// The framerate an Aegisub keyframe file was written with.
+    pub fps: Option<f64>,
+}
+
+impl Keyframes {
+    pub fn new(mut frames: Vec<i64>) -> Keyframes {
+        frames.sort_unstable();
+        frames.dedup();
+        Keyframes { frames, fps: None }
+    }
+
+    pub fn frames(&self) -> &[i64] {
+        &self.frames
+    }
+
+    pub fn is_keyframe(&self, frame: i64) -> bool {
+        self.frames.binary_search(&frame).is_ok()
+    }
+
+    /// The keyframe nearest to `frame`, the earlier one on a tie.
+    pub fn closest(&self, frame: i64) -> Option<i64> {
+        let idx = self.frames.partition_point(|k| *k < frame);
+        let after = self.frames.get(idx).copied();
+        let before = idx.checked_sub(1).map(|idx| self.frames[idx]);
+        match (before, after) {
+            (Some(b), Some(a)) if a - frame < frame - b => Some(a),
+            (Some(b), _) => Some(b),
+            (None, a) => a,
+        }
+    }
+
+    /// The nearest keyframe at most `ahead` frames after `frame` or `behind` frames before it,
+    /// the earlier one on a tie. `None` if `frame` is already a keyframe.
+    fn nearby(&self, frame: i64, ahead: i64, behind: i64) -> Option<i64> {
+        let idx = self.frames.partition_point(|k| *k < frame);
+        let after = self.frames.get(idx).copied();
+        if after == Some(frame) {
+            return None;
+        }
+        let before = idx.checked_sub(1).map(|idx| self.frames[idx]);
+        let before = before.filter(|k| frame - k <= behind);
+        let after = after.filter(|k| k - frame <= ahead);
+        match (before, after) {
+            (Some(b), Some(a)) if a - frame < frame - b => Some(a),
+            (before, after) => before.or(after),
+        }
+    }
+
+    /// Reads an Aegisub keyframe file, an XviD pass file, x264 stats, a qpfile, or a plain list
+    /// of frame numbers, one per line.
+    pub fn parse(data: &str) -> Result<Keyframes, KeyframeError> {
+        let mut lines = TimecodeLines::new(data);
+        let (header, span) = lines.next_raw().ok_or(KeyframeError::UnknownFormat {
+            span: Span::start_of_line(0, 1),
+        })?;
+        let header = header.trim_start_matches('\u{feff}').trim();
+
+        if header.eq_ignore_ascii_case("# keyframe format v1") {
+            parse_aegisub(lines)
+        } else if header.starts_with("# XviD 2pass stat file") {
+            Ok(parse_xvid(lines))
+        } else if header.starts_with("#options:") {
+            parse_x264_stats(lines)
+        } else {
+            parse_list(TimecodeLines::new(data)).map_err(|e| match e {
+                KeyframeError::InvalidLine { span: s } if s.line == 1 => {
+                    KeyframeError::UnknownFormat { span }
+                }
+                e => e,
+            })
+        }
+    }
+
+    /// Moves the start and end of `event` onto nearby keyframes, like Aegisub's timing
+    /// post-processor. A line that would end up ending at or before its start is left as it
+    /// is. Returns whether the line changed.
+    pub fn snap(
+        &self,
+        event: &mut EventLine<'_>,
+        framerate: &Framerate,
+        thresholds: &SnapThresholds,
+    ) -> bool {
+        let start = event.start.and_then(|start| {
+            let frame = framerate.frame_at_time(start, FrameTime::Start);
+            let key = self.nearby(frame, thresholds.before_start, thresholds.after_start)?;
+            Some(framerate.time_at_frame(key, FrameTime::Start))
+        });
+
+        let end = event.end.and_then(|end| {
+            // a line snapped to a keyframe ends on the frame before it, so distances are
+            // measured from the frame after the end
+            let next = framerate.frame_at_time(end, FrameTime::End) + 1;
+            let key = self.nearby(next, thresholds.before_end, thresholds.after_end)?;
+            Some(framerate.time_at_frame(key - 1, FrameTime::End))
+        });
+
+        if start.is_none() && end.is_none() {
+            return false;
+        }
+        if let (Some(start), Some(end)) = (start.or(event.start), end.or(event.end)) {
+            if end <= start {
+                return false;
+            }
+        }
+        event.start = start.or(event.start);
+        event.end = end.or(event.end);
+        true
+    }
+
+    /// Snaps every line and returns how many changed.
+    pub fn snap_all(
+        &self,
+        events: &mut [EventLine<'_>],
+        framerate: &Framerate,
+        thresholds: &SnapThresholds,
+    ) -> usize {
+        events
+            .iter_mut()
+            .filter(|e| !e.is_comment)
+            .map(|e| self.snap(e, framerate, thresholds))
+            .filter(|changed| *changed)
+            .count()
+    }
+}
+
+/// How far, in frames, a line boundary may move to reach a keyframe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapThresholds {
+    /// A line starting this many frames before a keyframe is moved forward onto it.
+    pub before_start: i64,
+    /// A line starting this many frames after a keyframe is moved back onto it.
+    pub after_start: i64,
+    /// A line ending this many frames before a keyframe is extended up to it.
+    pub before_end: i64,
+    /// A line ending this many frames after a keyframe is cut back to it.
+    pub after_end: i64,
+}
+
+impl Default for SnapThresholds {
+    fn default() -> Self {
+        SnapThresholds {
+            before_start: 5,
+            after_start: 4,
+            before_end: 5,
+            after_end: 4,
+        }
+    }
+}
+
+fn frame_number(s: &str, span: Span) -> Result<i64, KeyframeError> {
+    s.parse::<i64>()
+        .ok()
+        .filter(|v| *v >= 0)
+        .ok_or(KeyframeError::InvalidLine { span })
+}
+
+fn parse_aegisub(lines: TimecodeLines<'_>) -> Result<Keyframes, KeyframeError> {
+    let mut frames = Vec::new();
+    let mut fps = None;
+    for (line, span) in lines {
+        if let Some(value) = line.strip_prefix("fps") {
+            fps = Some(
+                value
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|_| KeyframeError::InvalidLine { span })?,
+            )
+            .filter(|v| *v > 0.0);
+            continue;
+        }
+        frames.push(frame_number(line, span)?);
+    }
+
+    Ok(Keyframes {
+        fps,
+        ..Keyframes::new(frames)
+    })
+}
+
+/// Each line after the header is one frame; `i` frames are keyframes.
+fn parse_xvid(lines: TimecodeLines<'_>) -> Keyframes {
+    let mut frames = Vec::new();
+    let mut count = 0;
+    for (line, _) in lines {
+        match line.as_bytes()[0] {
+            b'i' => {
+                frames.push(count);
+                count += 1;
+            }
+            b'p' | b'b' | b's' => count += 1,
+            _ => {}
+        }
+    }
+    Keyframes::new(frames)
+}
+
+/// Lines look like `in:0 out:0 type:I ...`; the frame number is the output one.
+fn parse_x264_stats(lines: TimecodeLines<'_>) -> Result<Keyframes, KeyframeError> {
+    let mut frames = Vec::new();
+    for (line, span) in lines {
+        let mut out = None;
+        let mut keyframe = false;
+        for field in line.split_whitespace() {
+            match field.split_once(':') {
+                Some(("out", v)) => out = Some(frame_number(v, span)?),
+                Some(("type", v)) => keyframe = matches!(v, "I" | "i"),
+                _ => {}
+            }
+        }
+        let out = out.ok_or(KeyframeError::InvalidLine { span })?;
+        if keyframe {
+            frames.push(out);
+        }
+    }
+    Ok(Keyframes::new(frames))
+}
+
+/// Plain frame numbers, or qpfile lines of a frame number, a frame type and an optional QP.
+/// In a qpfile only `I` and `K` frames are keyframes.
+fn parse_list(lines: TimecodeLines<'_>) -> Result<Keyframes, KeyframeError> {
+    let mut frames = Vec::new();
+    for (line, span) in lines {
+        let mut fields = line.split_whitespace();
+        let frame = frame_number(fields.next().unwrap_or_default(), span)?;
+        match fields.next() {
+            None | Some("I" | "K") => frames.push(frame),
+            Some("i" | "P" | "B" | "b") => {}
+            Some(_) => return Err(KeyframeError::InvalidLine { span }),
+        }
+    }
+    Ok(Keyframes::new(frames))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(framerate: &Framerate, start: i64, end: i64) -> EventLine<'static> {
+        EventLine {
+            start: Some(framerate.time_at_frame(start, FrameTime::Start)),
+            end: Some(framerate.time_at_frame(end, FrameTime::End)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn snap_moves_boundaries_onto_keyframes() {
+        let framerate = Framerate::cfr(24, 1).unwrap();
+        let keyframes = Keyframes::new(vec![0, 48, 120]);
+        let mut event = line(&framerate, 50, 116);
+        assert!(keyframes.snap(&mut event, &framerate, &SnapThresholds::default()));
+        assert_eq!(framerate.event_frames(&event), (48, 119));
+    }
+
+    #[test]
+    fn snap_never_inverts_a_line() {
+        let framerate = Framerate::cfr(24, 1).unwrap();
+        let keyframes = Keyframes::new(vec![0, 10]);
+        let mut event = line(&framerate, 7, 8);
+        let (start, end) = (event.start, event.end);
+        assert!(!keyframes.snap(&mut event, &framerate, &SnapThresholds::default()));
+        assert_eq!((event.start, event.end), (start, end));
+    }
+
+    #[test]
+    fn snap_checks_keyframes_on_both_sides() {
+        let framerate = Framerate::cfr(24, 1).unwrap();
+        let thresholds = SnapThresholds::default();
+        // 100 is too far back for the end, but 110 is close enough ahead
+        let keyframes = Keyframes::new(vec![100, 110]);
+        let mut event = line(&framerate, 90, 104);
+        assert!(keyframes.snap(&mut event, &framerate, &thresholds));
+        assert_eq!(framerate.event_frames(&event), (90, 109));
+
+        // the same for a start 5 frames after one keyframe and 5 before the next
+        let keyframes = Keyframes::new(vec![10, 20]);
+        let mut event = line(&framerate, 15, 40);
+        assert!(keyframes.snap(&mut event, &framerate, &thresholds));
+        assert_eq!(framerate.event_frames(&event), (20, 40));
+    }
+}
@@ -0,0 +1,400 @@
+use std::fmt;
+
+use crate::{models::events::EventLine, Span, Timestamp};
+
+pub mod index;
+pub mod keyframes;
+pub mod retime;
+
+/// Which time a frame stands for, following Aegisub's rules.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FrameTime {
+    /// The moment the frame is first displayed.
+    #[default]
+    Exact,
+    /// A line starting on the frame; the time is halfway between the frame and the one before it.
+    Start,
+    /// A line ending on the frame; the time is halfway between the frame and the one after it.
+    End,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimecodeError {
+    UnknownFormat { span: Span },
+    InvalidLine { span: Span },
+    InvalidFramerate { span: Span },
+    OverlappingRange { span: Span },
+    OutOfOrder { span: Span },
+    TooFewTimecodes { span: Span },
+    TooManyFrames { span: Span },
+}
+
+impl TimecodeError {
+    pub fn span(&self) -> Span {
+        use TimecodeError::*;
+        match self {
+            UnknownFormat { span }
+            | InvalidLine { span }
+            | InvalidFramerate { span }
+            | OverlappingRange { span }
+            | OutOfOrder { span }
+            | TooFewTimecodes { span }
+            | TooManyFrames { span } => *span,
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        use TimecodeError::*;
+        match self {
+            UnknownFormat { .. } => "not a v1 or v2 timecode file",
+            InvalidLine { .. } => "invalid timecode line",
+            InvalidFramerate { .. } => "framerate must be positive",
+            OverlappingRange { .. } => "frame range overlaps an earlier one",
+            OutOfOrder { .. } => "timecodes are out of order",
+            TooFewTimecodes { .. } => "timecode file needs at least two timecodes",
+            TooManyFrames { .. } => "frame range ends too far into the video",
+        }
+    }
+}
+
+impl fmt::Display for TimecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}", self.message(), self.span())
+    }
+}
+
+impl std::error::Error for TimecodeError {}
+
+/// Maps frame numbers to times in milliseconds. Frames past the end of a timecode list are
+/// extrapolated with the list's average framerate (or the assumed framerate of a v1 file).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Framerate {
+    numerator: i64,
+    denominator: i64,
+    timecodes: Vec<i64>,
+}
+
+/// Scale used for framerates given as decimals.
+const FPS_DENOMINATOR: i64 = 1_000_000;
+
+/// v1 files are expanded to one timecode per frame, so ranges must end before this frame:
+/// about 46 hours at 60 fps.
+const MAX_V1_FRAMES: i64 = 10_000_000;
+
+fn div_floor(a: i128, b: i128) -> i128 {
+    let q = a / b;
+    if (a % b != 0) && ((a < 0) != (b < 0)) {
+        q - 1
+    } else {
+        q
+    }
+}
+
+fn decimal_fps(fps: f64) -> Option<i64> {
+    let numerator = (fps * FPS_DENOMINATOR as f64).round();
+    (fps.is_finite() && numerator >= 1.0).then_some(numerator as i64)
+}
+
+impl Framerate {
+    /// A constant framerate of `numerator / denominator` frames per second, e.g. `24000 / 1001`.
+    pub fn cfr(numerator: i64, denominator: i64) -> Option<Framerate> {
+        (numerator > 0 && denominator > 0).then(|| Framerate {
+            numerator,
+            denominator,
+            timecodes: vec![0],
+        })
+    }
+
+    pub fn from_fps(fps: f64) -> Option<Framerate> {
+        Framerate::cfr(decimal_fps(fps)?, FPS_DENOMINATOR)
+    }
+
+    /// A variable framerate from the display time of every frame in milliseconds. The times are
+    /// shifted so the first frame starts at zero.
+    pub fn from_timecodes(mut timecodes: Vec<i64>) -> Option<Framerate> {
+        if timecodes.len() < 2 || timecodes.windows(2).any(|w| w[0] > w[1]) {
+            return None;
+        }
+        let first = timecodes[0];
+        timecodes.iter_mut().for_each(|t| *t -= first);
+        let last = *timecodes.last()?;
+        if last == 0 {
+            return None;
+        }
+        Some(Framerate {
+            numerator: (timecodes.len() as i64 - 1) * 1000,
+            denominator: last,
+            timecodes,
+        })
+    }
+
+    /// Reads an mkvmerge timecode file in format v1 or v2.
+    pub fn parse_timecodes(data: &str) -> Result<Framerate, TimecodeError> {
+        let mut lines = TimecodeLines::new(data);
+        let (header, span) = lines.next_raw().ok_or(TimecodeError::UnknownFormat {
+            span: Span::start_of_line(0, 1),
+        })?;
+        let header = header.trim_start_matches('\u{feff}').trim();
+        if header.eq_ignore_ascii_case("# timecode format v1") {
+            parse_v1(lines)
+        } else if header.eq_ignore_ascii_case("# timecode format v2") {
+            parse_v2(lines)
+        } else {
+            Err(TimecodeError::UnknownFormat { span })
+        }
+    }
+
+    pub fn is_vfr(&self) -> bool {
+        self.timecodes.len() > 1
+    }
+
+    /// Average frames per second.
+    pub fn fps(&self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+
+    /// Average frames per second as a `(numerator, denominator)` fraction.
+    pub fn ratio(&self) -> (i64, i64) {
+        (self.numerator, self.denominator)
+    }
+
+    pub fn timecodes(&self) -> &[i64] {
+        &self.timecodes
+    }
+
+    fn last_frame(&self) -> i64 {
+        self.timecodes.len() as i64 - 1
+    }
+
+    fn exact_time(&self, frame: i64) -> i64 {
+        if let Some(&time) = usize::try_from(frame)
+            .ok()
+            .and_then(|idx| self.timecodes.get(idx))
+        {
+            return time;
+        }
+
+        let last_frame = self.last_frame();
+        let anchor = if frame < 0 { 0 } else { last_frame };
+        let base = self.timecodes[anchor as usize] as i128;
+        let frames = (frame - anchor) as i128;
+        let (num, den) = (self.numerator as i128, self.denominator as i128);
+        (base + div_floor(frames * 1000 * den * 2 + num, num * 2)) as i64
+    }
+
+    fn exact_frame(&self, ms: i64) -> i64 {
+        let last = *self.timecodes.last().expect("timecodes are never empty");
+        if (0..=last).contains(&ms) {
+            return self.timecodes.partition_point(|t| *t <= ms) as i64 - 1;
+        }
+
+        // the last frame whose extrapolated time is at or before `ms`
+        let anchor = if ms < 0 { 0 } else { self.last_frame() };
+        let base = self.timecodes[anchor as usize] as i128;
+        let (num, den) = (self.numerator as i128, self.denominator as i128);
+        let offset = (ms as i128 - base + 1) * num * 2 - num;
+        let frames = -div_floor(-offset, 1000 * den * 2) - 1;
+        anchor + frames as i64
+    }
+
+    /// Time of `frame` in milliseconds.
+    pub fn time_at_frame_ms(&self, frame: i64, kind: FrameTime) -> i64 {
+        match kind {
+            FrameTime::Exact => self.exact_time(frame),
+            FrameTime::Start => {
+                let prev = self.exact_time(frame - 1);
+                let cur = self.exact_time(frame);
+                prev + (cur - prev + 1) / 2
+            }
+            FrameTime::End => {
+                let cur = self.exact_time(frame);
+                let next = self.exact_time(frame + 1);
+                cur + (next - cur + 1) / 2
+            }
+        }
+    }
+
+    /// The frame shown at `ms` milliseconds, or the first or last frame of a line starting or
+    /// ending at that time.
+    pub fn frame_at_ms(&self, ms: i64, kind: FrameTime) -> i64 {
+        match kind {
+            FrameTime::Exact => self.exact_frame(ms),
+            FrameTime::Start => self.exact_frame(ms - 1) + 1,
+            FrameTime::End => self.exact_frame(ms - 1),
+        }
+    }
+
+    pub fn time_at_frame(&self, frame: i64, kind: FrameTime) -> Timestamp {
+        Timestamp::from_millis(self.time_at_frame_ms(frame, kind))
+    }
+
+    pub fn frame_at_time(&self, time: Timestamp, kind: FrameTime) -> i64 {
+        self.frame_at_ms(time.millis(), kind)
+    }
+
+    /// The first and last frame `event` is shown on. The last frame is before the first one if
+    /// the line is too short to cover a frame.
+    pub fn event_frames(&self, event: &EventLine<'_>) -> (i64, i64) {
+        (
+            self.frame_at_time(event.start.unwrap_or_default(), FrameTime::Start),
+            self.frame_at_time(event.end.unwrap_or_default(), FrameTime::End),
+        )
+    }
+}
+
+struct TimecodeLines<'a> {
+    rest: &'a str,
+    offset: usize,
+    line: usize,
+}
+
+impl<'a> TimecodeLines<'a> {
+    fn new(data: &'a str) -> Self {
+        TimecodeLines {
+            rest: data,
+            offset: 0,
+            line: 1,
+        }
+    }
+
+    fn next_raw(&mut self) -> Option<(&'a str, Span)> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let (line, rest) = self.rest.split_once('\n').unwrap_or((self.rest, ""));
+        let span = Span::start_of_line(self.offset, self.line);
+        self.offset += self.rest.len() - rest.len();
+        self.line += 1;
+        self.rest = rest;
+        Some((line.strip_suffix('\r').unwrap_or(line), span))
+    }
+}
+
+impl<'a> Iterator for TimecodeLines<'a> {
+    type Item = (&'a str, Span);
+
+    /// Skips blank lines and `#` comments.
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (line, span) = self.next_raw()?;
+            let trimmed = line.trim();
+            if !trimmed.is_empty() && !trimmed.starts_with('#') {
+                return Some((trimmed, span.of_subslice(line, trimmed)));
+            }
+        }
+    }
+}
+
+fn parse_v2(lines: TimecodeLines<'_>) -> Result<Framerate, TimecodeError> {
+    let mut timecodes = Vec::new();
+    let mut last_span = Span::start_of_line(0, 1);
+    for (line, span) in lines {
+        let time = line
+            .parse::<f64>()
+            .ok()
+            .filter(|v| v.is_finite())
+            .ok_or(TimecodeError::InvalidLine { span })?
+            .round() as i64;
+        if timecodes.last().is_some_and(|last| *last > time) {
+            return Err(TimecodeError::OutOfOrder { span });
+        }
+        timecodes.push(time);
+        last_span = span;
+    }
+
+    Framerate::from_timecodes(timecodes).ok_or(TimecodeError::TooFewTimecodes { span: last_span })
+}
+
+fn parse_v1(mut lines: TimecodeLines<'_>) -> Result<Framerate, TimecodeError> {
+    let (assume, span) = lines.next().ok_or(TimecodeError::InvalidLine {
+        span: Span::start_of_line(lines.offset, lines.line),
+    })?;
+    let assumed = assume
+        .get(..6)
+        .filter(|v| v.eq_ignore_ascii_case("assume"))
+        .and_then(|_| assume[6..].trim().parse::<f64>().ok())
+        .ok_or(TimecodeError::InvalidLine { span })?;
+    let assumed_num = decimal_fps(assumed).ok_or(TimecodeError::InvalidFramerate { span })?;
+
+    let mut ranges: Vec<(i64, i64, f64, Span)> = Vec::new();
+    for (line, span) in lines {
+        let mut parts = line.split(',').map(str::trim);
+        let (Some(start), Some(end), Some(fps), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(TimecodeError::InvalidLine { span });
+        };
+        let (Ok(start), Ok(end), Ok(fps)) =
+            (start.parse::<i64>(), end.parse::<i64>(), fps.parse::<f64>())
+        else {
+            return Err(TimecodeError::InvalidLine { span });
+        };
+        if start < 0 || end < start {
+            return Err(TimecodeError::InvalidLine { span });
+        }
+        if end >= MAX_V1_FRAMES {
+            return Err(TimecodeError::TooManyFrames { span });
+        }
+        if !(fps.is_finite() && fps > 0.0) {
+            return Err(TimecodeError::InvalidFramerate { span });
+        }
+        ranges.push((start, end, fps, span));
+    }
+
+    ranges.sort_by_key(|r| r.0);
+    for pair in ranges.windows(2) {
+        if pair[1].0 <= pair[0].1 {
+            return Err(TimecodeError::OverlappingRange { span: pair[1].3 });
+        }
+    }
+
+    let Some(frames) = ranges.last().map(|r| r.1 + 2) else {
+        return Ok(Framerate {
+            numerator: assumed_num,
+            denominator: FPS_DENOMINATOR,
+            timecodes: vec![0],
+        });
+    };
+
+    let mut timecodes = Vec::with_capacity(frames as usize);
+    let mut ranges = ranges.iter().peekable();
+    let mut time = 0.0f64;
+    for frame in 0..frames {
+        timecodes.push(time.round() as i64);
+        while ranges.next_if(|r| r.1 < frame).is_some() {}
+        let fps = match ranges.peek() {
+            Some(r) if r.0 <= frame => r.2,
+            _ => assumed,
+        };
+        time += 1000.0 / fps;
+    }
+
+    Ok(Framerate {
+        numerator: assumed_num,
+        denominator: FPS_DENOMINATOR,
+        timecodes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn v1_ranges_override_the_assumed_rate() {
+        let framerate =
+            Framerate::parse_timecodes("# timecode format v1\nAssume 25\n2,3,50\n").unwrap();
+        assert_eq!(framerate.timecodes(), [0, 40, 80, 100, 120]);
+    }
+
+    #[test]
+    fn huge_v1_ranges_are_rejected() {
+        for range in ["0,2000000000,24", "0,9223372036854775807,24"] {
+            let data = format!("# timecode format v1\nAssume 23.976\n{range}\n");
+            assert!(matches!(
+                Framerate::parse_timecodes(&data),
+                Err(TimecodeError::TooManyFrames { .. })
+            ));
+        }
+    }
+}
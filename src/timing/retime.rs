@@ -0,0 +1,217 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{models::events::EventLine, tags::OverrideTag, Timestamp};
+
+/// A linear change to line times: each time `t` becomes `t * ratio + offset`. Times that would
+/// become negative stop at zero.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Retime {
+    ratio: f64,
+    /// In centiseconds.
+    offset: f64,
+}
+
+impl Default for Retime {
+    fn default() -> Self {
+        Retime {
+            ratio: 1.0,
+            offset: 0.0,
+        }
+    }
+}
+
+impl Retime {
+    /// Moves every time by `offset`.
+    pub fn shift(offset: Timestamp) -> Retime {
+        Retime {
+            ratio: 1.0,
+            offset: offset.centis() as f64,
+        }
+    }
+
+    /// Multiplies every time by `ratio`, which must be positive.
+    pub fn scale(ratio: f64) -> Option<Retime> {
+        (ratio.is_finite() && ratio > 0.0).then_some(Retime { ratio, offset: 0.0 })
+    }
+
+    /// Keeps lines on the same frames when the video changes from `from` to `to` frames per
+    /// second, e.g. from 25 to 23.976 to undo PAL speedup.
+    pub fn framerate(from: f64, to: f64) -> Option<Retime> {
+        Retime::scale(from / to)
+    }
+
+    /// Maps `a` to `new_a` and `b` to `new_b`, stretching the times in between and moving those
+    /// outside along the same line. The points must keep their order.
+    pub fn resync(
+        (a, new_a): (Timestamp, Timestamp),
+        (b, new_b): (Timestamp, Timestamp),
+    ) -> Option<Retime> {
+        let ratio = (new_b - new_a).centis() as f64 / (b - a).centis() as f64;
+        let retime = Retime::scale(ratio)?;
+        Some(Retime {
+            offset: new_a.centis() as f64 - a.centis() as f64 * ratio,
+            ..retime
+        })
+    }
+
+    pub fn ratio(&self) -> f64 {
+        self.ratio
+    }
+
+    pub fn apply(&self, time: Timestamp) -> Timestamp {
+        let centis = (time.centis() as f64 * self.ratio + self.offset).round();
+        Timestamp::from_centis(centis as i64).max(Timestamp::ZERO)
+    }
+
+    /// Retimes `event`. Times in override tags are relative to the start of the line, so they
+    /// only change when the retime changes lengths: `\move` and `\t` times, `\fad` and `\fade`
+    /// and karaoke durations are then scaled, to whole units since VSFilter reads them as
+    /// integers. Karaoke durations are rounded on their running total, so the syllables still
+    /// add up to the scaled length of the line's karaoke.
+    pub fn apply_to(&self, event: &mut EventLine<'_>) {
+        event.start = event.start.map(|t| self.apply(t));
+        event.end = event.end.map(|t| self.apply(t));
+        if self.ratio == 1.0 {
+            return;
+        }
+
+        let ratio = self.ratio;
+        // the karaoke time so far, before and after rounding
+        let (mut karaoke, mut karaoke_rounded) = (0.0, 0.0);
+        event.map_tags(|tag| {
+            match tag {
+                OverrideTag::Move {
+                    times: Some((t1, t2)),
+                    ..
+                }
+                | OverrideTag::Transform {
+                    times: Some((t1, t2)),
+                    ..
+                }
+                | OverrideTag::Fad {
+                    fade_in: t1,
+                    fade_out: t2,
+                } => {
+                    *t1 = (*t1 * ratio).round();
+                    *t2 = (*t2 * ratio).round();
+                }
+                OverrideTag::Fade { times, .. } => {
+                    times.iter_mut().for_each(|t| *t = (*t * ratio).round())
+                }
+                OverrideTag::Karaoke(_, duration) => {
+                    karaoke += *duration * ratio;
+                    *duration = karaoke.round() - karaoke_rounded;
+                    karaoke_rounded = karaoke.round();
+                }
+                _ => return false,
+            }
+            true
+        });
+    }
+
+    /// Retimes the events `filter` picks and returns the indices of those left with a zero or
+    /// negative duration.
+    pub fn apply_to_events(
+        &self,
+        events: &mut [EventLine<'_>],
+        mut filter: impl FnMut(&EventLine<'_>) -> bool,
+    ) -> Vec<usize> {
+        let mut empty = Vec::new();
+        for (idx, event) in events.iter_mut().enumerate() {
+            if !filter(event) {
+                continue;
+            }
+            self.apply_to(event);
+            if let (Some(start), Some(end)) = (event.start, event.end) {
+                if end <= start {
+                    empty.push(idx);
+                }
+            }
+        }
+        empty
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(start: i64, end: i64, text: &str) -> EventLine<'static> {
+        EventLine {
+            start: Some(Timestamp::from_centis(start)),
+            end: Some(Timestamp::from_centis(end)),
+            text: text.to_owned().into(),
+            ..EventLine::default()
+        }
+    }
+
+    #[test]
+    fn shift_moves_times_and_keeps_tags() {
+        let mut line = event(100, 200, r"{\move(0,0,10,10,100,500)}text");
+        Retime::shift(Timestamp::from_centis(50)).apply_to(&mut line);
+        assert_eq!(line.start, Some(Timestamp::from_centis(150)));
+        assert_eq!(line.end, Some(Timestamp::from_centis(250)));
+        assert_eq!(line.text, r"{\move(0,0,10,10,100,500)}text");
+    }
+
+    #[test]
+    fn scale_scales_times_and_tags() {
+        let mut line = event(100, 300, r"{\fad(100,200)\t(0,1000,\fscx120)}text");
+        Retime::scale(0.5).unwrap().apply_to(&mut line);
+        assert_eq!(line.start, Some(Timestamp::from_centis(50)));
+        assert_eq!(line.end, Some(Timestamp::from_centis(150)));
+        assert_eq!(line.text, r"{\fad(50,100)\t(0,500,\fscx120)}text");
+        assert!(Retime::scale(0.0).is_none());
+        assert!(Retime::scale(f64::NAN).is_none());
+    }
+
+    #[test]
+    fn karaoke_keeps_its_total() {
+        let mut line = event(0, 100, r"{\k10}a{\k10}b{\k10}c");
+        let retime = Retime::scale(1.05).unwrap();
+        retime.apply_to(&mut line);
+        // 31.5 in total: each syllable alone would round to 11, or 33
+        assert_eq!(line.text, r"{\k11}a{\k10}b{\k11}c");
+    }
+
+    #[test]
+    fn resync_maps_both_points() {
+        let a = Timestamp::from_centis(1000);
+        let b = Timestamp::from_centis(5000);
+        let retime = Retime::resync(
+            (a, Timestamp::from_centis(1100)),
+            (b, Timestamp::from_centis(9100)),
+        )
+        .unwrap();
+        assert_eq!(retime.ratio(), 2.0);
+        assert_eq!(retime.apply(a), Timestamp::from_centis(1100));
+        assert_eq!(retime.apply(b), Timestamp::from_centis(9100));
+        assert_eq!(
+            retime.apply(Timestamp::from_centis(3000)),
+            Timestamp::from_centis(5100)
+        );
+
+        assert!(Retime::resync((a, a), (a, b)).is_none());
+        assert!(Retime::resync((a, b), (b, a)).is_none());
+    }
+
+    #[test]
+    fn times_stop_at_zero() {
+        let retime = Retime::shift(Timestamp::from_centis(-500));
+        assert_eq!(retime.apply(Timestamp::from_centis(200)), Timestamp::ZERO);
+    }
+
+    #[test]
+    fn lines_left_empty_are_reported() {
+        let mut events = vec![
+            event(100, 200, "kept"),
+            event(100, 600, "long"),
+            event(300, 400, "skipped"),
+        ];
+        let retime = Retime::shift(Timestamp::from_centis(-300));
+        let empty = retime.apply_to_events(&mut events, |e| e.text != "skipped");
+        assert_eq!(empty, [0]);
+        assert_eq!(events[1].start, Some(Timestamp::ZERO));
+        assert_eq!(events[2].start, Some(Timestamp::from_centis(300)));
+    }
+}
@@ -0,0 +1,558 @@
+use std::{
+    borrow::Cow,
+    fmt::{self, Write},
+    io,
+};
+
+use crate::{
+    models::{
+        aegisub::{
+            Extradata, ProjectGarbage, ProjectGarbageFields, EXTRADATA_TITLE, PROJECT_GARBAGE_TITLE,
+        },
+        attachment::AttachmentKind,
+        events::{EventFields, EventLine},
+        script::{RawSection, Script},
+        script_info::{ScriptInfo, ScriptInfoFields},
+        style::{Style, StyleFields},
+    },
+    tags::Num,
+    Timestamp,
+};
+
+pub const V4_PLUS_STYLE_FORMAT: [StyleFields; 23] = {
+    use StyleFields::*;
+    [
+        Name,
+        Fontname,
+        Fontsize,
+        PrimaryColor,
+        SecondaryColor,
+        OutlineColor,
+        BackColor,
+        Bold,
+        Italic,
+        Underline,
+        Strikeout,
+        ScaleX,
+        ScaleY,
+        Spacing,
+        Angle,
+        BorderStyle,
+        Outline,
+        Shadow,
+        Alignment,
+        MarginL,
+        MarginR,
+        MarginV,
+        Encoding,
+    ]
+};
+
+pub const V4_PLUS_EVENT_FORMAT: [EventFields; 10] = {
+    use EventFields::*;
+    [
+        Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text,
+    ]
+};
+
+/// The fields of a Matroska block. The times are stored in the block itself and `ReadOrder`
+/// restores the order of the lines in the script.
+pub const MATROSKA_EVENT_FORMAT: [EventFields; 9] = {
+    use EventFields::*;
+    [
+        ReadOrder, Layer, Style, Name, MarginL, MarginR, MarginV, Effect, Text,
+    ]
+};
+
+pub const V4_STYLE_FORMAT: [StyleFields; 18] = {
+    use StyleFields::*;
+    [
+        Name,
+        Fontname,
+        Fontsize,
+        PrimaryColor,
+        SecondaryColor,
+        OutlineColor,
+        BackColor,
+        Bold,
+        Italic,
+        BorderStyle,
+        Outline,
+        Shadow,
+        Alignment,
+        MarginL,
+        MarginR,
+        MarginV,
+        AlphaLevel,
+        Encoding,
+    ]
+};
+
+pub const V4_EVENT_FORMAT: [EventFields; 10] = {
+    use EventFields::*;
+    [
+        Marked, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text,
+    ]
+};
+
+/// Serialization back into ASS text.
+pub trait WriteAss {
+    fn write_ass<W: Write>(&self, out: &mut W) -> fmt::Result;
+
+    fn write_ass_io<W: io::Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut adapter = IoAdapter {
+            inner: out,
+            error: Ok(()),
+        };
+        match self.write_ass(&mut adapter) {
+            Ok(()) => Ok(()),
+            Err(_) => adapter.error.and(Err(io::Error::other("formatter error"))),
+        }
+    }
+
+    fn to_ass_string(&self) -> String {
+        let mut out = String::new();
+        self.write_ass(&mut out)
+            .expect("writing to a String cannot fail");
+        out
+    }
+}
+
+/// Lines that are written as comma-separated fields in the order given by a Format line.
+pub trait FormattedLine {
+    type Fields: Copy;
+
+    fn key(&self) -> &'static str;
+
+    fn write_field<W: Write>(&self, out: &mut W, field: Self::Fields) -> fmt::Result;
+
+    fn write_with_format<W: Write>(&self, out: &mut W, fields: &[Self::Fields]) -> fmt::Result {
+        write!(out, "{}: ", self.key())?;
+        self.write_fields(out, fields)?;
+        out.write_char('\n')
+    }
+
+    /// The values of `fields`, separated by commas, without the key or a line break.
+    fn write_fields<W: Write>(&self, out: &mut W, fields: &[Self::Fields]) -> fmt::Result {
+        for (idx, field) in fields.iter().enumerate() {
+            if idx > 0 {
+                out.write_char(',')?;
+            }
+            self.write_field(out, *field)?;
+        }
+        Ok(())
+    }
+}
+
+struct IoAdapter<'a, W: io::Write> {
+    inner: &'a mut W,
+    error: io::Result<()>,
+}
+
+impl<'a, W: io::Write> Write for IoAdapter<'a, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Err(e);
+            fmt::Error
+        })
+    }
+}
+
+fn write_bool<W: Write>(out: &mut W, value: bool) -> fmt::Result {
+    out.write_str(if value { "-1" } else { "0" })
+}
+
+fn write_format_line<W: Write, F: Copy + Into<&'static str>>(
+    out: &mut W,
+    fields: &[F],
+) -> fmt::Result {
+    write_format_names(out, fields.iter().map(|field| (*field).into()))
+}
+
+fn write_format_names<'n, W: Write>(
+    out: &mut W,
+    names: impl IntoIterator<Item = &'n str>,
+) -> fmt::Result {
+    out.write_str("Format: ")?;
+    for (idx, name) in names.into_iter().enumerate() {
+        if idx > 0 {
+            out.write_str(", ")?;
+        }
+        out.write_str(name)?;
+    }
+    out.write_char('\n')
+}
+
+/// `[Script Info]` keys in the order they are written, with their canonical spelling.
+pub const SCRIPT_INFO_FIELDS: [(ScriptInfoFields, &str); 28] = {
+    use ScriptInfoFields::*;
+    [
+        (Title, "Title"),
+        (OriginalScript, "Original Script"),
+        (OriginalTranslation, "Original Translation"),
+        (OriginalEditing, "Original Editing"),
+        (OriginalTiming, "Original Timing"),
+        (SynchPoint, "Synch Point"),
+        (ScriptUpdatedBy, "Script Updated By"),
+        (UpdateDetails, "Update Details"),
+        (ScriptType, "ScriptType"),
+        (Collisions, "Collisions"),
+        (PlayResX, "PlayResX"),
+        (PlayResY, "PlayResY"),
+        (PlayDepth, "PlayDepth"),
+        (Timer, "Timer"),
+        (WrapStyle, "WrapStyle"),
+        (ScaledBorderAndShadow, "ScaledBorderAndShadow"),
+        (YCbCrMatrix, "YCbCr Matrix"),
+        (LayoutResX, "LayoutResX"),
+        (LayoutResY, "LayoutResY"),
+        (Kerning, "Kerning"),
+        (Language, "Language"),
+        (VideoFile, "Video File"),
+        (AudioFile, "Audio File"),
+        (VideoAspectRatio, "Video Aspect Ratio"),
+        (VideoZoom, "Video Zoom"),
+        (VideoPosition, "Video Position"),
+        (LastStyleStorage, "Last Style Storage"),
+        (KeyframesFile, "Keyframes File"),
+    ]
+};
+
+impl<'a> ScriptInfo<'a> {
+    /// The value written for `field`, or `None` if the key is left out.
+    pub fn field_text(&self, field: ScriptInfoFields) -> Option<Cow<'_, str>> {
+        use ScriptInfoFields::*;
+        match field {
+            Title => (!self.title.is_empty()).then(|| self.title.as_ref().into()),
+            OriginalScript => self.authors.script.as_deref().map(Cow::from),
+            OriginalTranslation => self.authors.translation.as_deref().map(Cow::from),
+            OriginalEditing => self.authors.editing.as_deref().map(Cow::from),
+            OriginalTiming => self.authors.timing.as_deref().map(Cow::from),
+            SynchPoint => self.synch_point.as_deref().map(Cow::from),
+            ScriptUpdatedBy => self.authors.updated_by.as_deref().map(Cow::from),
+            UpdateDetails => self.authors.update_details.as_deref().map(Cow::from),
+            ScriptType => Some(self.script_type.as_deref().unwrap_or("v4.00+").into()),
+            Collisions => self.collisions.as_ref().map(|v| v.to_string().into()),
+            PlayResX => self.play_info.play_res_x.map(|v| v.to_string().into()),
+            PlayResY => self.play_info.play_res_y.map(|v| v.to_string().into()),
+            PlayDepth => self.play_info.play_depth.as_deref().map(Cow::from),
+            Timer => self.timer.map(|v| format!("{v:.4}").into()),
+            // SSA v4 has neither key
+            WrapStyle | ScaledBorderAndShadow if self.is_ssa() => None,
+            WrapStyle => self.wrap_style.map(|v| (v as u8).to_string().into()),
+            ScaledBorderAndShadow => self.scaled_border_and_shadow.map(|v| yes_no(v).into()),
+            YCbCrMatrix => self.ycbcr_matrix.as_ref().map(|v| v.to_string().into()),
+            LayoutResX => self.play_info.layout_res_x.map(|v| v.to_string().into()),
+            LayoutResY => self.play_info.layout_res_y.map(|v| v.to_string().into()),
+            Kerning => self.kerning.map(|v| yes_no(v).into()),
+            Language => self.language.as_deref().map(Cow::from),
+            VideoFile => self.video_file.as_deref().map(Cow::from),
+            AudioFile => self.audio_file.as_deref().map(Cow::from),
+            VideoAspectRatio => self.video_aspect_ratio.as_deref().map(Cow::from),
+            VideoZoom => self.video_zoom.as_deref().map(Cow::from),
+            VideoPosition => self.video_position.map(|v| v.to_string().into()),
+            LastStyleStorage => self.last_style_storage.as_deref().map(Cow::from),
+            KeyframesFile => self.keyframes_file.as_deref().map(Cow::from),
+        }
+    }
+}
+
+/// Keys in the order Aegisub writes them.
+pub const PROJECT_GARBAGE_FIELDS: [(ProjectGarbageFields, &str); 14] = {
+    use ProjectGarbageFields::*;
+    [
+        (AutomationScripts, "Automation Scripts"),
+        (ExportFilters, "Export Filters"),
+        (ExportEncoding, "Export Encoding"),
+        (LastStyleStorage, "Last Style Storage"),
+        (AudioFile, "Audio File"),
+        (VideoFile, "Video File"),
+        (TimecodesFile, "Timecodes File"),
+        (KeyframesFile, "Keyframes File"),
+        (VideoArMode, "Video AR Mode"),
+        (VideoArValue, "Video AR Value"),
+        (VideoZoomPercent, "Video Zoom Percent"),
+        (ScrollPosition, "Scroll Position"),
+        (ActiveLine, "Active Line"),
+        (VideoPosition, "Video Position"),
+    ]
+};
+
+impl<'a> ProjectGarbage<'a> {
+    /// The value written for `field`, or `None` if the key is left out.
+    pub fn field_text(&self, field: ProjectGarbageFields) -> Option<Cow<'_, str>> {
+        use ProjectGarbageFields::*;
+        match field {
+            AutomationScripts => self.automation_scripts.as_deref().map(Cow::from),
+            ExportFilters => self.export_filters.as_deref().map(Cow::from),
+            ExportEncoding => self.export_encoding.as_deref().map(Cow::from),
+            LastStyleStorage => self.last_style_storage.as_deref().map(Cow::from),
+            AudioFile => self.audio_file.as_deref().map(Cow::from),
+            VideoFile => self.video_file.as_deref().map(Cow::from),
+            TimecodesFile => self.timecodes_file.as_deref().map(Cow::from),
+            KeyframesFile => self.keyframes_file.as_deref().map(Cow::from),
+            VideoArMode => self.video_ar_mode.map(|v| v.to_string().into()),
+            VideoArValue => self.video_ar_value.map(|v| format!("{v:.6}").into()),
+            VideoZoomPercent => self.video_zoom.map(|v| format!("{v:.6}").into()),
+            ScrollPosition => self.scroll_position.map(|v| v.to_string().into()),
+            ActiveLine => self.active_line.map(|v| v.to_string().into()),
+            VideoPosition => self.video_position.map(|v| v.to_string().into()),
+        }
+    }
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+impl<'a> WriteAss for ScriptInfo<'a> {
+    fn write_ass<W: Write>(&self, out: &mut W) -> fmt::Result {
+        writeln!(out, "[Script Info]")?;
+        for (field, key) in SCRIPT_INFO_FIELDS {
+            if let Some(value) = self.field_text(field) {
+                writeln!(out, "{key}: {value}")?;
+            }
+        }
+        for (key, value) in &self.extra {
+            writeln!(out, "{key}: {value}")?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> WriteAss for ProjectGarbage<'a> {
+    fn write_ass<W: Write>(&self, out: &mut W) -> fmt::Result {
+        writeln!(out, "[{PROJECT_GARBAGE_TITLE}]")?;
+        for (field, key) in PROJECT_GARBAGE_FIELDS {
+            if let Some(value) = self.field_text(field) {
+                writeln!(out, "{key}: {value}")?;
+            }
+        }
+        for (key, value) in &self.extra {
+            writeln!(out, "{key}: {value}")?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> WriteAss for [Extradata<'a>] {
+    fn write_ass<W: Write>(&self, out: &mut W) -> fmt::Result {
+        writeln!(out, "[{EXTRADATA_TITLE}]")?;
+        for entry in self {
+            writeln!(out, "Data: {}", entry.encoded())?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> FormattedLine for Style<'a> {
+    type Fields = StyleFields;
+
+    fn key(&self) -> &'static str {
+        "Style"
+    }
+
+    fn write_field<W: Write>(&self, out: &mut W, field: StyleFields) -> fmt::Result {
+        use StyleFields::*;
+        match field {
+            Name => out.write_str(&self.name),
+            Fontname => out.write_str(&self.font_name),
+            Fontsize => write!(out, "{}", Num(self.font_size)),
+            PrimaryColor => write!(out, "{}", self.primary_color.with_alpha()),
+            SecondaryColor => write!(out, "{}", self.secondary_color.with_alpha()),
+            OutlineColor => write!(
+                out,
+                "{}",
+                self.outline_color.unwrap_or_default().with_alpha()
+            ),
+            BackColor => write!(out, "{}", self.back_color.with_alpha()),
+            Bold => write_bool(out, self.bold),
+            Italic => write_bool(out, self.italic),
+            Underline => write_bool(out, self.underline.unwrap_or(false)),
+            Strikeout => write_bool(out, self.strikeout.unwrap_or(false)),
+            ScaleX => write!(out, "{}", Num(self.scale_x.unwrap_or(100.0))),
+            ScaleY => write!(out, "{}", Num(self.scale_y.unwrap_or(100.0))),
+            Spacing => write!(out, "{}", Num(self.spacing.unwrap_or(0.0))),
+            Angle => write!(out, "{}", Num(self.angle.unwrap_or(0.0))),
+            BorderStyle => write!(out, "{}", self.border_style),
+            Outline => write!(out, "{}", Num(self.outline)),
+            Shadow => write!(out, "{}", Num(self.shadow)),
+            Alignment => write!(out, "{}", self.alignment),
+            MarginL => write!(out, "{}", self.margin_left),
+            MarginR => write!(out, "{}", self.margin_right),
+            MarginV => write!(out, "{}", self.margin_vertical),
+            Encoding => out.write_str(self.encoding.as_deref().unwrap_or("1")),
+            AlphaLevel => write!(out, "{}", self.alpha_level.unwrap_or(0)),
+            Other(_) => Ok(()),
+        }
+    }
+}
+
+impl<'a> WriteAss for Style<'a> {
+    fn write_ass<W: Write>(&self, out: &mut W) -> fmt::Result {
+        self.write_with_format(out, &V4_PLUS_STYLE_FORMAT)
+    }
+}
+
+/// A style written as an SSA v4 line, which has decimal colours without alpha.
+struct SsaStyle<'s, 'a>(&'s Style<'a>);
+
+impl<'s, 'a> FormattedLine for SsaStyle<'s, 'a> {
+    type Fields = StyleFields;
+
+    fn key(&self) -> &'static str {
+        "Style"
+    }
+
+    fn write_field<W: Write>(&self, out: &mut W, field: StyleFields) -> fmt::Result {
+        use StyleFields::*;
+        let style = self.0;
+        match field {
+            PrimaryColor => write!(out, "{}", style.primary_color.bgr()),
+            SecondaryColor => write!(out, "{}", style.secondary_color.bgr()),
+            OutlineColor => write!(out, "{}", style.outline_color.unwrap_or_default().bgr()),
+            BackColor => write!(out, "{}", style.back_color.bgr()),
+            field => style.write_field(out, field),
+        }
+    }
+}
+
+impl<'a> FormattedLine for EventLine<'a> {
+    type Fields = EventFields;
+
+    fn key(&self) -> &'static str {
+        if self.is_comment {
+            "Comment"
+        } else {
+            "Dialogue"
+        }
+    }
+
+    fn write_field<W: Write>(&self, out: &mut W, field: EventFields) -> fmt::Result {
+        use EventFields::*;
+        match field {
+            ReadOrder => write!(out, "{}", self.read_order.unwrap_or(0)),
+            Marked => out.write_str(self.marked.as_deref().unwrap_or("Marked=0")),
+            Layer => write!(out, "{}", self.layer.unwrap_or(0)),
+            // ASS has no negative times
+            Start => write!(
+                out,
+                "{}",
+                self.start.unwrap_or_default().max(Timestamp::ZERO)
+            ),
+            End => write!(out, "{}", self.end.unwrap_or_default().max(Timestamp::ZERO)),
+            Style => out.write_str(&self.style),
+            Name => out.write_str(&self.name),
+            MarginL => write!(out, "{}", self.margin_left),
+            MarginR => write!(out, "{}", self.margin_right),
+            MarginV => write!(out, "{}", self.margin_vertical),
+            Effect => out.write_str(&self.effect),
+            Text => out.write_str(&self.text),
+            Other(_) => Ok(()),
+        }
+    }
+}
+
+impl<'a> WriteAss for EventLine<'a> {
+    fn write_ass<W: Write>(&self, out: &mut W) -> fmt::Result {
+        self.write_with_format(out, &V4_PLUS_EVENT_FORMAT)
+    }
+}
+
+impl<'a> EventLine<'a> {
+    /// Writes the line as the payload of a Matroska block, in [`MATROSKA_EVENT_FORMAT`]. The
+    /// start and end are left to the block's timestamp and duration.
+    pub fn write_matroska_block<W: Write>(&self, out: &mut W) -> fmt::Result {
+        self.write_fields(out, &MATROSKA_EVENT_FORMAT)
+    }
+}
+
+impl<'a> WriteAss for RawSection<'a> {
+    fn write_ass<W: Write>(&self, out: &mut W) -> fmt::Result {
+        writeln!(out, "[{}]", self.title)?;
+        for line in &self.lines {
+            writeln!(out, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Script<'a> {
+    fn write_attachments<W: Write>(&self, out: &mut W) -> fmt::Result {
+        for kind in [AttachmentKind::Font, AttachmentKind::Graphic] {
+            let attachments = self.attachments(kind);
+            if attachments.is_empty() {
+                continue;
+            }
+
+            writeln!(out, "\n[{}]", kind.section_title())?;
+            for attachment in attachments {
+                writeln!(out, "{}: {}", kind.key(), attachment.name)?;
+                for line in attachment.encoded_lines() {
+                    writeln!(out, "{line}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> WriteAss for Script<'a> {
+    fn write_ass<W: Write>(&self, out: &mut W) -> fmt::Result {
+        self.info.write_ass(out)?;
+        if !self.project.is_empty() {
+            out.write_char('\n')?;
+            self.project.write_ass(out)?;
+        }
+
+        if self.info.is_ssa() {
+            writeln!(out, "\n[V4 Styles]")?;
+            write_format_names(
+                out,
+                V4_STYLE_FORMAT.iter().map(|field| match field {
+                    StyleFields::OutlineColor => "TertiaryColour",
+                    field => (*field).into(),
+                }),
+            )?;
+            for style in &self.styles {
+                SsaStyle(style).write_with_format(out, &V4_STYLE_FORMAT)?;
+            }
+            self.write_attachments(out)?;
+
+            writeln!(out, "\n[Events]")?;
+            write_format_line(out, &V4_EVENT_FORMAT)?;
+            for event in &self.events {
+                event.write_with_format(out, &V4_EVENT_FORMAT)?;
+            }
+        } else {
+            writeln!(out, "\n[V4+ Styles]")?;
+            write_format_line(out, &V4_PLUS_STYLE_FORMAT)?;
+            for style in &self.styles {
+                style.write_with_format(out, &V4_PLUS_STYLE_FORMAT)?;
+            }
+            self.write_attachments(out)?;
+
+            writeln!(out, "\n[Events]")?;
+            write_format_line(out, &V4_PLUS_EVENT_FORMAT)?;
+            for event in &self.events {
+                event.write_with_format(out, &V4_PLUS_EVENT_FORMAT)?;
+            }
+        }
+
+        if !self.extradata.is_empty() {
+            out.write_char('\n')?;
+            self.extradata.write_ass(out)?;
+        }
+
+        for section in &self.extra_sections {
+            out.write_char('\n')?;
+            section.write_ass(out)?;
+        }
+
+        Ok(())
+    }
+}
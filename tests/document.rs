@@ -0,0 +1,166 @@
+use ssa::{
+    cst::Document,
+    diagnostics::{Diagnostics, ParseMode},
+    models::attachment::Attachment,
+    writer::WriteAss,
+};
+
+const SCRIPT: &str = "\u{feff}[Script Info]
+; written by hand
+Title:   Round trip
+ScriptType: v4.00+
+Collisions: Normal
+PlayResX: 1920
+PlayResY: 1080
+ScaledBorderAndShadow: yes
+Custom Key: kept
+
+[Aegisub Project Garbage]
+Video File: video.mkv
+Active Line: 3
+Unknown Garbage: 1
+
+[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: Default,Arial,48.5,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,1.5,0,2,10,10,10,1
+; a comment between styles
+Style:  Sign ,Arial,40,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,-1,0,0,0,100,100,0,0,1,2,0,8,10,10,10,1
+
+[Fonts]
+fontname: x_0.ttf
+!!%#!Q1&\"A=)#1I,$!U/$R!2%B-5&298'\"E;'RQ>(B]A)3)D*#5G*SAJ+CMM,3YP-$%S-T1V.D=Y/4I\\
+0$U_0U\"\"1E.%25:(3%F+3UR.4E^156*46&676VB:7FN=86Z@9'&C9Q
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Dialogue: 0,0:00:01.00,0:00:02.50,Default,,0,0,0,,{=1}{\\fs20}Hello,  world
+Comment: 0,0:00:03.00,0:00:04.00,Sign,,0000,0000,0000,,note
+
+[Aegisub Extradata]
+Data: 1,note,eplain text
+
+[Custom Section]
+anything: goes
+";
+
+#[test]
+fn unmodified_document_writes_source_back() {
+    let crlf = SCRIPT.replace('\n', "\r\n");
+    let unterminated = SCRIPT.trim_end();
+    let mixed = SCRIPT.replacen('\n', "\r\n", 3);
+    for source in [
+        SCRIPT,
+        &crlf,
+        unterminated,
+        &mixed,
+        "",
+        "\n\n",
+        "[Script Info]",
+    ] {
+        let document = Document::parse(source);
+        assert_eq!(document.to_ass_string(), source);
+    }
+}
+
+#[test]
+fn document_reads_the_same_script_as_parse() {
+    let broken = format!("stray line\n{SCRIPT}Dialogue: 0,bad,0:00:01.00,Default,,0,0,0,,x\n");
+    for source in [SCRIPT.to_owned(), SCRIPT.replace('\n', "\r\n"), broken] {
+        let (parsed, expected) = ssa::parse_lenient(&source).unwrap();
+        let document = Document::parse(&source);
+        let mut diagnostics = Diagnostics::new(ParseMode::Lenient);
+        let script = document.script_with(&mut diagnostics).unwrap();
+        assert_eq!(format!("{script:?}"), format!("{parsed:?}"));
+        assert_eq!(diagnostics.take(), expected);
+    }
+}
+
+#[test]
+fn update_with_unchanged_script_keeps_source() {
+    for source in [SCRIPT.to_owned(), SCRIPT.replace('\n', "\r\n")] {
+        let document = Document::parse(&source);
+        let script = document.script().unwrap();
+        let mut updated = document.clone();
+        updated.update(&script);
+        assert_eq!(updated.to_ass_string(), source);
+    }
+}
+
+fn edit(source: &str, edit: impl FnOnce(&mut ssa::Script<'_>)) -> String {
+    let document = Document::parse(source);
+    let mut script = document.script().unwrap();
+    edit(&mut script);
+    let mut updated = document.clone();
+    updated.update(&script);
+    updated.to_ass_string()
+}
+
+#[test]
+fn update_rewrites_project_garbage() {
+    let updated = edit(SCRIPT, |script| {
+        script.project.active_line = Some(99);
+        script.project.scroll_position = Some(2);
+        script.project.extra.clear();
+    });
+    let expected = SCRIPT
+        .replace("Active Line: 3\n", "Active Line: 99\nScroll Position: 2\n")
+        .replace("Unknown Garbage: 1\n", "");
+    assert_eq!(updated, expected);
+}
+
+#[test]
+fn update_adds_project_garbage_section() {
+    let source = "[Script Info]\nScriptType: v4.00+\nCollisions: Normal\nScaledBorderAndShadow: no\n\n[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n";
+    let updated = edit(source, |script| script.project.active_line = Some(1));
+    assert_eq!(
+        updated,
+        source.replace(
+            "\n[Events]",
+            "\n[Aegisub Project Garbage]\nActive Line: 1\n\n[Events]"
+        )
+    );
+}
+
+#[test]
+fn update_rewrites_extradata() {
+    let updated = edit(SCRIPT, |script| {
+        script.extradata[0].value = b"changed".to_vec();
+        script.add_extradata("other", b"more".to_vec());
+    });
+    let expected = SCRIPT.replace(
+        "Data: 1,note,eplain text\n",
+        "Data: 1,note,echanged\nData: 2,other,emore\n",
+    );
+    assert_eq!(updated, expected);
+
+    let updated = edit(SCRIPT, |script| script.extradata.clear());
+    assert_eq!(updated, SCRIPT.replace("Data: 1,note,eplain text\n", ""));
+}
+
+#[test]
+fn update_rewrites_attachments() {
+    let updated = edit(SCRIPT, |script| {
+        script.fonts[0].name = "y_0.ttf".into();
+        script
+            .graphics
+            .push(Attachment::new("logo.png", vec![1, 2, 3]));
+    });
+    let expected = SCRIPT
+        .replace("fontname: x_0.ttf", "fontname: y_0.ttf")
+        .replace(
+            "\n[Events]",
+            "\n[Graphics]\nfilename: logo.png\n!1)$\n\n[Events]",
+        );
+    assert_eq!(updated, expected);
+
+    let document = Document::parse(&updated);
+    let script = document.script().unwrap();
+    assert_eq!(
+        script.graphics,
+        [Attachment::new("logo.png", vec![1, 2, 3])]
+    );
+
+    let updated = edit(SCRIPT, |script| script.fonts.clear());
+    assert!(!updated.contains("fontname"));
+    assert!(updated.contains("[Fonts]\n\n[Events]"));
+}
@@ -0,0 +1,62 @@
+use ssa::{
+    lint::{lint, LintConfig, Rule},
+    models::{attachment::Attachment, style::Style},
+    Script,
+};
+
+/// A font file with just a table directory and a `name` table holding one Windows family name.
+fn font_file(family: &str) -> Vec<u8> {
+    let name: Vec<u8> = family.encode_utf16().flat_map(u16::to_be_bytes).collect();
+    let mut data = Vec::new();
+    data.extend(0x0001_0000u32.to_be_bytes());
+    data.extend(1u16.to_be_bytes());
+    data.extend([0; 6]);
+    data.extend(b"name");
+    data.extend(0u32.to_be_bytes());
+    data.extend(28u32.to_be_bytes());
+    data.extend((18 + name.len() as u32).to_be_bytes());
+    // format, count, string offset
+    for field in [0u16, 1, 18] {
+        data.extend(field.to_be_bytes());
+    }
+    // platform, encoding, language, name id, length, offset
+    for field in [3u16, 1, 0x409, 1, name.len() as u16, 0] {
+        data.extend(field.to_be_bytes());
+    }
+    data.extend(name);
+    data
+}
+
+fn missing_fonts(script: &Script<'_>) -> Vec<String> {
+    let config = LintConfig {
+        fonts: Some(vec!["Arial".to_owned()]),
+        ..Default::default()
+    };
+    lint(script, &config)
+        .into_iter()
+        .filter(|diagnostic| diagnostic.rule == Rule::MissingFont)
+        .map(|diagnostic| diagnostic.message)
+        .collect()
+}
+
+#[test]
+fn embedded_fonts_are_available() {
+    let mut script = Script {
+        styles: ["Arial", "Fancy Serif", "@Fancy Serif", "Other"]
+            .into_iter()
+            .map(|font| Style {
+                font_name: font.into(),
+                ..Style::named_default()
+            })
+            .collect(),
+        fonts: vec![Attachment::new("fancy_0.ttf", font_file("Fancy Serif"))],
+        ..Default::default()
+    };
+    assert_eq!(missing_fonts(&script), ["font `Other` is not available"]);
+
+    // without a readable name table the file name stands in for the font name
+    script
+        .fonts
+        .push(Attachment::new("Other_B0.ttf", vec![0; 8]));
+    assert!(missing_fonts(&script).is_empty());
+}
@@ -0,0 +1,89 @@
+use ssa::{
+    diagnostics::{Diagnostics, ParseMode, Severity},
+    models::{events::EventLineParser, script_info::YCbCrMatrix},
+    writer::WriteAss,
+    LineStreamParser, ParseError, Span,
+};
+
+const EVENTS: &str = "[Script Info]
+ScriptType: v4.00+
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Extra, Text
+Dialogue: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,fx,ignored,hello, world
+";
+
+#[test]
+fn unknown_format_columns_are_skipped() {
+    let script = ssa::parse(EVENTS).unwrap();
+    assert_eq!(script.events[0].effect, "fx");
+    assert_eq!(script.events[0].text, "hello, world");
+
+    let (_, diagnostics) = ssa::parse_lenient(EVENTS).unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].severity, Severity::Warning);
+}
+
+#[test]
+fn unknown_event_lines_are_rejected() {
+    let source = format!("{EVENTS}Sound: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,,x.wav\n");
+    assert!(matches!(
+        ssa::parse(&source),
+        Err(ParseError::UnexpectedLineKind { key, .. }) if key == "Sound"
+    ));
+}
+
+#[test]
+fn matroska_block_lines_have_no_key() {
+    let mut diagnostics = Diagnostics::new(ParseMode::Strict);
+    let parser: LineStreamParser<9, EventLineParser> = LineStreamParser::new(
+        "ReadOrder, Layer, Style, Name, MarginL, MarginR, MarginV, Effect, Text",
+        Span::default(),
+        &mut diagnostics,
+    )
+    .unwrap();
+    let event = parser
+        .parse_line(
+            "",
+            "1,,Default,,0,0,0,,hi, there",
+            Span::default(),
+            &mut diagnostics,
+        )
+        .unwrap();
+    assert_eq!(event.read_order, Some(1));
+    assert!(!event.is_comment);
+    assert_eq!(event.text, "hi, there");
+}
+
+#[test]
+fn old_aegisub_script_info_keys_are_typed() {
+    let script = ssa::parse(
+        "[Script Info]\nScriptType: v4.00+\nVideo Aspect Ratio: c1.777778\nVideo Zoom: 6\n\
+         Video Position: 1234\nLast Style Storage: Default\nKeyframes File: kf.txt\n",
+    )
+    .unwrap();
+    let info = &script.info;
+    assert_eq!(info.video_aspect_ratio.as_deref(), Some("c1.777778"));
+    assert_eq!(info.video_zoom.as_deref(), Some("6"));
+    assert_eq!(info.video_position, Some(1234));
+    assert_eq!(info.last_style_storage.as_deref(), Some("Default"));
+    assert_eq!(info.keyframes_file.as_deref(), Some("kf.txt"));
+    assert!(info.extra.is_empty());
+}
+
+#[test]
+fn unknown_script_info_values_are_kept() {
+    let source = "[Script Info]\nScriptType: v4.00+\nCollisions: Sideways\nYCbCr Matrix: TV.2020\n";
+    let (_, diagnostics) = ssa::parse_lenient(source).unwrap();
+    assert_eq!(diagnostics.len(), 2);
+    assert!(diagnostics.iter().all(|d| d.severity == Severity::Warning));
+
+    let script = ssa::parse(source).unwrap();
+    assert_eq!(
+        script.info.ycbcr_matrix,
+        Some(YCbCrMatrix::Other("TV.2020".into()))
+    );
+    let text = script.to_ass_string();
+    assert!(text.contains("\nCollisions: Sideways\n"));
+    assert!(text.contains("\nYCbCr Matrix: TV.2020\n"));
+}
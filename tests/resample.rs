@@ -0,0 +1,191 @@
+use ssa::{
+    models::{script_info::YCbCrMatrix, Color},
+    resample::{convert_color, resample, AspectRatioMode, ResampleOptions},
+    Script,
+};
+
+fn resampled(
+    (width, height): (u32, u32),
+    text: &str,
+    options: ResampleOptions,
+    check: impl FnOnce(&Script<'_>, Vec<usize>),
+) {
+    let source = format!(
+        "[Script Info]\nScriptType: v4.00+\nPlayResX: {width}\nPlayResY: {height}\n\n\
+         [V4+ Styles]\nFormat: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, \
+         OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, \
+         Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\n\
+         Style: Default,Arial,20,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,\
+         1,2,2,2,10,10,10,1\n\n\
+         [Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+         Dialogue: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,{text}\n"
+    );
+    let mut script = ssa::parse(&source).unwrap();
+    let unreadable = resample(&mut script, &options);
+    check(&script, unreadable);
+}
+
+fn options(width: u32, height: u32, aspect_ratio: AspectRatioMode) -> ResampleOptions {
+    ResampleOptions {
+        width,
+        height,
+        aspect_ratio,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn same_shape_scales_everything() {
+    let text = r"{\pos(100,50)\fs20\bord1.5\clip(m 0 0 l 10 0 10 10)}x{\p1}m0 0l10 10{\p0}";
+    let options = options(1920, 1080, AspectRatioMode::Stretch);
+    resampled((640, 360), text, options, |script, unreadable| {
+        assert!(unreadable.is_empty());
+        let style = &script.styles[0];
+        assert_eq!((style.font_size, style.outline), (60.0, 6.0));
+        assert_eq!(
+            (style.margin_left, style.margin_right, style.margin_vertical),
+            (30, 30, 30)
+        );
+        assert_eq!(
+            script.events[0].text,
+            r"{\pos(300,150)\fs60\bord4.5\clip(m 0 0 l 30 0 30 30)}x{\p1}m 0 0 l 30 30{\p0}"
+        );
+        let info = &script.info.play_info;
+        assert_eq!((info.play_res_x, info.play_res_y), (Some(1920), Some(1080)));
+    });
+}
+
+#[test]
+fn stretch_squeezes_text_into_a_new_shape() {
+    let options = options(640, 480, AspectRatioMode::Stretch);
+    resampled((640, 360), r"{\pos(100,90)}x", options, |script, _| {
+        let style = &script.styles[0];
+        assert_eq!(style.scale_x, Some(75.0));
+        assert!((style.font_size - 80.0 / 3.0).abs() < 1e-9);
+        assert_eq!(script.events[0].text, r"{\pos(100,120)}x");
+    });
+}
+
+#[test]
+fn letterbox_adds_space_above_and_below() {
+    let text = r"{\pos(100,50)\clip(2,m 0 0 l 20 0)}x";
+    let options = options(640, 480, AspectRatioMode::Letterbox);
+    resampled((640, 360), text, options, |script, _| {
+        let style = &script.styles[0];
+        assert_eq!((style.font_size, style.scale_x), (20.0, Some(100.0)));
+        assert_eq!(style.margin_vertical, 70);
+        // clip coordinates at level 2 are in half pixels
+        assert_eq!(
+            script.events[0].text,
+            r"{\pos(100,110)\clip(2,m 0 120 l 20 120)}x"
+        );
+    });
+}
+
+#[test]
+fn pillarbox_adds_space_at_the_sides() {
+    let options = options(640, 360, AspectRatioMode::Pillarbox);
+    resampled((480, 360), r"{\pos(100,50)}x", options, |script, _| {
+        let style = &script.styles[0];
+        assert_eq!((style.margin_left, style.margin_right), (90, 90));
+        assert_eq!(script.events[0].text, r"{\pos(180,50)}x");
+    });
+}
+
+#[test]
+fn margins_move_and_crop_the_old_resolution() {
+    let options = ResampleOptions {
+        margins: [10.0, 10.0, -20.0, 20.0],
+        ..options(640, 360, AspectRatioMode::Stretch)
+    };
+    resampled((620, 360), r"{\pos(100,50)}x", options, |script, _| {
+        let style = &script.styles[0];
+        assert_eq!((style.margin_left, style.margin_right), (20, 20));
+        assert_eq!(style.margin_vertical, -10);
+        assert_eq!(script.events[0].text, r"{\pos(110,30)}x");
+    });
+}
+
+#[test]
+fn unreadable_drawings_are_reported() {
+    let text = r"{\pos(100,50)\clip(m 0 0 l 10)}x{\p1}m 0 0 l 10{\p0}";
+    let options = options(1280, 720, AspectRatioMode::Stretch);
+    resampled((640, 360), text, options, |script, unreadable| {
+        assert_eq!(unreadable, [0]);
+        assert_eq!(
+            script.events[0].text,
+            r"{\pos(200,100)\clip(m 0 0 l 10)}x{\p1}m 0 0 l 10{\p0}"
+        );
+    });
+}
+
+#[test]
+fn colors_move_between_601_and_709() {
+    use YCbCrMatrix::*;
+
+    let cases = [
+        (Color::rgb(255, 0, 0), Color::rgb(255, 25, 0)),
+        (Color::rgb(0, 255, 0), Color::rgb(0, 215, 0)),
+        (Color::rgb(0, 0, 255), Color::rgb(0, 15, 255)),
+        (Color::rgb(200, 100, 50), Color::rgb(209, 107, 46)),
+    ];
+    for (color, expected) in cases {
+        assert_eq!(convert_color(color, &Tv601, &Tv709), expected);
+    }
+    assert_eq!(
+        convert_color(Color::rgb(255, 0, 0), &Tv709, &Tv601),
+        Color::rgb(233, 0, 2)
+    );
+    // greys have no chroma, so every matrix agrees on them
+    for grey in [0, 128, 255] {
+        let color = Color::rgb(grey, grey, grey);
+        assert_eq!(convert_color(color, &Tv601, &Tv709), color);
+        assert_eq!(convert_color(color, &Pc709, &Pc601), color);
+    }
+}
+
+#[test]
+fn colors_move_between_tv_and_pc_range() {
+    use YCbCrMatrix::*;
+
+    // TV range puts black at 16 and white at 235
+    assert_eq!(
+        convert_color(Color::rgb(255, 255, 255), &Tv601, &Pc601),
+        Color::rgb(235, 235, 235)
+    );
+    assert_eq!(
+        convert_color(Color::rgb(0, 0, 0), &Tv709, &Pc709),
+        Color::rgb(16, 16, 16)
+    );
+    // and the other way the ends are clipped
+    assert_eq!(
+        convert_color(Color::rgb(255, 255, 255), &Pc601, &Tv601),
+        Color::rgb(255, 255, 255)
+    );
+    assert_eq!(
+        convert_color(Color::rgb(0, 0, 0), &Pc601, &Tv601),
+        Color::rgb(0, 0, 0)
+    );
+}
+
+#[test]
+fn color_conversion_keeps_alpha() {
+    let color = Color {
+        alpha: Some(0x80),
+        ..Color::rgb(255, 0, 0)
+    };
+    let converted = convert_color(color, &YCbCrMatrix::Tv601, &YCbCrMatrix::Tv709);
+    assert_eq!(converted.alpha, Some(0x80));
+    assert_eq!(converted.green, 25);
+}
+
+#[test]
+fn unknown_matrices_leave_colors_alone() {
+    let color = Color::rgb(200, 100, 50);
+    let other = YCbCrMatrix::Other("TV.2020".to_owned());
+    assert_eq!(convert_color(color, &other, &YCbCrMatrix::Tv709), color);
+    assert_eq!(
+        convert_color(color, &YCbCrMatrix::Tv601, &YCbCrMatrix::None),
+        color
+    );
+}
@@ -0,0 +1,72 @@
+use ssa::{models::events::EventLine, writer::WriteAss, Script, Timestamp};
+
+#[test]
+fn unset_script_info_keys_are_not_written() {
+    let script = ssa::parse("[Script Info]\nScriptType: v4.00+\nPlayResX: 640\n").unwrap();
+    let text = script.to_ass_string();
+    assert!(text.starts_with("[Script Info]\nScriptType: v4.00+\nPlayResX: 640\n\n"));
+    assert!(!text.contains("Collisions"));
+    assert!(!text.contains("ScaledBorderAndShadow"));
+}
+
+#[test]
+fn v4_plus_events_always_have_the_standard_format() {
+    let script = Script {
+        events: vec![EventLine {
+            marked: Some("Marked=0".into()),
+            read_order: Some(3),
+            text: "no times".into(),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let text = script.to_ass_string();
+    assert!(text.ends_with(
+        "\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+         Dialogue: 0,0:00:00.00,0:00:00.00,,,0,0,0,,no times\n"
+    ));
+}
+
+#[test]
+fn negative_times_are_written_as_zero() {
+    let event = EventLine {
+        start: Some(Timestamp::from_centis(-150)),
+        end: Some(Timestamp::from_centis(250)),
+        text: "early".into(),
+        ..Default::default()
+    };
+    assert_eq!(
+        event.to_ass_string(),
+        "Dialogue: 0,0:00:00.00,0:00:02.50,,,0,0,0,,early\n"
+    );
+}
+
+#[test]
+fn matroska_blocks_lead_with_read_order() {
+    let event = EventLine {
+        read_order: Some(7),
+        layer: Some(1),
+        start: Some(Timestamp::from_centis(100)),
+        style: "Default".into(),
+        text: "hi, there".into(),
+        ..Default::default()
+    };
+    let mut block = String::new();
+    event.write_matroska_block(&mut block).unwrap();
+    assert_eq!(block, "7,1,Default,,0,0,0,,hi, there");
+}
+
+#[test]
+fn fractional_style_values_round_trip() {
+    let source = "[V4+ Styles]\nFormat: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, \
+                  OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, \
+                  Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, \
+                  MarginV, Encoding\nStyle: Default,Arial,20.5,&H00FFFFFF,&H000000FF,&H00000000,\
+                  &H00000000,0,0,0,0,100,100,0.5,0,1,1.5,0.25,2,10,10,10,1\n";
+    let script = ssa::parse(source).unwrap();
+    let style = &script.styles[0];
+    assert_eq!(style.spacing, Some(0.5));
+    assert!(script
+        .to_ass_string()
+        .contains("Style: Default,Arial,20.5,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0.5,0,1,1.5,0.25,2,10,10,10,1\n"));
+}